@@ -0,0 +1,44 @@
+/* C interface to the scrimshady_core DLL. See src/ffi.rs for details. */
+#ifndef SCRIMSHADY_H
+#define SCRIMSHADY_H
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <windows.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ScrimshadyRenderer ScrimshadyRenderer;
+
+/* Every call returns E_UNEXPECTED, or does nothing for scrimshady_destroy and 0 for
+ * scrimshady_shader_count, if it panics inside the library. The panic is appended to
+ * scrimshady_crash.log in the working directory. */
+
+/* Create a renderer drawing into hwnd, which is excluded from screen capture. */
+HRESULT scrimshady_create(HWND hwnd, ScrimshadyRenderer **renderer);
+/* Create a renderer capturing source, in physical desktop pixels, and publishing each frame
+ * as the DXGI shared texture named name, to open with
+ * ID3D11Device1::OpenSharedResourceByName. Nothing is shown on screen. Only exported when
+ * the library is built with the shared-output feature. */
+HRESULT scrimshady_create_shared(const wchar_t *name, RECT source,
+                                 ScrimshadyRenderer **renderer);
+void scrimshady_destroy(ScrimshadyRenderer *renderer);
+
+uint32_t scrimshady_shader_count(const ScrimshadyRenderer *renderer);
+HRESULT scrimshady_set_shader(ScrimshadyRenderer *renderer, uint32_t index);
+
+/* Call from the host window's WM_MOVE (resized = false) and WM_SIZE (resized = true). */
+HRESULT scrimshady_window_changed(ScrimshadyRenderer *renderer, bool resized);
+
+/* Capture and present one frame, typically from WM_PAINT or the host's render loop.
+ * Returns DXGI_STATUS_OCCLUDED without rendering while the window is covered; keep
+ * calling, ideally less often, and rendering resumes once it's visible again. */
+HRESULT scrimshady_render_frame(ScrimshadyRenderer *renderer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SCRIMSHADY_H */
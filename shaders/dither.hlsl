@@ -0,0 +1,35 @@
+//! param0 = 2 "scale" 1 16 1
+Texture2D screenTexture : register(t0);
+SamplerState texSampler : register(s0);
+cbuffer FrameBuffer : register(b0) {
+    float Time;
+    float2 Resolution;  // Render target size in pixels
+    uint Pass;
+    float4 Params;      // x = dither scale (pixels per matrix cell)
+}
+
+// 4x4 Bayer matrix, thresholds in [0, 16)
+static const float bayer[16] = {
+     0.0,  8.0,  2.0, 10.0,
+    12.0,  4.0, 14.0,  6.0,
+     3.0, 11.0,  1.0,  9.0,
+    15.0,  7.0, 13.0,  5.0
+};
+
+float4 main(float4 pos : SV_POSITION, float2 texCoord : TEXCOORD) : SV_Target {
+    float scale = max(Params.x, 1.0);
+
+    // Snap to the dither cell so every pixel in a cell shares one source sample
+    float2 cellCoord = floor(texCoord * Resolution / scale);
+    float2 sampleCoord = (cellCoord + 0.5) * scale / Resolution;
+    float3 color = screenTexture.Sample(texSampler, sampleCoord).rgb;
+    float brightness = dot(color, float3(0.299, 0.587, 0.114));
+
+    uint2 matrixPos = uint2(cellCoord) % 4;
+    float threshold = (bayer[matrixPos.y * 4 + matrixPos.x] + 0.5) / 16.0;
+
+    // Ink on paper rather than pure black and white
+    float3 ink = float3(0.08, 0.07, 0.06);
+    float3 paper = float3(0.96, 0.94, 0.88);
+    return float4(brightness > threshold ? paper : ink, 1.0);
+}
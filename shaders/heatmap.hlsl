@@ -0,0 +1,33 @@
+Texture2D screenTexture : register(t0);
+SamplerState texSampler : register(s0);
+
+cbuffer HistoryConstants : register(b0) {
+    float Time;
+    float Decay;       // How much accumulated heat survives each frame
+    uint2 Resolution;  // Render target size, matches the history buffer layout
+}
+
+// Persistent across frames: rgb = last frame's color, a = accumulated motion heat
+RWStructuredBuffer<float4> History : register(u1);
+
+float4 main(float4 pos : SV_POSITION, float2 texCoord : TEXCOORD) : SV_Target {
+    float4 color = screenTexture.Sample(texSampler, texCoord);
+
+    uint2 pixel = min(uint2(pos.xy), Resolution - 1);
+    uint index = pixel.y * Resolution.x + pixel.x;
+
+    // Compare against what this pixel looked like last frame
+    float4 previous = History[index];
+    float delta = dot(abs(color.rgb - previous.rgb), float3(0.299, 0.587, 0.114));
+    float heat = saturate(max(previous.a * Decay, delta * 4.0));
+    History[index] = float4(color.rgb, heat);
+
+    // Cold-to-hot ramp (blue -> red -> yellow) over a dimmed copy of the screen
+    float3 ramp = float3(
+        saturate(heat * 2.0),
+        saturate(heat * 2.0 - 1.0),
+        saturate(1.0 - heat * 2.0)
+    );
+    float3 dimmed = color.rgb * 0.35;
+    return float4(lerp(dimmed, ramp, heat), color.a);
+}
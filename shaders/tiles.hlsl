@@ -1,6 +1,6 @@
 // Texture inputs
 Texture2D SourceImage : register(t0);
-Texture2D TileSpritesheet : register(t1);
+Texture2DArray TileSpritesheet : register(t1); // A slice per animation frame
 SamplerState samplerState : register(s0);
 
 // Constants
@@ -11,79 +11,43 @@ cbuffer Constants : register(b0)
     uint TilesPerRow;              // e.g. 16 - columns in your spritesheet
     uint TotalTiles;               // e.g. 95 - total number of tiles
     float2 SpritesheetResolution; // Total spritesheet size
+    float GlyphPadding;            // e.g. 1 - gap between cells in pixels
+    float CurveGamma;              // e.g. 1 - applied to source brightness first
+    float4 CurvePoints;            // Curve output at inputs 0, 1/3, 2/3 and 1
+    float4 LumaWeights;            // e.g. (0.299, 0.587, 0.114) - rgb brightness weights
+    uint2 GridSize;                // Tiles across and down the source
+    uint2 GridOffset;              // Only used by the update pass
+    float OverlayOpacity;          // e.g. 0.5 - glyphs over the source, 1 for glyphs only
+    uint FrameCount;               // e.g. 1 - spritesheet slices the glyphs animate through
+    float FrameRate;               // e.g. 8 - slices a second
+    float Time;                    // Seconds, as shaders see it
 };
 
-// Precomputed tile brightnesses (compute once on CPU, pass as buffer)
-StructuredBuffer<float> TileBrightness : register(t2);
-
-float GetAverageBrightness(Texture2D tex, float2 topLeft, float2 size, float2 texResolution)
-{
-    float brightness = 0.0;
-    int samples = 16; // Sample a grid within the tile
-    
-    for (int y = 0; y < samples; y++)
-    {
-        for (int x = 0; x < samples; x++)
-        {
-            float2 offset = float2(x, y) / float(samples - 1);
-            float2 uv = (topLeft + offset * size) / texResolution;
-            float3 color = tex.Sample(samplerState, uv).rgb;
-            
-            // Simple luminance calculation
-            brightness += dot(color, float3(0.299, 0.587, 0.114));
-        }
-    }
-    
-    return brightness / (samples * samples);
-}
-
-uint FindBestTile(float targetBrightness)
-{
-    uint bestTile = 0;
-    float bestDiff = 1000.0;
-    
-    for (uint i = 0; i < TotalTiles; i++)
-    {
-        float diff = abs(TileBrightness[i] - targetBrightness);
-        if (diff < bestDiff)
-        {
-            bestDiff = diff;
-            bestTile = i;
-        }
-    }
-    
-    return bestTile;
-}
+// Spritesheet cell chosen for each tile, row-major, kept up to date by tiles_update.hlsl
+StructuredBuffer<uint> TileCells : register(t2);
 
 float4 main(float4 pos : SV_POSITION, float2 texCoord : TEXCOORD) : SV_Target
 {
     // Determine which tile this pixel belongs to
     float2 pixelPos = texCoord * SourceResolution;
-    int2 tileIndex = int2(pixelPos / TileSize);
-
-    // Calculate the region in the source image for this tile
-    float2 sourceTileTopLeft = float2(tileIndex) * TileSize;
-
-    // Get average brightness of this tile region in source
-    float sourceBrightness = GetAverageBrightness(
-        SourceImage,
-        sourceTileTopLeft,
-        TileSize,
-        SourceResolution
-    );
-
-    // Find best matching tile from spritesheet
-    uint bestTile = (uint)FindBestTile(sourceBrightness);
+    uint2 tileIndex = min(uint2(pixelPos / TileSize), GridSize - 1);
 
     // Calculate position within the current tile (0-1 range)
     float2 posInTile = frac(pixelPos / TileSize);
 
     // Calculate UV coordinates for the matched tile in spritesheet
-    uint tileCol = bestTile % TilesPerRow;
-    uint tileRow = bestTile / TilesPerRow;
-    float2 spriteTileTopLeft = float2(tileCol, tileRow) * TileSize;
+    uint cell = TileCells[tileIndex.y * GridSize.x + tileIndex.x];
+    uint tileCol = cell % TilesPerRow;
+    uint tileRow = cell / TilesPerRow;
+    float2 spriteTileTopLeft = float2(tileCol, tileRow) * (TileSize + GlyphPadding);
     float2 spriteUV = (spriteTileTopLeft + posInTile * TileSize) / SpritesheetResolution;
 
-    // Sample from the matched tile
-    return TileSpritesheet.Sample(samplerState, spriteUV);
+    // Step each tile through the frames out of phase with its neighbours, so the glyphs
+    // shimmer rather than flip all at once
+    uint frame = (uint(Time * FrameRate) + tileIndex.x * 7 + tileIndex.y * 13) % max(FrameCount, 1);
+
+    // Sample from the matched tile, over the original image unless fully opaque
+    float4 glyph = TileSpritesheet.Sample(samplerState, float3(spriteUV, frame));
+    float4 source = SourceImage.Sample(samplerState, texCoord);
+    return lerp(source, glyph, OverlayOpacity);
 }
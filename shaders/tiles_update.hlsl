@@ -0,0 +1,102 @@
+// Picks the glyph for each tile of the tiles effect, one thread per tile. Dispatched over the
+// tiles whose part of the source changed, so the rest keep the glyph they already have.
+Texture2D SourceImage : register(t0);
+SamplerState samplerState : register(s0);
+
+// Same layout as the tiles pixel shader's constants
+cbuffer Constants : register(b0)
+{
+    float2 SourceResolution;      // e.g. (1920, 1080)
+    float2 TileSize;              // e.g. (8, 16) - size of each tile in pixels
+    uint TilesPerRow;              // e.g. 16 - columns in your spritesheet
+    uint TotalTiles;               // e.g. 95 - total number of tiles
+    float2 SpritesheetResolution; // Total spritesheet size
+    float GlyphPadding;            // e.g. 1 - gap between cells in pixels
+    float CurveGamma;              // e.g. 1 - applied to source brightness first
+    float4 CurvePoints;            // Curve output at inputs 0, 1/3, 2/3 and 1
+    float4 LumaWeights;            // e.g. (0.299, 0.587, 0.114) - rgb brightness weights
+    uint2 GridSize;                // Tiles across and down the source
+    uint2 GridOffset;              // First tile this dispatch updates
+    float OverlayOpacity;          // Only used when drawing
+    uint FrameCount;               // Only used when drawing
+    float FrameRate;               // Only used when drawing
+    float Time;                    // Only used when drawing
+};
+
+// Precomputed brightness of each usable glyph and the spritesheet cell it's in
+struct TileGlyph
+{
+    float Brightness;
+    uint Cell;
+};
+StructuredBuffer<TileGlyph> TileGlyphs : register(t1);
+
+// Spritesheet cell chosen for each tile, row-major
+RWStructuredBuffer<uint> TileCells : register(u0);
+
+float GetAverageBrightness(Texture2D tex, float2 topLeft, float2 size, float2 texResolution)
+{
+    float brightness = 0.0;
+    int samples = 16; // Sample a grid within the tile
+    
+    for (int y = 0; y < samples; y++)
+    {
+        for (int x = 0; x < samples; x++)
+        {
+            float2 offset = float2(x, y) / float(samples - 1);
+            float2 uv = (topLeft + offset * size) / texResolution;
+            float3 color = tex.SampleLevel(samplerState, uv, 0).rgb;
+            
+            // Same weighting the glyph brightnesses were computed with
+            brightness += dot(color, LumaWeights.rgb);
+        }
+    }
+    
+    return brightness / (samples * samples);
+}
+
+float RemapBrightness(float brightness)
+{
+    float x = pow(saturate(brightness), CurveGamma) * 3.0;
+    uint segment = min((uint)x, 2);
+    float2 ends = segment == 0 ? CurvePoints.xy : (segment == 1 ? CurvePoints.yz : CurvePoints.zw);
+    return lerp(ends.x, ends.y, x - segment);
+}
+
+uint FindBestTile(float targetBrightness)
+{
+    uint bestTile = 0;
+    float bestDiff = 1000.0;
+    
+    for (uint i = 0; i < TotalTiles; i++)
+    {
+        float diff = abs(TileGlyphs[i].Brightness - targetBrightness);
+        if (diff < bestDiff)
+        {
+            bestDiff = diff;
+            bestTile = i;
+        }
+    }
+    
+    return bestTile;
+}
+
+[numthreads(8, 8, 1)]
+void main(uint3 dispatchThreadID : SV_DispatchThreadID)
+{
+    uint2 tileIndex = dispatchThreadID.xy + GridOffset;
+    if (tileIndex.x >= GridSize.x || tileIndex.y >= GridSize.y)
+        return;
+
+    // Get average brightness of this tile region in source
+    float sourceBrightness = GetAverageBrightness(
+        SourceImage,
+        float2(tileIndex) * TileSize,
+        TileSize,
+        SourceResolution
+    );
+
+    // Find best matching tile from spritesheet
+    uint bestTile = FindBestTile(RemapBrightness(sourceBrightness));
+    TileCells[tileIndex.y * GridSize.x + tileIndex.x] = TileGlyphs[bestTile].Cell;
+}
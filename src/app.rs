@@ -0,0 +1,8510 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::PathBuf,
+    rc::{Rc, Weak},
+};
+
+use crate::config::{
+    BrightnessCurve, CLEAR_COLORS, Config, EdgeMode, GoldenMode, ListMode, LumaWeights,
+    SaveBackground, ScreensaverMode, SsaaFilter, SwapEffect, TestSource, TimeMode,
+};
+use crate::crash_log;
+use crate::ipc::{self, WM_IPC_COMMAND};
+use crate::region_select::{self, WM_REGION_SELECTED};
+use crate::saved_state;
+#[cfg(feature = "shared-output")]
+use crate::shared_output;
+use serde::Deserialize;
+use windows::{
+    Win32::{
+        Foundation::*,
+        Graphics::{
+            Direct3D::Fxc::*, Direct3D::*, Direct3D11::*, DirectComposition::*, Dxgi::Common::*,
+            Dxgi::*, Gdi::*, Imaging::*,
+        },
+        System::Com::*,
+        System::Diagnostics::Debug::MessageBeep,
+        System::LibraryLoader::*,
+        System::Performance::*,
+        System::SystemInformation::*,
+        UI::HiDpi::*,
+        UI::Input::KeyboardAndMouse::*,
+        UI::Shell::*,
+        UI::WindowsAndMessaging::*,
+    },
+    core::*,
+};
+
+#[derive(Clone)]
+enum ShaderType {
+    Simple(ID3D11PixelShader),
+    Tiles {
+        shader: ID3D11PixelShader,
+        // Compute pass choosing the glyph of each tile, run only where the source changed
+        update_shader: ID3D11ComputeShader,
+        spritesheet_srv: ID3D11ShaderResourceView,
+        brightness_srv: ID3D11ShaderResourceView,
+        constants_buffer: ID3D11Buffer,
+        sheet_width: u32,
+        sheet_height: u32,
+        layout: GlyphLayout,
+        // Remaps source brightness before picking a glyph, cycled with a hotkey
+        curves: Vec<BrightnessCurve>,
+        current_curve: usize,
+        // Weighting used for both the glyph and source brightness, so they're comparable
+        luma: LumaWeights,
+        // Fixed number of glyph columns across the window, `None` for one glyph per tile of
+        // source pixels
+        columns: Option<u32>,
+        // How much the glyphs cover the source, 1 replacing it entirely, cycled with a hotkey
+        opacity: f32,
+        // Slices of the spritesheet array the glyphs animate through, and how many a second
+        frame_count: u32,
+        frame_rate: f32,
+        // Glyph chosen for each tile, created on first use at the grid's size
+        cells: Option<Box<TileCells>>,
+    },
+    History {
+        shader: ID3D11PixelShader,
+        constants_buffer: ID3D11Buffer,
+        decay: f32,
+        history: Option<HistoryBuffer>,
+    },
+}
+
+/// Arrangement of glyph cells in the tiles spritesheet
+#[derive(Clone)]
+struct GlyphLayout {
+    tile_width: u32,
+    tile_height: u32,
+    // Gap between neighbouring cells, in pixels
+    padding: u32,
+    cells_per_row: u32,
+    // Usable cells, counted row-major from the top left
+    cells: Vec<u32>,
+}
+
+impl GlyphLayout {
+    /// The layout of a sheet of `tile_width` x `tile_height` cells `padding` pixels apart,
+    /// using the listed `cells`, or otherwise `glyph_count` cells from `first_glyph` on
+    fn new(
+        (sheet_width, sheet_height): (u32, u32),
+        (tile_width, tile_height): (u32, u32),
+        padding: u32,
+        first_glyph: u32,
+        glyph_count: Option<u32>,
+        cells: Option<&[u32]>,
+    ) -> Result<Self> {
+        // The last cell in a row/column doesn't need trailing padding
+        let cells_per_row = (sheet_width + padding) / (tile_width + padding);
+        let cells_per_col = (sheet_height + padding) / (tile_height + padding);
+        let total_cells = cells_per_row * cells_per_col;
+
+        let cells = match cells {
+            Some(cells) => {
+                if let Some(cell) = cells.iter().find(|&&cell| cell >= total_cells) {
+                    return Err(Error::new(
+                        E_INVALIDARG,
+                        format!(
+                            "Glyph {} doesn't fit in a {}x{} sheet of {} cells",
+                            cell, cells_per_row, cells_per_col, total_cells
+                        ),
+                    ));
+                }
+                cells.to_vec()
+            }
+            None => {
+                let glyph_count = glyph_count.unwrap_or(total_cells.saturating_sub(first_glyph));
+                if first_glyph + glyph_count > total_cells {
+                    return Err(Error::new(
+                        E_INVALIDARG,
+                        format!(
+                            "Glyphs {}..{} don't fit in a {}x{} sheet of {} cells",
+                            first_glyph,
+                            first_glyph + glyph_count,
+                            cells_per_row,
+                            cells_per_col,
+                            total_cells
+                        ),
+                    ));
+                }
+                (first_glyph..first_glyph + glyph_count).collect()
+            }
+        };
+        if cells.is_empty() {
+            return Err(Error::new(E_INVALIDARG, "No glyphs to use in the sheet"));
+        }
+
+        Ok(Self {
+            tile_width,
+            tile_height,
+            padding,
+            cells_per_row,
+            cells,
+        })
+    }
+
+    /// Top left pixel of a glyph, indexed into the usable cells
+    fn glyph_origin(&self, glyph: usize) -> (u32, u32) {
+        self.cell_origin(self.cells[glyph])
+    }
+
+    /// Top left pixel of a cell, counted row-major from the top left of the sheet
+    fn cell_origin(&self, cell: u32) -> (u32, u32) {
+        (
+            (cell % self.cells_per_row) * (self.tile_width + self.padding),
+            (cell / self.cells_per_row) * (self.tile_height + self.padding),
+        )
+    }
+}
+
+/// Per-pixel state that survives across frames, read and written by the shader through a UAV
+#[derive(Clone)]
+struct HistoryBuffer {
+    _buffer: ID3D11Buffer,
+    uav: ID3D11UnorderedAccessView,
+}
+
+/// The glyph of each tile of the tiles effect, and what it was last worked out from so
+/// only the tiles whose source changed are updated
+#[derive(Clone)]
+struct TileCells {
+    buffer: ID3D11Buffer,
+    srv: ID3D11ShaderResourceView,
+    uav: ID3D11UnorderedAccessView,
+    constants: TilesConstants,
+    source: ID3D11ShaderResourceView,
+    // The `frame_serial` of the frame last drawn
+    serial: u64,
+}
+
+/// DirectComposition tree showing a swap chain, with its alpha, as a window's content
+struct Composition {
+    _device: IDCompositionDevice,
+    _target: IDCompositionTarget,
+    _visual: IDCompositionVisual,
+}
+
+/// Offscreen render target for intermediate passes of a multi-pass shader
+struct PassTarget {
+    texture: ID3D11Texture2D,
+    rtv: ID3D11RenderTargetView,
+    srv: ID3D11ShaderResourceView,
+}
+
+/// Multisampled render target the shader pass draws into, resolved before it's shown
+struct MsaaTarget {
+    texture: ID3D11Texture2D,
+    rtv: ID3D11RenderTargetView,
+    // Single sample sRGB texture to resolve into when shading in linear light, so samples
+    // are averaged as linear values before being copied into the plain swap chain buffer
+    srgb_resolve: Option<ID3D11Texture2D>,
+}
+
+/// How long AcquireNextFrame waits for a new frame, trading latency against CPU spent polling
+#[derive(Clone, Copy, PartialEq)]
+enum LatencyMode {
+    LowLatency,
+    Balanced,
+    PowerSaver,
+}
+
+impl LatencyMode {
+    fn timeout_millis(self) -> u32 {
+        match self {
+            LatencyMode::LowLatency => 0,
+            LatencyMode::Balanced => 8,
+            LatencyMode::PowerSaver => 33,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            LatencyMode::LowLatency => LatencyMode::Balanced,
+            LatencyMode::Balanced => LatencyMode::PowerSaver,
+            LatencyMode::PowerSaver => LatencyMode::LowLatency,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LatencyMode::LowLatency => "low latency",
+            LatencyMode::Balanced => "balanced",
+            LatencyMode::PowerSaver => "power saver",
+        }
+    }
+}
+
+/// Backoff between attempts to duplicate an output that another app is holding
+struct DuplicationRetry {
+    next_attempt: std::time::Instant,
+    delay: std::time::Duration,
+}
+
+// Seconds of shader time per captured frame in the fixed time mode
+const FIXED_TIME_STEP: f32 = 1.0 / 60.0;
+// How much of each new frame gap the smooth time mode takes in, and how far it may get from
+// the wall clock before snapping back to it
+const TIME_SMOOTHING: f32 = 0.1;
+const MAX_SMOOTH_TIME_DRIFT: f32 = 0.25;
+
+// Captured frames the latency overlay averages over
+const LATENCY_SAMPLES: usize = 60;
+
+const DUPLICATION_RETRY_INITIAL: std::time::Duration = std::time::Duration::from_millis(250);
+const DUPLICATION_RETRY_MAX: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Desktop capture through GDI, for when duplication isn't supported at all (some remote
+/// desktop sessions, or blocked by group policy). Every frame goes through system memory, so
+/// it's much slower than duplication.
+struct GdiCapture {
+    memory_dc: HDC,
+    bitmap: HBITMAP,
+    // Top-down BGRA pixels of the DIB section, owned by `bitmap`
+    bits: *mut u8,
+    // Desktop area the bitmap and texture cover
+    bounds: RECT,
+    // Output-sized like a duplicated frame, so it goes through the same pipeline
+    texture: ID3D11Texture2D,
+}
+
+impl GdiCapture {
+    fn new(device: &ID3D11Device, bounds: RECT) -> Result<Self> {
+        let width = bounds.right - bounds.left;
+        let height = bounds.bottom - bounds.top;
+        let info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width,
+                // Negative for rows from the top, matching the texture
+                biHeight: -height,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let texture = create_test_texture(
+            device,
+            width as u32,
+            height as u32,
+            &vec![0u8; (width * height * 4) as usize],
+        )?;
+
+        unsafe {
+            let memory_dc = CreateCompatibleDC(None);
+            if memory_dc.is_invalid() {
+                return Err(Error::from_thread());
+            }
+            let mut bits = std::ptr::null_mut();
+            let bitmap = match CreateDIBSection(
+                Some(memory_dc),
+                &info,
+                DIB_RGB_COLORS,
+                &mut bits,
+                None,
+                0,
+            ) {
+                Ok(bitmap) => bitmap,
+                Err(e) => {
+                    let _ = DeleteDC(memory_dc);
+                    return Err(e);
+                }
+            };
+            Ok(Self {
+                memory_dc,
+                bitmap,
+                bits: bits as *mut u8,
+                bounds,
+                texture,
+            })
+        }
+    }
+
+    /// Copy `region` of the desktop, which must lie within `bounds`, into the texture
+    fn capture(&self, context: &ID3D11DeviceContext, region: RECT) -> Result<()> {
+        let pitch = (self.bounds.right - self.bounds.left) as usize * 4;
+        let left = (region.left - self.bounds.left) as usize;
+        let top = (region.top - self.bounds.top) as usize;
+        let width = (region.right - region.left) as usize;
+        let height = (region.bottom - region.top) as usize;
+
+        unsafe {
+            let screen_dc = GetDC(None);
+            let previous = SelectObject(self.memory_dc, self.bitmap.into());
+            // Layered windows are only included with CAPTUREBLT. Windows excluded from capture,
+            // like this app's own, are left out as they are from duplication.
+            let copied = BitBlt(
+                self.memory_dc,
+                left as i32,
+                top as i32,
+                width as i32,
+                height as i32,
+                Some(screen_dc),
+                region.left,
+                region.top,
+                SRCCOPY | CAPTUREBLT,
+            );
+            SelectObject(self.memory_dc, previous);
+            ReleaseDC(None, screen_dc);
+            copied?;
+            let _ = GdiFlush();
+
+            // GDI leaves alpha at zero, where duplicated frames are opaque
+            for y in top..top + height {
+                let row =
+                    std::slice::from_raw_parts_mut(self.bits.add(y * pitch + left * 4), width * 4);
+                for pixel in row.chunks_exact_mut(4) {
+                    pixel[3] = 255;
+                }
+            }
+
+            context.UpdateSubresource(
+                &self.texture,
+                0,
+                Some(&D3D11_BOX {
+                    left: left as u32,
+                    top: top as u32,
+                    front: 0,
+                    right: (left + width) as u32,
+                    bottom: (top + height) as u32,
+                    back: 1,
+                }),
+                self.bits.add(top * pitch + left * 4) as *const _,
+                pitch as u32,
+                0,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Drop for GdiCapture {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DeleteObject(self.bitmap.into());
+            let _ = DeleteDC(self.memory_dc);
+        }
+    }
+}
+
+/// Shader input from the most recent frame that arrived, kept so it can be shown again
+/// while capture is unavailable
+#[derive(Clone)]
+struct CapturedFrame {
+    srv: ID3D11ShaderResourceView,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Clone)]
+struct PixelShaderConfig {
+    name: String,
+    shader_type: ShaderType,
+    user_texture: Option<PathBuf>,
+    sampler_mode: SamplerMode,
+    params: Vec<ShaderParam>,
+    // Times a simple shader runs per frame, each pass sampling the previous one's output
+    passes: u32,
+    // Frame rate cap while this shader is showing, `None` to render every new frame
+    max_fps: Option<f32>,
+    // Floats from the shader's sidecar data file, bound at t4
+    data_srv: Option<ID3D11ShaderResourceView>,
+    // Where the shader declared the engine's inputs
+    bindings: ShaderBindings,
+    // Replaces the default passthrough vertex shader, from the sidecar
+    vertex_stage: Option<VertexStage>,
+    // Compiled pixel shader, kept for dumping to a .cso file
+    bytecode: Rc<[u8]>,
+}
+
+/// A vertex shader and the layout of the fullscreen quad's vertices as it reads them
+#[derive(Clone)]
+struct VertexStage {
+    shader: ID3D11VertexShader,
+    input_layout: ID3D11InputLayout,
+}
+
+impl VertexStage {
+    /// Compile `source`'s `main` and match it to the quad's `POSITION` and `TEXCOORD`, of
+    /// which it may read either or both
+    fn compile(device: &ID3D11Device, source: &[u8], name: &str) -> Result<Self> {
+        unsafe {
+            let (shader_blob, error_blob, res) = d3d_compile(
+                source,
+                PCSTR::null(), // source name (optional)
+                None,          // defines (optional)
+                None,          // include handler (optional)
+                s!("main"),    // entry point
+                s!("vs_4_0"),  // target profile
+                D3DCOMPILE_DEBUG | D3DCOMPILE_SKIP_OPTIMIZATION, // compilation flags
+                0,             // secondary flags
+            );
+            println!("{} vertex shader compilation complete", name);
+
+            if let Some(error) = error_blob {
+                let error_message =
+                    std::str::from_utf8(blob_as_slice(&error)).unwrap_or("Unknown error");
+                println!("Shader compilation error: {}", error_message);
+            }
+
+            res?;
+
+            let Some(blob) = shader_blob else {
+                return Err(Error::new(
+                    E_FAIL,
+                    format!("Failed to compile {} vertex shader", name),
+                ));
+            };
+            let shader_byte_code = blob_as_slice(&blob);
+            let shader = {
+                let mut shader_out = None;
+                device.CreateVertexShader(shader_byte_code, None, Some(&mut shader_out))?;
+                shader_out.ok_or(E_POINTER)?
+            };
+
+            let input_elements = [
+                D3D11_INPUT_ELEMENT_DESC {
+                    SemanticName: s!("POSITION"),
+                    SemanticIndex: 0,
+                    Format: DXGI_FORMAT_R32G32_FLOAT,
+                    InputSlot: 0,
+                    AlignedByteOffset: 0,
+                    InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                    InstanceDataStepRate: 0,
+                },
+                D3D11_INPUT_ELEMENT_DESC {
+                    SemanticName: s!("TEXCOORD"),
+                    SemanticIndex: 0,
+                    Format: DXGI_FORMAT_R32G32_FLOAT,
+                    InputSlot: 0,
+                    AlignedByteOffset: D3D11_APPEND_ALIGNED_ELEMENT,
+                    InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                    InstanceDataStepRate: 0,
+                },
+            ];
+
+            let input_layout = {
+                let mut layout_out = None;
+                device.CreateInputLayout(
+                    &input_elements,
+                    shader_byte_code,
+                    Some(&mut layout_out),
+                )?;
+                layout_out.ok_or(E_POINTER)?
+            };
+
+            Ok(Self {
+                shader,
+                input_layout,
+            })
+        }
+    }
+}
+
+/// Registers a shader declared the engine's inputs at, found by reflecting its bytecode and
+/// matching resources by name. Inputs a shader doesn't name stay at the conventional register.
+#[derive(Clone, Copy)]
+struct ShaderBindings {
+    // The captured frame, or the previous pass's output
+    source: u32,
+    // `FrameBuffer` time, resolution and parameters
+    frame_constants: u32,
+    // The sampler with the effect's address mode
+    sampler: u32,
+    // The `--texture` image
+    user_texture: u32,
+    // The sidecar data file's structured buffer
+    data: u32,
+    // The frozen snapshot of the source
+    snapshot: u32,
+}
+
+impl ShaderBindings {
+    const CONVENTIONAL: Self = Self {
+        source: 0,
+        frame_constants: 0,
+        sampler: 0,
+        user_texture: 3,
+        data: 4,
+        snapshot: 5,
+    };
+
+    // Lowercase names each input is recognised by
+    const SOURCE_NAMES: &[&str] = &[
+        "source",
+        "sourceimage",
+        "sourcetexture",
+        "screentexture",
+        "inputtexture",
+        "input",
+    ];
+    const FRAME_CONSTANTS_NAMES: &[&str] = &["framebuffer", "timebuffer", "frame"];
+    const USER_TEXTURE_NAMES: &[&str] = &["usertexture", "lut", "gradient", "noise"];
+    const DATA_NAMES: &[&str] = &["data"];
+    const SNAPSHOT_NAMES: &[&str] = &["snapshot", "frozen", "freeze"];
+
+    /// Bindings for `bytecode`, along with the resources it declares that none of the
+    /// engine's inputs match, as `name (register)`
+    fn reflect(bytecode: &[u8]) -> Result<(Self, Vec<String>)> {
+        let reflection = reflect_shader(bytecode)?;
+        let mut shader_desc = D3D11_SHADER_DESC::default();
+        unsafe { reflection.GetDesc(&mut shader_desc) }?;
+
+        let mut resources = Vec::new();
+        for index in 0..shader_desc.BoundResources {
+            let mut desc = D3D11_SHADER_INPUT_BIND_DESC::default();
+            unsafe { reflection.GetResourceBindingDesc(index, &mut desc) }?;
+            let name = unsafe { desc.Name.to_string() }.unwrap_or_default();
+            resources.push((name, desc.Type, desc.BindPoint));
+        }
+
+        let find = |kind: D3D_SHADER_INPUT_TYPE, names: &[&str]| {
+            resources
+                .iter()
+                .find(|(name, ty, _)| *ty == kind && names.contains(&name.to_lowercase().as_str()))
+                .map(|&(_, _, slot)| slot)
+        };
+        let conventional = Self::CONVENTIONAL;
+        let bindings = Self {
+            source: find(D3D_SIT_TEXTURE, Self::SOURCE_NAMES).unwrap_or(conventional.source),
+            frame_constants: find(D3D_SIT_CBUFFER, Self::FRAME_CONSTANTS_NAMES)
+                .unwrap_or(conventional.frame_constants),
+            // Any sampler will do, there's only the one
+            sampler: resources
+                .iter()
+                .find(|(_, ty, _)| *ty == D3D_SIT_SAMPLER)
+                .map_or(conventional.sampler, |&(_, _, slot)| slot),
+            user_texture: find(D3D_SIT_TEXTURE, Self::USER_TEXTURE_NAMES)
+                .unwrap_or(conventional.user_texture),
+            data: find(D3D_SIT_STRUCTURED, Self::DATA_NAMES).unwrap_or(conventional.data),
+            snapshot: find(D3D_SIT_TEXTURE, Self::SNAPSHOT_NAMES).unwrap_or(conventional.snapshot),
+        };
+
+        let unbound = resources
+            .iter()
+            .filter(|&&(_, ty, slot)| match ty {
+                D3D_SIT_TEXTURE => {
+                    slot != bindings.source
+                        && slot != bindings.user_texture
+                        && slot != bindings.snapshot
+                }
+                D3D_SIT_CBUFFER => slot != bindings.frame_constants,
+                D3D_SIT_SAMPLER => slot != bindings.sampler,
+                D3D_SIT_STRUCTURED => slot != bindings.data,
+                _ => true,
+            })
+            .map(|(name, ty, slot)| {
+                let register = match *ty {
+                    D3D_SIT_CBUFFER => 'b',
+                    D3D_SIT_SAMPLER => 's',
+                    D3D_SIT_TEXTURE | D3D_SIT_STRUCTURED | D3D_SIT_TBUFFER
+                    | D3D_SIT_BYTEADDRESS => 't',
+                    _ => 'u',
+                };
+                format!("{} ({}{})", name, register, slot)
+            })
+            .collect();
+        Ok((bindings, unbound))
+    }
+}
+
+/// Tunable value handed to simple shaders in `Params`, adjusted with hotkeys
+#[derive(Clone)]
+struct ShaderParam {
+    name: String,
+    value: f32,
+    default: f32,
+    min: f32,
+    max: f32,
+    step: f32,
+}
+
+impl ShaderParam {
+    fn new(name: &str, default: f32, min: f32, max: f32, step: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            value: default,
+            default,
+            min,
+            max,
+            step,
+        }
+    }
+
+    /// `<default> ["label"] [<min> <max> <step>]`, as written in a `//! paramN` line
+    fn parse(value: &str, index: usize) -> Option<Self> {
+        let (default, rest) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+        let default: f32 = default.parse().ok()?;
+
+        let rest = rest.trim();
+        let (label, rest) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let (label, rest) = quoted.split_once('"')?;
+                (label.to_string(), rest)
+            }
+            None => (format!("param{}", index), rest),
+        };
+
+        let range = rest
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<std::result::Result<Vec<f32>, _>>()
+            .ok()?;
+        let (min, max, step) = match range[..] {
+            [] => {
+                let (min, max) = (default.min(0.0), default.max(1.0));
+                (min, max, (max - min) / 20.0)
+            }
+            [min, max, step] => (min, max, step),
+            _ => return None,
+        };
+        Some(Self::new(&label, default, min, max, step))
+    }
+}
+
+/// Contents of a `<shader>.params.json` sidecar
+#[derive(Deserialize)]
+struct ParamsSidecar {
+    params: Option<Vec<SidecarParam>>,
+    max_fps: Option<f32>,
+    /// File of numbers for the shader's structured buffer, relative to the sidecar
+    data: Option<PathBuf>,
+    /// HLSL vertex shader to draw the effect with, relative to the sidecar
+    vertex_shader: Option<PathBuf>,
+}
+
+/// Settings from a sidecar that replace the ones in the shader's header, where given
+struct SidecarOverrides {
+    params: Option<Vec<ShaderParam>>,
+    max_fps: Option<f32>,
+    data: Option<PathBuf>,
+    vertex_shader: Option<PathBuf>,
+}
+
+/// One `Params` slot as written in a sidecar, unset fields take the same defaults as metadata
+#[derive(Deserialize)]
+struct SidecarParam {
+    name: Option<String>,
+    #[serde(default)]
+    value: f32,
+    min: Option<f32>,
+    max: Option<f32>,
+    step: Option<f32>,
+}
+
+impl ParamsSidecar {
+    /// `Ok(None)` when the shader has no sidecar
+    fn load(dir: &std::path::Path, shader_name: &str) -> Result<Option<SidecarOverrides>> {
+        let path = dir.join(format!("{}.params.json", shader_name));
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::new(
+                    E_FAIL,
+                    format!("Failed to read {}: {}", path.display(), e),
+                ));
+            }
+        };
+        let sidecar: ParamsSidecar = serde_json::from_str(&text).map_err(|e| {
+            Error::new(
+                E_INVALIDARG,
+                format!("Failed to parse {}: {}", path.display(), e),
+            )
+        })?;
+
+        let params = sidecar.params.map(|params| {
+            if params.len() > MAX_SHADER_PARAMS {
+                println!(
+                    "{} defines {} parameters, only the first {} are used",
+                    path.display(),
+                    params.len(),
+                    MAX_SHADER_PARAMS
+                );
+            }
+            params
+                .into_iter()
+                .take(MAX_SHADER_PARAMS)
+                .enumerate()
+                .map(|(index, param)| {
+                    let min = param.min.unwrap_or(param.value.min(0.0));
+                    let max = param.max.unwrap_or(param.value.max(1.0));
+                    ShaderParam::new(
+                        &param.name.unwrap_or_else(|| format!("param{}", index)),
+                        param.value,
+                        min,
+                        max,
+                        param.step.unwrap_or((max - min) / 20.0),
+                    )
+                })
+                .collect()
+        });
+
+        let max_fps = sidecar.max_fps.filter(|&fps| {
+            let valid = fps > 0.0;
+            if !valid {
+                println!("{}: ignoring max_fps {}", path.display(), fps);
+            }
+            valid
+        });
+        Ok(Some(SidecarOverrides {
+            params,
+            max_fps,
+            data: sidecar.data.map(|data| dir.join(data)),
+            vertex_shader: sidecar.vertex_shader.map(|path| dir.join(path)),
+        }))
+    }
+}
+
+// Shaders see at most one float4 of parameters
+const MAX_SHADER_PARAMS: usize = 4;
+
+/// Texture addressing for the sampler bound at s0, requested by a shader's header comment
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SamplerMode {
+    Clamp,
+    Wrap,
+    Mirror,
+}
+
+impl SamplerMode {
+    const ALL: [SamplerMode; 3] = [SamplerMode::Clamp, SamplerMode::Wrap, SamplerMode::Mirror];
+
+    fn address_mode(self) -> D3D11_TEXTURE_ADDRESS_MODE {
+        match self {
+            SamplerMode::Clamp => D3D11_TEXTURE_ADDRESS_CLAMP,
+            SamplerMode::Wrap => D3D11_TEXTURE_ADDRESS_WRAP,
+            SamplerMode::Mirror => D3D11_TEXTURE_ADDRESS_MIRROR,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(SamplerMode::Clamp),
+            "wrap" => Some(SamplerMode::Wrap),
+            "mirror" => Some(SamplerMode::Mirror),
+            _ => None,
+        }
+    }
+}
+
+/// Settings a shader declares about itself in `//! key = value` lines of its leading comment block
+struct ShaderMetadata {
+    passes: u32,
+    sampler_mode: SamplerMode,
+    params: Vec<ShaderParam>,
+    max_fps: Option<f32>,
+}
+
+impl ShaderMetadata {
+    fn parse(source: &[u8], name: &str) -> Self {
+        let mut metadata = Self {
+            passes: 1,
+            sampler_mode: SamplerMode::Clamp,
+            params: Vec::new(),
+            max_fps: None,
+        };
+
+        let source = String::from_utf8_lossy(source);
+        for line in source.lines().map(str::trim) {
+            let Some(comment) = line.strip_prefix("//") else {
+                break; // Header ends at the first non-comment line
+            };
+            let Some(entry) = comment.strip_prefix('!') else {
+                continue; // Ordinary comment
+            };
+            let Some((key, value)) = entry.split_once('=') else {
+                println!(
+                    "{} shader: ignoring malformed metadata '{}'",
+                    name,
+                    entry.trim()
+                );
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "passes" => match value.parse() {
+                    Ok(passes) if passes > 0 => metadata.passes = passes,
+                    _ => println!("{} shader: invalid pass count '{}'", name, value),
+                },
+                "max_fps" => match value.parse() {
+                    Ok(fps) if fps > 0.0 => metadata.max_fps = Some(fps),
+                    _ => println!("{} shader: invalid max_fps '{}'", name, value),
+                },
+                "sampler" => match SamplerMode::from_name(value) {
+                    Some(mode) => metadata.sampler_mode = mode,
+                    None => println!("{} shader requested unknown sampler '{}'", name, value),
+                },
+                _ => {
+                    let index = key
+                        .strip_prefix("param")
+                        .and_then(|index| index.parse::<usize>().ok())
+                        .filter(|&index| index < MAX_SHADER_PARAMS);
+                    let Some(index) = index else {
+                        println!("{} shader: ignoring unknown metadata key '{}'", name, key);
+                        continue;
+                    };
+                    let Some(param) = ShaderParam::parse(value, index) else {
+                        println!("{} shader: invalid {} '{}'", name, key, value);
+                        continue;
+                    };
+                    // Params.x..w are positional, so fill any gap before this one
+                    while metadata.params.len() <= index {
+                        let slot = metadata.params.len();
+                        metadata.params.push(ShaderParam::new(
+                            &format!("param{}", slot),
+                            0.0,
+                            0.0,
+                            1.0,
+                            0.05,
+                        ));
+                    }
+                    metadata.params[index] = param;
+                }
+            }
+        }
+        metadata
+    }
+}
+
+pub(crate) struct CaptureState {
+    start_time: std::time::Instant,
+    // Time shaders see instead of the time since startup, for reproducible output
+    fixed_time: Option<f32>,
+    // How shader time advances, and where it's got to in the smooth and fixed modes
+    time_mode: TimeMode,
+    frame_time: f32,
+    // Smoothed seconds between captured frames, and when the last one arrived
+    frame_interval: f32,
+    last_frame_at: Option<std::time::Instant>,
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    swap_chain: IDXGISwapChain1,
+    swap_chain_format: DXGI_FORMAT,
+    // Shaders sample and write linear light, with sRGB decoded and encoded by the views
+    linear: bool,
+    swap_chain_buffers: u32,
+    swap_effect: SwapEffect,
+    // Set when presenting with alpha through DirectComposition instead of to the HWND
+    composition: Option<Composition>,
+    dxgi_adapter: IDXGIAdapter,
+    // This window's share of its output's duplication
+    pub(crate) duplication: Option<Rc<SharedDuplication>>,
+    // Every duplication on this window's device, for windows capturing the same output to share
+    duplications: DuplicationRegistry,
+    // Serials of the shared duplication's frame this window last drew, `None` before the first
+    seen_serial: Option<u64>,
+    seen_image_serial: Option<u64>,
+    // When the captured image was presented on the desktop as a QueryPerformanceCounter
+    // value, how many presents there have been, and whether the frame being drawn is a newly
+    // captured one rather than a redraw. For the frame constants.
+    present_qpc: i64,
+    present_count: u64,
+    new_frame: bool,
+    // Counts captured frames, and the parts of the latest one that changed since the one
+    // before it, in the shader input's pixels. `None` when everything may have changed.
+    frame_serial: u64,
+    dirty_rects: Option<Vec<RECT>>,
+    // The source rect, output origin and scale the latest frame was copied with. The changed
+    // rects only hold while these stay the same, since they're relative to the frame before.
+    dirty_mapping: Option<(RECT, POINT, f32)>,
+    vertex_shader: ID3D11VertexShader,
+    pixel_shaders: Vec<PixelShaderConfig>,
+    current_shader: usize,
+    // The effect the backtick toggle returns to from passthru
+    last_effect_shader: usize,
+    compute_shader: ID3D11ComputeShader,
+    extend_params_buffer: ID3D11Buffer,
+    // One per SamplerMode, indexed by its discriminant
+    samplers: Vec<ID3D11SamplerState>,
+    vertex_buffer: ID3D11Buffer,
+    render_target_view: Option<ID3D11RenderTargetView>,
+    shader_resource_view: Option<ID3D11ShaderResourceView>,
+    input_layout: ID3D11InputLayout,
+    time_buffer: ID3D11Buffer,
+    // Index into the current shader's params that the adjust hotkeys change
+    selected_param: usize,
+    // Loaded on first use; failed loads are remembered so they aren't retried every frame
+    user_textures: HashMap<PathBuf, Option<ID3D11ShaderResourceView>>,
+    // Copy of the shader input frozen with Ctrl+Z, bound at t5 for comparing against
+    snapshot: Option<ID3D11ShaderResourceView>,
+
+    staging_texture: Option<ID3D11Texture2D>,
+    extended_texture: Option<ID3D11Texture2D>,
+    extended_srv: Option<ID3D11ShaderResourceView>,
+    extended_uav: Option<ID3D11UnorderedAccessView>,
+    last_frame: Option<CapturedFrame>,
+    // When the last frame was rendered, for the current shader's frame rate cap
+    last_render: std::time::Instant,
+    latency_mode: LatencyMode,
+    // Set while another app holds the output and DuplicateOutput keeps being refused
+    duplication_retry: Option<DuplicationRetry>,
+    // Set while the output doesn't exist at all, such as with every monitor asleep or
+    // undocked, retried on the same backoff until one appears
+    waiting_for_display: bool,
+    // Whether the adapter has had an output to duplicate, so a missing one is worth waiting
+    // for rather than a sign the adapter drives no displays at all
+    had_output: bool,
+    // Screen capture through GDI once duplication turned out to be unsupported
+    gdi_capture: Option<GdiCapture>,
+    // Ping-pong targets for multi-pass shaders, sized to the shader pass
+    pass_targets: Vec<PassTarget>,
+    // Supersampling: the shader pass renders at ssaa_factor times the window size into
+    // ssaa_target, then downsample_shader filters it down to the swap chain
+    ssaa_factor: u32,
+    ssaa_target: Option<PassTarget>,
+    ssaa_filter: SsaaFilter,
+    supersample_shader: ID3D11PixelShader,
+    supersample_constants_buffer: ID3D11Buffer,
+    downsample_shader: ID3D11PixelShader,
+    // Multisampling: the shader pass renders into msaa_target with msaa_samples samples per
+    // pixel, which is resolved into the supersampling target or the swap chain
+    msaa_samples: u32,
+    msaa_target: Option<MsaaTarget>,
+    // The shader pass draws into effect_output instead when it's post-processed, by motion
+    // blur or color adjustment
+    effect_output: Option<PassTarget>,
+    // Motion blur: how much of the previous output each frame keeps, 0 for off. The effect's
+    // output is blended into motion_blur_accumulation with blend_factor_state, and the
+    // accumulation is what's shown.
+    motion_blur: f32,
+    motion_blur_accumulation: Option<PassTarget>,
+    blend_factor_state: ID3D11BlendState,
+    // Gamma, brightness, contrast and saturation applied to the effect's output by
+    // adjust_shader while adjust_colors is on, and the one the hotkeys change
+    adjust_colors: bool,
+    color_adjustments: [f32; 4],
+    selected_adjustment: usize,
+    adjust_shader: ID3D11PixelShader,
+    adjust_constants_buffer: ID3D11Buffer,
+    // Split view divider as a fraction of the window width, with the raw capture drawn to
+    // its left over the effect. `None` while the split view is off.
+    split_view: Option<f32>,
+    dragging_divider: bool,
+    // The shader picker's highlighted shader, `None` while the picker is closed. Each
+    // thumbnail is drawn into picker_thumbnail at the grid's cell size, then copied into place.
+    picker: Option<usize>,
+    picker_thumbnail: Option<PassTarget>,
+    // The hotkey list is drawn over the output, until F1 or any other key
+    help: bool,
+    // The last frame presented has an overlay drawn over it, which saving has to draw again
+    // without
+    overlay_drawn: bool,
+    // Whether the capture latency is drawn over the output, and the latest measurements in
+    // milliseconds from the desktop presenting an image to this window presenting it
+    show_latency: bool,
+    latency_samples: std::collections::VecDeque<f32>,
+    // Clips the raw side of the split view to the left of the divider
+    scissor_rasterizer: ID3D11RasterizerState,
+    // Draws text from the font spritesheet over the output, for the screenshot countdown
+    glyph_shader: ID3D11PixelShader,
+    glyph_constants_buffer: ID3D11Buffer,
+    font_srv: ID3D11ShaderResourceView,
+    font_layout: GlyphLayout,
+    // Cycling through the shaders on a timer, and how long each one shows for
+    slideshow: bool,
+    slideshow_interval: u32,
+    screenshot_delay: u32,
+    // Where screenshots go, `None` for the working directory
+    screenshot_dir: Option<PathBuf>,
+    // Seconds left before the delayed screenshot is saved, while counting down
+    screenshot_countdown: Option<u32>,
+    save_beep: bool,
+    save_flash: bool,
+    save_background: SaveBackground,
+    source_rect: RECT,
+    capture_output: u32,
+    // Desktop position of the duplicated output, which its frames are relative to
+    output_origin: POINT,
+    // How the duplicated output's display is rotated, its frames arrive unrotated
+    output_rotation: DXGI_MODE_ROTATION,
+    // Window sits on another monitor and shows the whole captured output
+    presentation: bool,
+    // source_rect was pinned on the command line or by dragging out a region, and no longer
+    // follows the window
+    fixed_source: bool,
+    // Replaces desktop duplication with a pattern or image when set
+    test_source: Option<TestSource>,
+    test_texture: Option<ID3D11Texture2D>,
+    // Two side by side periods of the gradient test source, scrolled through by copying
+    test_gradient: Option<ID3D11Texture2D>,
+    // Fraction of the window resolution the capture/extend/shader input runs at
+    render_scale: f32,
+    // Most pixels the scaled capture may have before it's scaled down further, and whether
+    // the last frame needed that
+    max_capture_pixels: Option<u32>,
+    capture_capped: bool,
+
+    // Padding where the capture region runs off the output, and its color when filled
+    edge_mode: EdgeMode,
+    edge_fill: [f32; 4],
+    // Mirror the captured source before the shader sees it
+    flip_h: bool,
+    flip_v: bool,
+    // Draw the mouse cursor into the captured source, which frames don't include by themselves
+    include_cursor: bool,
+    // Shows through wherever a shader leaves the frame uncovered or transparent
+    clear_color: [f32; 4],
+
+    always_on_top: bool,
+    paused: bool,
+    // Holds the last captured frame as the source while shading and time carry on, unlike
+    // pausing, which stops rendering altogether
+    frozen: bool,
+    // Pausing also lets other apps capture the window, so it can be screenshotted
+    pause_makes_capturable: bool,
+    // Hidden to the tray icon, nothing is rendered until restored
+    hidden: bool,
+    // The last present reported nothing of the window is visible
+    occluded: bool,
+    // Closing the window hides it to the tray instead of exiting
+    close_to_tray: bool,
+    pub(crate) hwnd: HWND,
+    // The first window has the tray icon and quits the app when closed. Extra views share its
+    // device and are owned windows of it, so they close along with it.
+    primary: bool,
+    // The primary's command endpoint, with `--ipc-port`
+    ipc: Option<ipc::Listener>,
+    // Running as the full-screen screensaver, which quits on keyboard or mouse input
+    screensaver: bool,
+    // Where the cursor was when the screensaver started
+    screensaver_cursor: POINT,
+
+    idle_timeout: Option<std::time::Duration>,
+    // No user input for longer than idle_timeout, so rendering is suspended
+    idle: bool,
+
+    // Frames presented since the title was last refreshed
+    frames_since_title: u32,
+    last_title_update: std::time::Instant,
+    printed_tiles_constants: bool,
+
+    #[cfg(feature = "shared-output")]
+    shared_output: Option<shared_output::SharedOutput>,
+}
+
+#[repr(C)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coord: [f32; 2],
+}
+
+const VERTEX_SHADER: &[u8] = b"
+struct VS_INPUT {
+    float2 pos : POSITION;
+    float2 tex : TEXCOORD;
+};
+
+struct VS_OUTPUT {
+    float4 pos : SV_POSITION;
+    float2 tex : TEXCOORD;
+};
+
+VS_OUTPUT main(VS_INPUT input) {
+    VS_OUTPUT output;
+    output.pos = float4(input.pos, 0.0f, 1.0f);
+    output.tex = input.tex;
+    return output;
+}";
+
+// Bilinear copy of a texture into the viewport, whatever their sizes
+const DOWNSAMPLE_SHADER: &[u8] = b"
+Texture2D source : register(t0);
+SamplerState linearSampler : register(s0);
+
+float4 main(float4 pos : SV_POSITION, float2 tex : TEXCOORD) : SV_TARGET {
+    return source.Sample(linearSampler, tex);
+}";
+
+#[repr(C)]
+struct SupersampleConstants {
+    factor: u32,
+    gaussian: u32,
+    padding: [u32; 2],
+}
+
+// Filters the factor x factor block of supersampled texels under each window pixel, evenly
+// or with gaussian weights that also take in the texels bordering the block
+const SUPERSAMPLE_SHADER: &[u8] = b"
+Texture2D source : register(t0);
+
+cbuffer SupersampleConstants : register(b0) {
+    uint factor;
+    uint gaussian;
+    uint2 padding;
+};
+
+float4 main(float4 pos : SV_POSITION, float2 tex : TEXCOORD) : SV_TARGET {
+    uint width, height;
+    source.GetDimensions(width, height);
+    int2 origin = int2(pos.xy) * factor;
+    float2 center = floor(pos.xy) * factor + factor * 0.5;
+    int reach = gaussian ? 1 : 0;
+    float sigma = factor * 0.5;
+
+    float4 sum = 0;
+    float total = 0;
+    for (int y = -reach; y < (int)factor + reach; y++) {
+        for (int x = -reach; x < (int)factor + reach; x++) {
+            int2 texel = origin + int2(x, y);
+            float2 offset = texel + 0.5 - center;
+            float weight = gaussian ? exp(-dot(offset, offset) / (2 * sigma * sigma)) : 1.0;
+            texel = clamp(texel, int2(0, 0), int2(width - 1, height - 1));
+            sum += source.Load(int3(texel, 0)) * weight;
+            total += weight;
+        }
+    }
+    return sum / total;
+}";
+
+// Color correction of the effect's output, undoing and redoing premultiplied alpha around it
+const ADJUST_SHADER: &[u8] = b"
+Texture2D source : register(t0);
+SamplerState linearSampler : register(s0);
+
+cbuffer Adjustments : register(b0) {
+    float gamma;
+    float brightness;
+    float contrast;
+    float saturation;
+};
+
+float4 main(float4 pos : SV_POSITION, float2 tex : TEXCOORD) : SV_TARGET {
+    float4 color = source.Sample(linearSampler, tex);
+    float3 rgb = color.a > 0 ? color.rgb / color.a : color.rgb;
+    rgb = pow(saturate(rgb), 1.0 / gamma);
+    rgb = (rgb - 0.5) * contrast + 0.5 + brightness;
+    float luma = dot(rgb, float3(0.2126, 0.7152, 0.0722));
+    rgb = saturate(lerp(luma, rgb, saturation));
+    return float4(rgb * color.a, color.a);
+}";
+
+/// One of the color adjustments, in the order the adjust shader takes them
+struct ColorAdjustment {
+    name: &'static str,
+    identity: f32,
+    step: f32,
+    min: f32,
+    max: f32,
+}
+
+const COLOR_ADJUSTMENTS: [ColorAdjustment; 4] = [
+    ColorAdjustment {
+        name: "gamma",
+        identity: 1.0,
+        step: 0.1,
+        min: 0.2,
+        max: 5.0,
+    },
+    ColorAdjustment {
+        name: "brightness",
+        identity: 0.0,
+        step: 0.05,
+        min: -1.0,
+        max: 1.0,
+    },
+    ColorAdjustment {
+        name: "contrast",
+        identity: 1.0,
+        step: 0.1,
+        min: 0.0,
+        max: 4.0,
+    },
+    ColorAdjustment {
+        name: "saturation",
+        identity: 1.0,
+        step: 0.1,
+        min: 0.0,
+        max: 4.0,
+    },
+];
+
+#[repr(C)]
+struct GlyphConstants {
+    glyph_origin: [u32; 2],
+    glyph_size: [u32; 2],
+}
+
+// Draws one spritesheet cell across the viewport
+const GLYPH_SHADER: &[u8] = b"
+Texture2D<float4> spritesheet : register(t0);
+
+cbuffer GlyphConstants : register(b0) {
+    uint2 glyphOrigin;  // Top left texel of the cell
+    uint2 glyphSize;
+}
+
+float4 main(float4 pos : SV_POSITION, float2 tex : TEXCOORD) : SV_TARGET {
+    // Nearest texel, so the glyph stays crisp when scaled up
+    uint2 texel = glyphOrigin + min(uint2(tex * glyphSize), glyphSize - 1);
+    float coverage = spritesheet.Load(int3(texel, 0)).r;
+    return float4(coverage, coverage, coverage, 1.0);
+}";
+
+#[repr(C)]
+struct ExtendParams {
+    src_size: [u32; 2],
+    dst_size: [u32; 2],
+    src_offset: [i32; 2],
+    scale: f32,
+    flip: u32,
+    // Quarter turns clockwise from the staging texture's layout to the desktop's
+    rotation: u32,
+    // EdgeMode as 0 clamp, 1 mirror, 2 wrap, 3 fill
+    edge_mode: u32,
+    // HLSL starts a float4 on a 16-byte boundary
+    padding: [u32; 2],
+    // Padding color for the fill edge mode, in the extended texture's encoding
+    fill_color: [f32; 4],
+}
+
+// ExtendParams::flip bits
+const FLIP_HORIZONTAL: u32 = 1;
+const FLIP_VERTICAL: u32 = 2;
+
+const EXTEND_COMPUTE_SHADER: &[u8] = b"
+Texture2D<float4> srcTexture : register(t0);
+RWTexture2D<float4> dstTexture : register(u0);
+
+cbuffer ExtendParams : register(b0) {
+    uint2 srcSize;
+    uint2 dstSize;
+    int2 srcOffset;  // Where the source starts in the destination (unscaled)
+    float scale;     // Destination size relative to the unscaled extended region
+    uint flip;       // Bit 0 mirrors horizontally, bit 1 vertically
+    uint rotation;   // Quarter turns clockwise from the source's layout to the desktop's
+    uint edgeMode;   // Padding off the source's edges: 0 clamp, 1 mirror, 2 wrap, 3 fill
+    float4 fillColor; // Padding for the fill edge mode
+}
+
+// Bring a position outside [0, size) back inside by reflecting or repeating the source
+int fold(int pos, int size) {
+    if (edgeMode == 1) {
+        int period = 2 * size;
+        int m = ((pos % period) + period) % period;
+        return m < size ? m : period - 1 - m;
+    }
+    if (edgeMode == 2)
+        return ((pos % size) + size) % size;
+    return clamp(pos, 0, size - 1);
+}
+
+[numthreads(8, 8, 1)]
+void main(uint3 dispatchThreadID : SV_DispatchThreadID) {
+    uint2 dstPos = dispatchThreadID.xy;
+
+    if (dstPos.x >= dstSize.x || dstPos.y >= dstSize.y)
+        return;
+
+    // Mirror where we read from, so the clamping below still sees an unflipped layout
+    uint2 readPos = dstPos;
+    if (flip & 1)
+        readPos.x = dstSize.x - 1 - dstPos.x;
+    if (flip & 2)
+        readPos.y = dstSize.y - 1 - dstPos.y;
+
+    // Calculate source position (may be out of bounds), nearest sample when downscaled
+    int2 unscaledPos = int2((float2(readPos) + 0.5) / scale);
+    int2 srcPos = unscaledPos - srcOffset;
+
+    // Off the source's edges the fill mode pads with its color rather than source pixels
+    if (edgeMode == 3 && (any(srcPos < 0) || any(srcPos >= int2(srcSize)))) {
+        dstTexture[dstPos] = fillColor;
+        return;
+    }
+
+    // Keep within the source texture bounds, padding in the chosen edge mode
+    srcPos.x = fold(srcPos.x, (int)srcSize.x);
+    srcPos.y = fold(srcPos.y, (int)srcSize.y);
+
+    // A rotated display's frames are stored turned, srcSize is the desktop's orientation
+    if (rotation == 1)
+        srcPos = int2(srcPos.y, (int)srcSize.x - 1 - srcPos.x);
+    else if (rotation == 2)
+        srcPos = int2(srcSize) - 1 - srcPos;
+    else if (rotation == 3)
+        srcPos = int2((int)srcSize.y - 1 - srcPos.y, srcPos.x);
+
+    // Read from source and write to destination
+    float4 color = srcTexture.Load(int3(srcPos, 0));
+    dstTexture[dstPos] = color;
+}";
+
+const PIXEL_SHADER_PASSTHRU: &[u8] = include_bytes!("../shaders/passthru.hlsl");
+const PIXEL_SHADER_WOBBLY: &[u8] = include_bytes!("../shaders/wobbly.hlsl");
+const PIXEL_SHADER_LIGHTNING: &[u8] = include_bytes!("../shaders/lightning.hlsl");
+const PIXEL_SHADER_SORTY: &[u8] = include_bytes!("../shaders/sorty.hlsl");
+const PIXEL_SHADER_TILES: &[u8] = include_bytes!("../shaders/tiles.hlsl");
+const TILES_UPDATE_SHADER: &[u8] = include_bytes!("../shaders/tiles_update.hlsl");
+const PIXEL_SHADER_HEATMAP: &[u8] = include_bytes!("../shaders/heatmap.hlsl");
+const PIXEL_SHADER_DITHER: &[u8] = include_bytes!("../shaders/dither.hlsl");
+const FONT_SPRITESHEET_PNG: &[u8] = include_bytes!("../shaders/font_spritesheet.png");
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+struct TilesConstants {
+    source_resolution: [f32; 2],
+    tile_size: [f32; 2],
+    tiles_per_row: i32,
+    total_tiles: i32,
+    spritesheet_resolution: [f32; 2],
+    glyph_padding: f32,
+    curve_gamma: f32,
+    // HLSL starts a float4 on a 16-byte boundary
+    padding: [f32; 2],
+    curve_points: [f32; 4],
+    // Red, green and blue brightness weights, the last component is unused
+    luma_weights: [f32; 4],
+    // Tiles across and down the source, and the first one the update pass dispatch covers
+    grid_size: [u32; 2],
+    grid_offset: [u32; 2],
+    overlay_opacity: f32,
+    // Spritesheet frames the glyphs animate through, how many a second, and the time
+    frame_count: u32,
+    frame_rate: f32,
+    time: f32,
+}
+
+/// Tiles overlay opacities cycled with Ctrl+Shift+G, from glyphs only to a faint overlay
+const TILES_OPACITIES: [f32; 4] = [1.0, 0.75, 0.5, 0.25];
+
+// Changed tile ranges updated separately before the whole grid is cheaper to redo at once
+const MAX_DIRTY_TILE_RANGES: usize = 32;
+
+/// One usable glyph in the tiles brightness buffer, matching the shader's `TileGlyph`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TileGlyph {
+    brightness: f32,
+    // Spritesheet cell, counted row-major from the top left
+    cell: u32,
+}
+
+/// Built-in brightness curves for the tiles effect, cycled with Ctrl+G
+const BRIGHTNESS_CURVES: [BrightnessCurve; 4] = [
+    BrightnessCurve::gamma("linear", 1.0),
+    BrightnessCurve::gamma("lift shadows", 0.6),
+    BrightnessCurve::gamma("deepen shadows", 1.6),
+    BrightnessCurve {
+        name: "contrast",
+        gamma: 1.0,
+        points: [0.0, 0.15, 0.85, 1.0],
+    },
+];
+
+/// Contents of the b0 buffer shared by the simple shaders
+#[repr(C)]
+struct FrameConstants {
+    time: f32,
+    resolution: [f32; 2],
+    pass: u32,
+    params: [f32; MAX_SHADER_PARAMS],
+    present_time: f32,
+    present_count: u32,
+    new_frame: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+struct HistoryConstants {
+    time: f32,
+    decay: f32,
+    resolution: [u32; 2],
+}
+
+// One float4 per render target pixel
+const HISTORY_ELEMENT_SIZE: u32 = 16;
+
+// The motion blur decays Ctrl+O steps through
+const MOTION_BLUR_STEPS: [f32; 3] = [0.5, 0.8, 0.9];
+
+// Ctrl+M cycles supersampling from 1x up to this
+const MAX_SSAA_FACTOR: u32 = 4;
+
+// Exit code when startup fails, distinct from the generic 1 of a panic
+const EXIT_INIT_FAILURE: u8 = 2;
+// Exit code when a shader's output no longer matches its golden image
+const EXIT_GOLDEN_MISMATCH: u8 = 3;
+
+// Size the test patterns are rendered at for golden images
+const GOLDEN_WIDTH: u32 = 640;
+const GOLDEN_HEIGHT: u32 = 360;
+
+pub fn main() -> std::process::ExitCode {
+    // Everything `run` returns an error for happens during startup; per-frame errors are
+    // handled inside the message loop and never end the process
+    crash_log::install();
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            println!("Fatal error: {:?}", e);
+            let text = format!(
+                "Scrim Shady failed to start:\n\n{}\n\nHRESULT 0x{:08X}",
+                e.message(),
+                e.code().0 as u32
+            );
+            let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+            unsafe {
+                MessageBoxW(
+                    None,
+                    PCWSTR(text_wide.as_ptr()),
+                    w!("Scrim Shady"),
+                    MB_OK | MB_ICONERROR,
+                );
+            }
+            std::process::ExitCode::from(EXIT_INIT_FAILURE)
+        }
+    }
+}
+
+impl CaptureState {
+    /// Create the device, swap chain and shader pipeline that render into `hwnd`
+    pub(crate) fn new(hwnd: HWND, config: &Config, shaders: EmbeddedShaders) -> Result<Self> {
+        #[cfg(debug_assertions)]
+        let flags = D3D11_CREATE_DEVICE_BGRA_SUPPORT | D3D11_CREATE_DEVICE_DEBUG;
+        #[cfg(not(debug_assertions))]
+        let flags = D3D11_CREATE_DEVICE_BGRA_SUPPORT;
+
+        // Create D3D11 device and context, in software where there's no hardware to use
+        let (device, context) = if config.force_warp {
+            println!("Using WARP software rendering");
+            create_device(D3D_DRIVER_TYPE_WARP, flags)?
+        } else {
+            create_device(D3D_DRIVER_TYPE_HARDWARE, flags).or_else(|e| {
+                println!(
+                    "Warning: no hardware D3D11 device ({:?}), falling back to WARP software rendering",
+                    e
+                );
+                create_device(D3D_DRIVER_TYPE_WARP, flags)
+            })?
+        };
+
+        // Create swap chain
+        let dxgi_device: IDXGIDevice = device.cast()?;
+        let dxgi_adapter: IDXGIAdapter = unsafe { dxgi_device.GetAdapter()? };
+
+        // 10-bit output smooths gradients on capable displays, if the device can scan it out
+        let swap_chain_format = if config.ten_bit && config.transparent {
+            // Composition only takes premultiplied alpha in 8-bit and float formats
+            println!("10-bit output isn't available with --transparent, using 8-bit");
+            DXGI_FORMAT_B8G8R8A8_UNORM
+        } else if config.ten_bit {
+            let support =
+                unsafe { device.CheckFormatSupport(DXGI_FORMAT_R10G10B10A2_UNORM) }.unwrap_or(0);
+            let required =
+                (D3D11_FORMAT_SUPPORT_RENDER_TARGET.0 | D3D11_FORMAT_SUPPORT_DISPLAY.0) as u32;
+            if support & required == required {
+                DXGI_FORMAT_R10G10B10A2_UNORM
+            } else {
+                println!("10-bit swap chain not supported, falling back to 8-bit");
+                DXGI_FORMAT_B8G8R8A8_UNORM
+            }
+        } else {
+            DXGI_FORMAT_B8G8R8A8_UNORM
+        };
+
+        // There's no sRGB view of a 10-bit swap chain
+        let linear = config.linear && swap_chain_format == DXGI_FORMAT_B8G8R8A8_UNORM;
+        if config.linear && !linear {
+            println!("Linear light rendering isn't available with --10bit, shading in sRGB");
+        }
+
+        let (swap_chain, composition) = create_swap_chain(
+            &device,
+            &dxgi_adapter,
+            hwnd,
+            swap_chain_format,
+            config.swap_chain_buffers,
+            config.swap_effect,
+            config.transparent,
+        )?;
+
+        // Create shaders
+        let VertexStage {
+            shader: vertex_shader,
+            input_layout,
+        } = VertexStage::compile(&device, VERTEX_SHADER, "default")?;
+        println!("created vertex shader");
+
+        let EmbeddedShaders {
+            simple: simple_shaders,
+            tiles: tiles_pending,
+            heatmap: heatmap_pending,
+            dither: dither_pending,
+        } = shaders;
+        let mut pixel_shaders = simple_shaders
+            .into_iter()
+            .map(|pending| {
+                let name = pending.name;
+                let metadata = ShaderMetadata::parse(pending.source, name);
+                let (shader, bindings, bytecode) = pending.finish(&device, &[])?;
+                Ok(PixelShaderConfig {
+                    name: name.to_string(),
+                    shader_type: ShaderType::Simple(shader),
+                    user_texture: config.texture_for(name),
+                    sampler_mode: metadata.sampler_mode,
+                    params: metadata.params,
+                    passes: metadata.passes,
+                    max_fps: metadata.max_fps,
+                    data_srv: None,
+                    bindings,
+                    vertex_stage: None,
+                    bytecode,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        println!("compiled pixel shaders");
+
+        // Setup tiles shader (ASCII art effect)
+        println!("Setting up tiles shader...");
+        // The spritesheet and glyph cells are bound by the tiles setup itself
+        let (tiles_shader, tiles_bindings, tiles_bytecode) =
+            tiles_pending.finish(&device, &["TileSpritesheet", "TileCells"])?;
+        let tiles_update_shader =
+            compile_compute_shader(&device, TILES_UPDATE_SHADER, "tiles update")?;
+
+        // Load the font spritesheet from embedded bytes
+        let (_sheet_tex, sheet_srv, sheet_w, sheet_h, mut pixels) =
+            load_image_from_bytes(&device, FONT_SPRITESHEET_PNG, "font_spritesheet.png", false)?;
+        let font_srv = sheet_srv.clone();
+        // Filtering straight alpha blends in the color of transparent texels, darkening the
+        // edges of anti-aliased glyphs, so the tiles sheets are premultiplied. Brightness is
+        // then measured as the glyph shows over black.
+        premultiply_bgra8(&mut pixels);
+
+        // Determine tile layout (8x16 character tiles)
+        let layout = GlyphLayout::new(
+            (sheet_w, sheet_h),
+            (8, 16),
+            config.glyph_padding,
+            config.glyph_offset,
+            config.glyph_count,
+            config.glyph_cells.as_deref(),
+        )?;
+
+        // Compute brightness for each tile
+        let glyphs = compute_tile_brightness(&pixels, sheet_w, &layout, config.tiles_luma);
+
+        // Animation frames follow the embedded sheet in one array. Glyphs are picked by the
+        // embedded sheet's brightness, so each frame should keep its cells' look roughly alike.
+        let mut frame_pixels = vec![pixels];
+        for path in &config.tiles_frames {
+            let (_, _, width, height, mut pixels) = load_image_from_file(&device, path, false)?;
+            if (width, height) != (sheet_w, sheet_h) {
+                return Err(Error::new(
+                    E_INVALIDARG,
+                    format!(
+                        "{} is {}x{}, tiles frames must match the {}x{} spritesheet",
+                        path.display(),
+                        width,
+                        height,
+                        sheet_w,
+                        sheet_h
+                    ),
+                ));
+            }
+            premultiply_bgra8(&mut pixels);
+            frame_pixels.push(pixels);
+        }
+        let sheet_frames = frame_pixels.len() as u32;
+        let sheet_srv = create_texture_array_srv(&device, sheet_w, sheet_h, &frame_pixels)?;
+
+        let brightness_srv = create_structured_buffer_srv(&device, &glyphs)?;
+        println!("Created brightness buffer with {} elements", glyphs.len());
+
+        // Create constant buffer for tiles shader parameters
+        println!(
+            "Creating constant buffer ({} bytes)",
+            std::mem::size_of::<TilesConstants>()
+        );
+        let tiles_constants_buffer = unsafe {
+            let buffer_desc = D3D11_BUFFER_DESC {
+                ByteWidth: std::mem::size_of::<TilesConstants>() as u32,
+                Usage: D3D11_USAGE_DYNAMIC,
+                BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+                CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
+                MiscFlags: 0,
+                StructureByteStride: 0,
+            };
+
+            let mut buffer_out = None;
+            let result = device.CreateBuffer(&buffer_desc, None, Some(&mut buffer_out));
+            if let Err(e) = result {
+                println!("ERROR creating constant buffer: {:?}", e);
+                println!(
+                    "Buffer size: {} bytes",
+                    std::mem::size_of::<TilesConstants>()
+                );
+                return Err(e);
+            }
+            buffer_out.ok_or(E_POINTER)?
+        };
+        println!("Constant buffer created successfully");
+
+        // Add tiles shader to the list
+        let tiles_metadata = ShaderMetadata::parse(PIXEL_SHADER_TILES, "tiles");
+        pixel_shaders.push(PixelShaderConfig {
+            name: "tiles".to_string(),
+            shader_type: ShaderType::Tiles {
+                shader: tiles_shader,
+                update_shader: tiles_update_shader,
+                spritesheet_srv: sheet_srv,
+                brightness_srv,
+                constants_buffer: tiles_constants_buffer,
+                sheet_width: sheet_w,
+                sheet_height: sheet_h,
+                layout: layout.clone(),
+                // A curve from the command line comes first so it's the one in effect at startup
+                curves: config
+                    .tiles_curve
+                    .iter()
+                    .chain(BRIGHTNESS_CURVES.iter())
+                    .cloned()
+                    .collect(),
+                current_curve: 0,
+                luma: config.tiles_luma,
+                columns: config.tiles_columns,
+                opacity: config.tiles_opacity,
+                frame_count: sheet_frames,
+                frame_rate: config.tiles_frame_rate,
+                cells: None, // Created on first use at the grid's size
+            },
+            user_texture: config.texture_for("tiles"),
+            sampler_mode: tiles_metadata.sampler_mode,
+            params: tiles_metadata.params,
+            passes: tiles_metadata.passes,
+            max_fps: tiles_metadata.max_fps,
+            data_srv: None,
+            vertex_stage: None,
+            bindings: tiles_bindings,
+            bytecode: tiles_bytecode,
+        });
+        println!("tiles shader ready");
+
+        // Compile and setup heatmap shader (motion accumulated across frames)
+        let (heatmap_shader, heatmap_bindings, heatmap_bytecode) =
+            heatmap_pending.finish(&device, &["History"])?;
+        let history_constants_buffer = unsafe {
+            let buffer_desc = D3D11_BUFFER_DESC {
+                ByteWidth: std::mem::size_of::<HistoryConstants>() as u32,
+                Usage: D3D11_USAGE_DYNAMIC,
+                BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+                CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
+                MiscFlags: 0,
+                StructureByteStride: 0,
+            };
+
+            let mut buffer_out = None;
+            device.CreateBuffer(&buffer_desc, None, Some(&mut buffer_out))?;
+            buffer_out.ok_or(E_POINTER)?
+        };
+        let heatmap_metadata = ShaderMetadata::parse(PIXEL_SHADER_HEATMAP, "heatmap");
+        pixel_shaders.push(PixelShaderConfig {
+            name: "heatmap".to_string(),
+            shader_type: ShaderType::History {
+                shader: heatmap_shader,
+                constants_buffer: history_constants_buffer,
+                decay: 0.95,
+                history: None, // Created on first use at the render target size
+            },
+            user_texture: config.texture_for("heatmap"),
+            sampler_mode: heatmap_metadata.sampler_mode,
+            params: heatmap_metadata.params,
+            passes: heatmap_metadata.passes,
+            max_fps: heatmap_metadata.max_fps,
+            data_srv: None,
+            vertex_stage: None,
+            bindings: heatmap_bindings,
+            bytecode: heatmap_bytecode,
+        });
+        println!("heatmap shader ready");
+
+        // Ordered-dither monochrome print look
+        let dither_metadata = ShaderMetadata::parse(PIXEL_SHADER_DITHER, "dither");
+        let (dither_shader, dither_bindings, dither_bytecode) =
+            dither_pending.finish(&device, &[])?;
+        pixel_shaders.push(PixelShaderConfig {
+            name: "dither".to_string(),
+            shader_type: ShaderType::Simple(dither_shader),
+            user_texture: config.texture_for("dither"),
+            sampler_mode: dither_metadata.sampler_mode,
+            params: dither_metadata.params,
+            passes: dither_metadata.passes,
+            max_fps: dither_metadata.max_fps,
+            data_srv: None,
+            vertex_stage: None,
+            bindings: dither_bindings,
+            bytecode: dither_bytecode,
+        });
+
+        // Sidecar settings take precedence over the defaults in a shader's header
+        if let Some(dir) = &config.shader_dir {
+            for shader in pixel_shaders.iter_mut() {
+                match ParamsSidecar::load(dir, &shader.name) {
+                    Ok(Some(overrides)) => {
+                        if let Some(params) = overrides.params {
+                            println!("Loaded {} parameters for {}", params.len(), shader.name);
+                            shader.params = params;
+                        }
+                        if let Some(fps) = overrides.max_fps {
+                            println!("{} capped at {} fps", shader.name, fps);
+                            shader.max_fps = Some(fps);
+                        }
+                        if let Some(path) = overrides.data {
+                            match load_float_data(&path)
+                                .and_then(|values| create_structured_buffer_srv(&device, &values))
+                            {
+                                Ok(srv) => shader.data_srv = Some(srv),
+                                Err(e) => println!(
+                                    "Failed to load {} data from {}: {:?}",
+                                    shader.name,
+                                    path.display(),
+                                    e
+                                ),
+                            }
+                        }
+                        if let Some(path) = overrides.vertex_shader {
+                            let compiled = std::fs::read(&path)
+                                .map_err(|e| {
+                                    Error::new(
+                                        E_FAIL,
+                                        format!("Failed to read {}: {}", path.display(), e),
+                                    )
+                                })
+                                .and_then(|source| {
+                                    VertexStage::compile(&device, &source, &shader.name)
+                                });
+                            match compiled {
+                                Ok(stage) => shader.vertex_stage = Some(stage),
+                                Err(e) => println!(
+                                    "Failed to load {} vertex shader from {}, using the default: {:?}",
+                                    shader.name,
+                                    path.display(),
+                                    e
+                                ),
+                            }
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => println!("Failed to load {} parameters: {:?}", shader.name, e),
+                }
+            }
+        }
+
+        for config in &pixel_shaders {
+            if config.passes > 1 && !matches!(config.shader_type, ShaderType::Simple(_)) {
+                println!(
+                    "Warning: {} shader asks for {} passes, only simple shaders support more than one",
+                    config.name, config.passes
+                );
+            }
+        }
+
+        for (name, path) in &config.shader_textures {
+            if !pixel_shaders.iter().any(|config| &config.name == name) {
+                println!(
+                    "Warning: texture {} configured for unknown shader {}",
+                    path.display(),
+                    name
+                );
+            }
+        }
+
+        // Create compute shader for texture extension
+        let compute_shader = compile_compute_shader(&device, EXTEND_COMPUTE_SHADER, "extend")?;
+        println!("created compute shader");
+
+        let downsample_shader = unsafe {
+            let bytecode = compile_pixel_shader(DOWNSAMPLE_SHADER, "downsample", false)?;
+            let mut shader_out = None;
+            device.CreatePixelShader(&bytecode, None, Some(&mut shader_out))?;
+            shader_out.ok_or(E_POINTER)?
+        };
+
+        let supersample_shader = unsafe {
+            let bytecode = compile_pixel_shader(SUPERSAMPLE_SHADER, "supersample", false)?;
+            let mut shader_out = None;
+            device.CreatePixelShader(&bytecode, None, Some(&mut shader_out))?;
+            shader_out.ok_or(E_POINTER)?
+        };
+        let supersample_constants_buffer = unsafe {
+            let buffer_desc = D3D11_BUFFER_DESC {
+                ByteWidth: std::mem::size_of::<SupersampleConstants>() as u32,
+                Usage: D3D11_USAGE_DYNAMIC,
+                BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+                CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
+                MiscFlags: 0,
+                StructureByteStride: 0,
+            };
+            let mut buffer_out = None;
+            device.CreateBuffer(&buffer_desc, None, Some(&mut buffer_out))?;
+            buffer_out.ok_or(E_POINTER)?
+        };
+
+        let adjust_shader = unsafe {
+            let bytecode = compile_pixel_shader(ADJUST_SHADER, "adjust", false)?;
+            let mut shader_out = None;
+            device.CreatePixelShader(&bytecode, None, Some(&mut shader_out))?;
+            shader_out.ok_or(E_POINTER)?
+        };
+        let adjust_constants_buffer = unsafe {
+            let buffer_desc = D3D11_BUFFER_DESC {
+                ByteWidth: std::mem::size_of::<[f32; 4]>() as u32,
+                Usage: D3D11_USAGE_DYNAMIC,
+                BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+                CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
+                MiscFlags: 0,
+                StructureByteStride: 0,
+            };
+            let mut buffer_out = None;
+            device.CreateBuffer(&buffer_desc, None, Some(&mut buffer_out))?;
+            buffer_out.ok_or(E_POINTER)?
+        };
+
+        let scissor_rasterizer = unsafe {
+            let desc = D3D11_RASTERIZER_DESC {
+                FillMode: D3D11_FILL_SOLID,
+                CullMode: D3D11_CULL_NONE,
+                DepthClipEnable: true.into(),
+                ScissorEnable: true.into(),
+                ..Default::default()
+            };
+            let mut rasterizer_out = None;
+            device.CreateRasterizerState(&desc, Some(&mut rasterizer_out))?;
+            rasterizer_out.ok_or(E_POINTER)?
+        };
+
+        // Mixes the source and target by the blend factor, for accumulating motion blur
+        let blend_factor_state = unsafe {
+            let mut desc = D3D11_BLEND_DESC::default();
+            desc.RenderTarget[0] = D3D11_RENDER_TARGET_BLEND_DESC {
+                BlendEnable: true.into(),
+                SrcBlend: D3D11_BLEND_INV_BLEND_FACTOR,
+                DestBlend: D3D11_BLEND_BLEND_FACTOR,
+                BlendOp: D3D11_BLEND_OP_ADD,
+                SrcBlendAlpha: D3D11_BLEND_INV_BLEND_FACTOR,
+                DestBlendAlpha: D3D11_BLEND_BLEND_FACTOR,
+                BlendOpAlpha: D3D11_BLEND_OP_ADD,
+                RenderTargetWriteMask: D3D11_COLOR_WRITE_ENABLE_ALL.0 as u8,
+            };
+            let mut blend_out = None;
+            device.CreateBlendState(&desc, Some(&mut blend_out))?;
+            blend_out.ok_or(E_POINTER)?
+        };
+
+        let glyph_shader = unsafe {
+            let bytecode = compile_pixel_shader(GLYPH_SHADER, "glyph", false)?;
+            let mut shader_out = None;
+            device.CreatePixelShader(&bytecode, None, Some(&mut shader_out))?;
+            shader_out.ok_or(E_POINTER)?
+        };
+        let glyph_constants_buffer = unsafe {
+            let buffer_desc = D3D11_BUFFER_DESC {
+                ByteWidth: std::mem::size_of::<GlyphConstants>() as u32,
+                Usage: D3D11_USAGE_DYNAMIC,
+                BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+                CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
+                MiscFlags: 0,
+                StructureByteStride: 0,
+            };
+            let mut buffer_out = None;
+            device.CreateBuffer(&buffer_desc, None, Some(&mut buffer_out))?;
+            buffer_out.ok_or(E_POINTER)?
+        };
+
+        // Create extend params buffer
+        let extend_params_buffer_desc = D3D11_BUFFER_DESC {
+            ByteWidth: std::mem::size_of::<ExtendParams>() as u32,
+            Usage: D3D11_USAGE_DYNAMIC,
+            BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+            CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
+            MiscFlags: 0,
+            StructureByteStride: 0,
+        };
+
+        let extend_params_buffer = unsafe {
+            let mut buffer_out = None;
+            device.CreateBuffer(&extend_params_buffer_desc, None, Some(&mut buffer_out))?;
+            buffer_out.ok_or(E_POINTER)?
+        };
+
+        // Create sampler states, one per address mode a shader can ask for
+        let samplers = SamplerMode::ALL
+            .iter()
+            .map(|mode| {
+                let sampler_desc = D3D11_SAMPLER_DESC {
+                    Filter: D3D11_FILTER_MIN_MAG_MIP_LINEAR,
+                    AddressU: mode.address_mode(),
+                    AddressV: mode.address_mode(),
+                    AddressW: mode.address_mode(),
+                    MipLODBias: 0.0,
+                    MaxAnisotropy: 1,
+                    ComparisonFunc: D3D11_COMPARISON_NEVER,
+                    BorderColor: [0.0; 4],
+                    MinLOD: 0.0,
+                    MaxLOD: D3D11_FLOAT32_MAX,
+                };
+
+                unsafe {
+                    let mut sampler_out = None;
+                    device.CreateSamplerState(&sampler_desc, Some(&mut sampler_out))?;
+                    sampler_out.ok_or(E_POINTER.into())
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        println!("created sampler");
+
+        // Create vertex buffer with fullscreen quad
+        let vertices = [
+            Vertex {
+                position: [-1.0, -1.0],
+                tex_coord: [0.0, 1.0],
+            },
+            Vertex {
+                position: [-1.0, 1.0],
+                tex_coord: [0.0, 0.0],
+            },
+            Vertex {
+                position: [1.0, -1.0],
+                tex_coord: [1.0, 1.0],
+            },
+            Vertex {
+                position: [1.0, 1.0],
+                tex_coord: [1.0, 0.0],
+            },
+        ];
+
+        let vertex_buffer_desc = D3D11_BUFFER_DESC {
+            ByteWidth: std::mem::size_of_val(&vertices) as u32,
+            Usage: D3D11_USAGE_IMMUTABLE,
+            BindFlags: D3D11_BIND_VERTEX_BUFFER.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+            StructureByteStride: 0,
+        };
+
+        let vertex_data = D3D11_SUBRESOURCE_DATA {
+            pSysMem: vertices.as_ptr() as *const _,
+            SysMemPitch: 0,
+            SysMemSlicePitch: 0,
+        };
+
+        let vertex_buffer = unsafe {
+            let mut buffer_out = None;
+            device.CreateBuffer(
+                &vertex_buffer_desc,
+                Some(&vertex_data),
+                Some(&mut buffer_out),
+            )?;
+            buffer_out.ok_or(E_POINTER)?
+        };
+
+        let time_buffer_desc = D3D11_BUFFER_DESC {
+            ByteWidth: std::mem::size_of::<FrameConstants>() as u32,
+            Usage: D3D11_USAGE_DYNAMIC,
+            BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+            CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
+            MiscFlags: 0,
+            StructureByteStride: 0,
+        };
+
+        let time_buffer = unsafe {
+            let mut buffer_out = None;
+            device.CreateBuffer(&time_buffer_desc, None, Some(&mut buffer_out))?;
+            buffer_out.ok_or(E_POINTER)?
+        };
+
+        Ok(CaptureState {
+            start_time: std::time::Instant::now(),
+            fixed_time: None,
+            time_mode: config.time_mode,
+            frame_time: 0.0,
+            frame_interval: FIXED_TIME_STEP,
+            last_frame_at: None,
+            device,
+            context,
+            swap_chain,
+            swap_chain_format,
+            linear,
+            swap_chain_buffers: config.swap_chain_buffers,
+            swap_effect: config.swap_effect,
+            composition,
+            dxgi_adapter,
+            duplication: None,
+            duplications: DuplicationRegistry::default(),
+            seen_serial: None,
+            seen_image_serial: None,
+            present_qpc: 0,
+            present_count: 0,
+            new_frame: false,
+            frame_serial: 0,
+            dirty_rects: None,
+            dirty_mapping: None,
+            vertex_shader,
+            pixel_shaders,
+            current_shader: 1,
+            last_effect_shader: 1,
+            compute_shader,
+            extend_params_buffer,
+            samplers,
+            vertex_buffer,
+            render_target_view: None,
+            shader_resource_view: None,
+            input_layout,
+            time_buffer,
+            selected_param: 0,
+            user_textures: HashMap::new(),
+            snapshot: None,
+            staging_texture: None,
+            extended_texture: None,
+            extended_srv: None,
+            extended_uav: None,
+            last_frame: None,
+            last_render: std::time::Instant::now(),
+            latency_mode: LatencyMode::LowLatency,
+            duplication_retry: None,
+            waiting_for_display: false,
+            had_output: false,
+            gdi_capture: None,
+            pass_targets: Vec::new(),
+            ssaa_factor: config.ssaa_factor,
+            ssaa_target: None,
+            ssaa_filter: config.ssaa_filter,
+            supersample_shader,
+            supersample_constants_buffer,
+            downsample_shader,
+            msaa_samples: config.msaa_samples,
+            msaa_target: None,
+            effect_output: None,
+            motion_blur: config.motion_blur,
+            motion_blur_accumulation: None,
+            blend_factor_state,
+            adjust_colors: false,
+            color_adjustments: COLOR_ADJUSTMENTS.map(|adjustment| adjustment.identity),
+            selected_adjustment: 0,
+            adjust_shader,
+            adjust_constants_buffer,
+            split_view: None,
+            dragging_divider: false,
+            picker: None,
+            help: false,
+            overlay_drawn: false,
+            show_latency: false,
+            latency_samples: std::collections::VecDeque::new(),
+            picker_thumbnail: None,
+            scissor_rasterizer,
+            glyph_shader,
+            glyph_constants_buffer,
+            font_srv,
+            font_layout: layout,
+            slideshow: config.slideshow,
+            slideshow_interval: config.slideshow_interval,
+            screenshot_delay: config.screenshot_delay,
+            screenshot_dir: config.screenshot_dir.clone(),
+            screenshot_countdown: None,
+            save_beep: config.save_beep,
+            save_flash: config.save_flash,
+            save_background: config.save_background,
+            source_rect: match config.source_rect {
+                Some([x, y, width, height]) => RECT {
+                    left: x,
+                    top: y,
+                    right: x + width,
+                    bottom: y + height,
+                },
+                None => client_rect_in_physical_pixels(hwnd),
+            },
+            capture_output: config.capture_output,
+            output_origin: POINT::default(),
+            output_rotation: DXGI_MODE_ROTATION_IDENTITY,
+            // The screensaver preview is too small to show anything but the whole output
+            presentation: config.display_monitor.is_some()
+                || matches!(config.screensaver, Some(ScreensaverMode::Preview(_))),
+            fixed_source: config.source_rect.is_some(),
+            test_source: config.test_source.clone(),
+            test_texture: None,
+            test_gradient: None,
+            render_scale: config.render_scale,
+            max_capture_pixels: config.max_capture_pixels,
+            capture_capped: false,
+            edge_mode: config.edge_mode,
+            edge_fill: config.edge_fill,
+            flip_h: false,
+            flip_v: false,
+            include_cursor: false,
+            clear_color: config.clear_color.unwrap_or(CLEAR_COLORS[0].1),
+            always_on_top: false,
+            paused: false,
+            frozen: false,
+            pause_makes_capturable: config.pause_makes_capturable,
+            hidden: false,
+            occluded: false,
+            close_to_tray: config.close_to_tray,
+            hwnd,
+            primary: true,
+            ipc: None,
+            screensaver: config.screensaver == Some(ScreensaverMode::Run),
+            screensaver_cursor: {
+                let mut cursor = POINT::default();
+                let _ = unsafe { GetCursorPos(&mut cursor) };
+                cursor
+            },
+            idle_timeout: config.idle_timeout,
+            idle: false,
+            frames_since_title: 0,
+            last_title_update: std::time::Instant::now(),
+            printed_tiles_constants: false,
+            #[cfg(feature = "shared-output")]
+            shared_output: config
+                .shared_output
+                .as_deref()
+                .map(shared_output::SharedOutput::new),
+        })
+    }
+
+    /// State for another window showing its own region and shader, sharing this one's device,
+    /// shaders and textures but with its own swap chain and capture
+    fn new_view(&self, hwnd: HWND) -> Result<Self> {
+        let (swap_chain, composition) = create_swap_chain(
+            &self.device,
+            &self.dxgi_adapter,
+            hwnd,
+            self.swap_chain_format,
+            self.swap_chain_buffers,
+            self.swap_effect,
+            self.composition.is_some(),
+        )?;
+
+        // Parameters are per view; history is per-pixel state of this window's output
+        let mut pixel_shaders = self.pixel_shaders.clone();
+        for config in pixel_shaders.iter_mut() {
+            match &mut config.shader_type {
+                ShaderType::History { history, .. } => *history = None,
+                ShaderType::Tiles { cells, .. } => *cells = None,
+                ShaderType::Simple(_) => {}
+            }
+        }
+
+        Ok(Self {
+            start_time: self.start_time,
+            fixed_time: self.fixed_time,
+            time_mode: self.time_mode,
+            frame_time: self.frame_time,
+            frame_interval: self.frame_interval,
+            last_frame_at: None,
+            device: self.device.clone(),
+            context: self.context.clone(),
+            swap_chain,
+            swap_chain_format: self.swap_chain_format,
+            linear: self.linear,
+            swap_chain_buffers: self.swap_chain_buffers,
+            swap_effect: self.swap_effect,
+            composition,
+            dxgi_adapter: self.dxgi_adapter.clone(),
+            duplication: None,
+            duplications: self.duplications.clone(),
+            seen_serial: None,
+            seen_image_serial: None,
+            present_qpc: 0,
+            present_count: 0,
+            new_frame: false,
+            frame_serial: 0,
+            dirty_rects: None,
+            dirty_mapping: None,
+            vertex_shader: self.vertex_shader.clone(),
+            pixel_shaders,
+            current_shader: self.current_shader,
+            last_effect_shader: self.last_effect_shader,
+            compute_shader: self.compute_shader.clone(),
+            extend_params_buffer: self.extend_params_buffer.clone(),
+            samplers: self.samplers.clone(),
+            vertex_buffer: self.vertex_buffer.clone(),
+            render_target_view: None,
+            shader_resource_view: None,
+            input_layout: self.input_layout.clone(),
+            time_buffer: self.time_buffer.clone(),
+            selected_param: 0,
+            user_textures: self.user_textures.clone(),
+            snapshot: None,
+            staging_texture: None,
+            extended_texture: None,
+            extended_srv: None,
+            extended_uav: None,
+            last_frame: None,
+            last_render: std::time::Instant::now(),
+            latency_mode: self.latency_mode,
+            duplication_retry: None,
+            waiting_for_display: false,
+            had_output: self.had_output,
+            gdi_capture: None,
+            pass_targets: Vec::new(),
+            ssaa_factor: self.ssaa_factor,
+            ssaa_target: None,
+            ssaa_filter: self.ssaa_filter,
+            supersample_shader: self.supersample_shader.clone(),
+            supersample_constants_buffer: self.supersample_constants_buffer.clone(),
+            downsample_shader: self.downsample_shader.clone(),
+            msaa_samples: self.msaa_samples,
+            msaa_target: None,
+            effect_output: None,
+            motion_blur: self.motion_blur,
+            motion_blur_accumulation: None,
+            blend_factor_state: self.blend_factor_state.clone(),
+            adjust_colors: self.adjust_colors,
+            color_adjustments: self.color_adjustments,
+            selected_adjustment: self.selected_adjustment,
+            adjust_shader: self.adjust_shader.clone(),
+            adjust_constants_buffer: self.adjust_constants_buffer.clone(),
+            split_view: None,
+            dragging_divider: false,
+            picker: None,
+            help: false,
+            overlay_drawn: false,
+            show_latency: false,
+            latency_samples: std::collections::VecDeque::new(),
+            picker_thumbnail: None,
+            scissor_rasterizer: self.scissor_rasterizer.clone(),
+            glyph_shader: self.glyph_shader.clone(),
+            glyph_constants_buffer: self.glyph_constants_buffer.clone(),
+            font_srv: self.font_srv.clone(),
+            font_layout: self.font_layout.clone(),
+            // Each view runs its own slideshow, off until toggled there
+            slideshow: false,
+            slideshow_interval: self.slideshow_interval,
+            screenshot_delay: self.screenshot_delay,
+            screenshot_dir: self.screenshot_dir.clone(),
+            screenshot_countdown: None,
+            save_beep: self.save_beep,
+            save_flash: self.save_flash,
+            save_background: self.save_background,
+            source_rect: client_rect_in_physical_pixels(hwnd),
+            capture_output: self.capture_output,
+            output_origin: POINT::default(),
+            output_rotation: DXGI_MODE_ROTATION_IDENTITY,
+            presentation: false,
+            fixed_source: false,
+            test_source: self.test_source.clone(),
+            test_texture: None,
+            test_gradient: None,
+            render_scale: self.render_scale,
+            max_capture_pixels: self.max_capture_pixels,
+            capture_capped: false,
+            edge_mode: self.edge_mode,
+            edge_fill: self.edge_fill,
+            flip_h: false,
+            flip_v: false,
+            include_cursor: false,
+            clear_color: self.clear_color,
+            always_on_top: false,
+            paused: false,
+            frozen: false,
+            pause_makes_capturable: self.pause_makes_capturable,
+            hidden: false,
+            occluded: false,
+            // Only the primary window has a tray icon to come back from
+            close_to_tray: false,
+            hwnd,
+            primary: false,
+            ipc: None,
+            screensaver: false,
+            screensaver_cursor: POINT::default(),
+            idle_timeout: self.idle_timeout,
+            idle: false,
+            frames_since_title: 0,
+            last_title_update: std::time::Instant::now(),
+            printed_tiles_constants: self.printed_tiles_constants,
+            // A shared texture name can only be published once
+            #[cfg(feature = "shared-output")]
+            shared_output: None,
+        })
+    }
+
+    /// Format of the texture the captured region is copied into. Copies between the plain
+    /// and sRGB variants reinterpret the bytes, so sampling an sRGB copy decodes it.
+    fn source_format(&self) -> DXGI_FORMAT {
+        if self.linear {
+            DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+        } else {
+            DXGI_FORMAT_B8G8R8A8_UNORM
+        }
+    }
+
+    /// Format of the extend pass's output. Unordered access views can't encode sRGB, so
+    /// linear light is kept in half floats rather than losing precision in the darks.
+    fn extended_format(&self) -> DXGI_FORMAT {
+        if self.linear {
+            DXGI_FORMAT_R16G16B16A16_FLOAT
+        } else {
+            DXGI_FORMAT_B8G8R8A8_UNORM
+        }
+    }
+
+    /// Whether the shader output holds premultiplied alpha, as composition expects of a
+    /// transparent swap chain, rather than the straight alpha PNGs store
+    fn premultiplied_output(&self) -> bool {
+        self.composition.is_some()
+    }
+
+    /// Format of intermediate pass and supersampling targets
+    fn pass_format(&self) -> DXGI_FORMAT {
+        if self.linear {
+            DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+        } else {
+            self.swap_chain_format
+        }
+    }
+
+    /// Seconds since startup as shaders see it, unless time is held still
+    fn shader_time(&self) -> f32 {
+        self.fixed_time.unwrap_or_else(|| match self.time_mode {
+            TimeMode::Realtime => self.start_time.elapsed().as_secs_f32(),
+            TimeMode::Smooth | TimeMode::Fixed => self.frame_time,
+        })
+    }
+
+    /// Move shader time on for a newly captured frame, as the time mode says
+    fn advance_time(&mut self) {
+        let now = std::time::Instant::now();
+        match self.time_mode {
+            TimeMode::Realtime => {}
+            TimeMode::Fixed => self.frame_time += FIXED_TIME_STEP,
+            TimeMode::Smooth => {
+                let real = self.start_time.elapsed().as_secs_f32();
+                match self.last_frame_at {
+                    Some(last) if (now - last).as_secs_f32() < MAX_SMOOTH_TIME_DRIFT => {
+                        let interval = (now - last).as_secs_f32();
+                        self.frame_interval += (interval - self.frame_interval) * TIME_SMOOTHING;
+                        self.frame_time += self.frame_interval;
+                        // Never wander far from the wall clock
+                        if (self.frame_time - real).abs() > MAX_SMOOTH_TIME_DRIFT {
+                            self.frame_time = real;
+                        }
+                    }
+                    // The first frame, or the first after a pause, picks up the wall clock
+                    _ => self.frame_time = real,
+                }
+            }
+        }
+        self.last_frame_at = Some(now);
+    }
+
+    /// When the captured image was presented, in seconds on the frame constants' clock
+    fn present_time(&self) -> f32 {
+        match self.present_age() {
+            Some(age) => (self.start_time.elapsed().as_secs_f64() - age) as f32,
+            None => 0.0,
+        }
+    }
+
+    /// Seconds since the captured image was presented on the desktop
+    fn present_age(&self) -> Option<f64> {
+        let mut now = 0;
+        let mut frequency = 0;
+        unsafe {
+            if QueryPerformanceCounter(&mut now).is_err()
+                || QueryPerformanceFrequency(&mut frequency).is_err()
+                || self.present_qpc == 0
+            {
+                return None;
+            }
+        }
+        Some((now - self.present_qpc) as f64 / frequency as f64)
+    }
+
+    pub(crate) fn shader_count(&self) -> usize {
+        self.pixel_shaders.len()
+    }
+
+    /// Switch to the shader at `index`, false if there is no such shader
+    pub(crate) fn select_shader(&mut self, index: usize) -> bool {
+        if index >= self.pixel_shaders.len() {
+            return false;
+        }
+        println!("Switched to {} shader", self.pixel_shaders[index].name);
+        self.current_shader = index;
+        self.selected_param = 0;
+        if self.pixel_shaders[index].name != "passthru" {
+            self.last_effect_shader = index;
+        }
+        true
+    }
+
+    /// Switch to passthru, or from it back to the last effect shown
+    fn toggle_passthru(&mut self) {
+        let Some(passthru) = self
+            .pixel_shaders
+            .iter()
+            .position(|shader| shader.name == "passthru")
+        else {
+            return;
+        };
+        if self.current_shader == passthru {
+            self.select_shader(self.last_effect_shader);
+        } else {
+            self.select_shader(passthru);
+        }
+    }
+}
+
+const WINDOW_CLASS: PCWSTR = w!("ScreenCaptureClass");
+
+/// Run the app until it exits, returning the exit code for runs that check something
+fn run() -> Result<std::process::ExitCode> {
+    let config = Config::from_args()?;
+    if let Some(ScreensaverMode::Configure(owner)) = config.screensaver {
+        show_screensaver_settings(&config, owner);
+        return Ok(std::process::ExitCode::SUCCESS);
+    }
+    match config.list {
+        Some(ListMode::Shaders) => {
+            list_shaders(&config);
+            return Ok(std::process::ExitCode::SUCCESS);
+        }
+        Some(ListMode::Monitors) => {
+            list_monitors()?;
+            return Ok(std::process::ExitCode::SUCCESS);
+        }
+        None => {}
+    }
+
+    // Compile the embedded pixel shaders on worker threads while the window and device come up
+    let shaders = EmbeddedShaders::spawn(config.shader_dir.as_deref());
+
+    unsafe {
+        // Enable DPI awareness for proper scaling
+        // Ignore errors if DPI awareness is already set
+        _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+    }
+    // Declared first so COM outlives every interface created below
+    let _com = ComInitScope::new()?;
+
+    let window_class = WINDOW_CLASS;
+    let hinstance: HINSTANCE = unsafe { GetModuleHandleW(None) }?.into();
+
+    let wc = WNDCLASSEXW {
+        cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+        style: CS_HREDRAW | CS_VREDRAW,
+        lpfnWndProc: Some(wndproc),
+        hInstance: hinstance,
+        lpszClassName: window_class,
+        ..Default::default()
+    };
+
+    unsafe {
+        RegisterClassExW(&wc);
+    }
+
+    let preview_parent = match config.screensaver {
+        Some(ScreensaverMode::Preview(parent)) => Some(HWND(parent as *mut _)),
+        _ => None,
+    };
+
+    // In presentation mode the window is borderless and covers the display monitor
+    let (style, x, y, window_width, window_height) = match (preview_parent, config.display_monitor)
+    {
+        // The screensaver preview fills the little monitor in the settings dialog
+        (Some(parent), _) => {
+            let mut rect = RECT::default();
+            unsafe { GetClientRect(parent, &mut rect)? };
+            (WS_CHILD, 0, 0, rect.right, rect.bottom)
+        }
+        (None, Some(index)) => {
+            let monitors = monitor_rects();
+            let Some(rect) = monitors.get(index as usize) else {
+                return Err(Error::new(
+                    E_INVALIDARG,
+                    format!(
+                        "Display monitor {} not found ({} available)",
+                        index,
+                        monitors.len()
+                    ),
+                ));
+            };
+            (
+                WS_POPUP,
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+            )
+        }
+        (None, None) => (WS_OVERLAPPEDWINDOW, CW_USEDEFAULT, CW_USEDEFAULT, 1280, 720),
+    };
+
+    // Composition content replaces the window's own surface, which would otherwise sit behind
+    // it and hide the desktop
+    let mut ex_style = if config.transparent {
+        WS_EX_NOREDIRECTIONBITMAP
+    } else {
+        Default::default()
+    };
+    if config.screensaver == Some(ScreensaverMode::Run) {
+        ex_style |= WS_EX_TOPMOST;
+    }
+    let hwnd = unsafe {
+        CreateWindowExW(
+            ex_style,
+            window_class,
+            w!("Screen Capture"),
+            style,
+            x,
+            y,
+            window_width,
+            window_height,
+            preview_parent,
+            None,
+            Some(hinstance),
+            None,
+        )?
+    };
+    println!("created window");
+
+    // Only top-level windows can be left out of capture, so the preview sees the dialog around it
+    if preview_parent.is_none() {
+        unsafe {
+            SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE)?;
+        }
+    }
+    if config.screensaver == Some(ScreensaverMode::Run) {
+        unsafe { ShowCursor(false) };
+    }
+
+    let mut capture_state = CaptureState::new(hwnd, &config, shaders)?;
+    println!("created capture state");
+    // Rendered offscreen, before saved parameters are restored, and the window never shows
+    if let Some(golden) = &config.golden {
+        let passed = run_golden(&mut capture_state, &config, golden)?;
+        return Ok(if passed {
+            std::process::ExitCode::SUCCESS
+        } else {
+            std::process::ExitCode::from(EXIT_GOLDEN_MISMATCH)
+        });
+    }
+    restore_saved_state(&mut capture_state);
+    // Screensavers have no use for hotkeys
+    if config.screensaver.is_some() {
+        capture_state.help = false;
+    }
+    println!(
+        "Current shader: {} (press 1 - {} to switch)",
+        capture_state.pixel_shaders[capture_state.current_shader].name,
+        capture_state.pixel_shaders.len(),
+    );
+    if capture_state.slideshow {
+        restart_slideshow_timer(&capture_state);
+    }
+    if let Some(port) = config.ipc_port {
+        match ipc::start(hwnd, port) {
+            Ok(listener) => capture_state.ipc = Some(listener),
+            Err(e) => println!("Failed to listen for commands on port {}: {}", port, e),
+        }
+    }
+
+    unsafe {
+        SetWindowLongPtrW(
+            hwnd,
+            GWLP_USERDATA,
+            Box::into_raw(Box::new(capture_state)) as isize,
+        );
+
+        let _ = ShowWindow(hwnd, SW_SHOW);
+        let _ = UpdateWindow(hwnd);
+    }
+
+    if config.screensaver.is_none()
+        && let Err(e) = add_tray_icon(hwnd)
+    {
+        println!("Failed to add tray icon: {:?}", e);
+    }
+
+    let haccel = create_accelerators()?;
+
+    let mut message = MSG::default();
+    loop {
+        unsafe {
+            let status = GetMessageW(&mut message, None, 0, 0);
+            if status.0 == 0 {
+                break;
+            }
+            if status.0 == -1 {
+                println!("GetMessageW failed with -1");
+                break;
+            }
+
+            // Hotkeys go to whichever view has focus
+            if TranslateAcceleratorW(message.hwnd, *haccel, &message) != 0 {
+                continue;
+            }
+
+            _ = TranslateMessage(&message);
+            _ = DispatchMessageW(&message);
+        }
+    }
+
+    // Every window's state went with it, COM goes with _com
+    Ok(std::process::ExitCode::SUCCESS)
+}
+
+/// Release what a destroyed window's state holds beyond its own fields: its timers, and for
+/// the primary the command endpoint, the saved state and the tray icon. Its GPU objects,
+/// shared duplication and capture resources are released when the state drops at the end.
+fn tear_down(mut state: Box<CaptureState>) {
+    // Before the window goes, so commands still queued for it are freed rather than dropped
+    state.ipc = None;
+    for timer in [
+        SCREENSHOT_TIMER_ID,
+        FLASH_TIMER_ID,
+        SLIDESHOW_TIMER_ID,
+        FRAME_PACING_TIMER_ID,
+        IDLE_POLL_TIMER_ID,
+    ] {
+        let _ = unsafe { KillTimer(Some(state.hwnd), timer) };
+    }
+    if state.primary {
+        save_state(&state);
+        remove_tray_icon(state.hwnd);
+    }
+}
+
+/// Close every window and exit, even when closing would only hide to the tray. Posted, so
+/// the windows aren't destroyed while the caller is still using their state.
+fn quit(state: &CaptureState) {
+    // Destroying the primary takes the other views with it
+    let primary = if state.primary {
+        state.hwnd
+    } else {
+        unsafe { GetWindow(state.hwnd, GW_OWNER) }.unwrap_or(state.hwnd)
+    };
+    let _ = unsafe { PostMessageW(Some(primary), WM_EXIT, WPARAM(0), LPARAM(0)) };
+}
+
+/// Return to the shader showing on the last exit, and give the shaders' parameters the values
+/// saved then, matched by name, so a shader whose parameters changed since keeps the ones
+/// that still exist
+fn restore_saved_state(state: &mut CaptureState) {
+    let saved = saved_state::load();
+    // Introduce the hotkeys on the first run
+    state.help = !saved.seen_help;
+    if let Some(name) = &saved.shader {
+        match state
+            .pixel_shaders
+            .iter()
+            .position(|shader| &shader.name == name)
+        {
+            Some(index) => {
+                state.current_shader = index;
+                if name != "passthru" {
+                    state.last_effect_shader = index;
+                }
+            }
+            None => println!("Last used shader {} is no longer available", name),
+        }
+    }
+    for shader in state.pixel_shaders.iter_mut() {
+        let Some(values) = saved.params.get(&shader.name) else {
+            continue;
+        };
+        for param in shader.params.iter_mut() {
+            if let Some(&value) = values.get(&param.name) {
+                param.value = value.clamp(param.min, param.max);
+            }
+        }
+    }
+}
+
+/// Record the primary window's shader and parameter values, keeping any saved for shaders
+/// that aren't loaded this run
+fn save_state(state: &CaptureState) {
+    let mut saved = saved_state::load();
+    saved.shader = Some(state.pixel_shaders[state.current_shader].name.clone());
+    saved.seen_help = true;
+    for shader in state
+        .pixel_shaders
+        .iter()
+        .filter(|shader| !shader.params.is_empty())
+    {
+        saved.params.insert(
+            shader.name.clone(),
+            shader
+                .params
+                .iter()
+                .map(|param| (param.name.clone(), param.value))
+                .collect(),
+        );
+    }
+    saved_state::save(&saved);
+}
+
+/// How far the cursor can drift, in pixels, before the screensaver closes. Windows sends a
+/// mouse move as the window appears, and mice jitter.
+const SCREENSAVER_MOUSE_SLOP: i32 = 8;
+
+/// Whether a keyboard, mouse or activation message should close the screensaver
+fn ends_screensaver(state: &CaptureState, message: u32, wparam: WPARAM) -> bool {
+    match message {
+        WM_MOUSEMOVE => {
+            let mut cursor = POINT::default();
+            let _ = unsafe { GetCursorPos(&mut cursor) };
+            (cursor.x - state.screensaver_cursor.x).abs() > SCREENSAVER_MOUSE_SLOP
+                || (cursor.y - state.screensaver_cursor.y).abs() > SCREENSAVER_MOUSE_SLOP
+        }
+        // Another app took the foreground
+        WM_ACTIVATEAPP => wparam.0 == 0,
+        _ => true,
+    }
+}
+
+/// Move the split view divider with a left-button drag anywhere in the window, returning
+/// whether the message was part of one
+fn drag_divider(state: &mut CaptureState, message: u32, lparam: LPARAM) -> bool {
+    match message {
+        WM_LBUTTONDOWN => {
+            state.dragging_divider = true;
+            // Keep following the drag outside the window
+            unsafe { SetCapture(state.hwnd) };
+        }
+        WM_MOUSEMOVE if state.dragging_divider => {}
+        WM_LBUTTONUP if state.dragging_divider => {
+            state.dragging_divider = false;
+            let _ = unsafe { ReleaseCapture() };
+        }
+        _ => return false,
+    }
+
+    let mut client_rect = RECT::default();
+    let _ = unsafe { GetClientRect(state.hwnd, &mut client_rect) };
+    let x = (lparam.0 & 0xFFFF) as i16 as f32;
+    let width = (client_rect.right - client_rect.left).max(1) as f32;
+    state.split_view = Some((x / width).clamp(0.0, 1.0));
+    true
+}
+
+/// Draw the raw capture over the part of the window left of the split view divider, and the
+/// divider itself
+fn draw_split_view(
+    state: &CaptureState,
+    rtv: &ID3D11RenderTargetView,
+    source_srv: &ID3D11ShaderResourceView,
+    divider: f32,
+    target_width: u32,
+    target_height: u32,
+) -> Result<()> {
+    let divider_x = (divider * target_width as f32).round() as i32;
+    unsafe {
+        state
+            .context
+            .OMSetRenderTargets(Some(&[Some(rtv.clone())]), None);
+        state.context.RSSetViewports(Some(&[D3D11_VIEWPORT {
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+            Width: target_width as f32,
+            Height: target_height as f32,
+            MinDepth: 0.0,
+            MaxDepth: 1.0,
+        }]));
+        state.context.RSSetState(&state.scissor_rasterizer);
+        state.context.RSSetScissorRects(Some(&[RECT {
+            left: 0,
+            top: 0,
+            right: divider_x,
+            bottom: target_height as i32,
+        }]));
+
+        // The downsample shader is a plain filtered copy, which is all the raw side needs
+        state.context.PSSetShader(&state.downsample_shader, None);
+        state.context.PSSetSamplers(
+            0,
+            Some(&[Some(state.samplers[SamplerMode::Clamp as usize].clone())]),
+        );
+        state
+            .context
+            .PSSetShaderResources(0, Some(&[Some(source_srv.clone())]));
+        state.context.Draw(4, 0);
+        state.context.PSSetShaderResources(0, Some(&[None]));
+        state.context.RSSetState(None);
+
+        let context1: ID3D11DeviceContext1 = state.context.cast()?;
+        context1.ClearView(
+            rtv,
+            &[1.0, 1.0, 1.0, 1.0],
+            Some(&[RECT {
+                left: (divider_x - SPLIT_DIVIDER_WIDTH / 2).max(0),
+                top: 0,
+                right: divider_x + SPLIT_DIVIDER_WIDTH / 2,
+                bottom: target_height as i32,
+            }]),
+        );
+    }
+    Ok(())
+}
+
+const SPLIT_DIVIDER_WIDTH: i32 = 2;
+
+// Space around the picker's thumbnails, and the outline around the highlighted one
+const PICKER_MARGIN: u32 = 12;
+const PICKER_OUTLINE_WIDTH: i32 = 3;
+
+/// The shader picker's grid: a cell per shader, in as close to a square as fits them
+struct PickerGrid {
+    columns: u32,
+    cell_width: u32,
+    cell_height: u32,
+}
+
+impl PickerGrid {
+    fn new(shader_count: usize, target_width: u32, target_height: u32) -> Self {
+        let count = shader_count.max(1) as u32;
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+        Self {
+            columns,
+            cell_width: (target_width.saturating_sub(PICKER_MARGIN * (columns + 1)) / columns)
+                .max(1),
+            cell_height: (target_height.saturating_sub(PICKER_MARGIN * (rows + 1)) / rows).max(1),
+        }
+    }
+
+    fn cell_rect(&self, index: usize) -> RECT {
+        let column = index as u32 % self.columns;
+        let row = index as u32 / self.columns;
+        let left = (PICKER_MARGIN + column * (self.cell_width + PICKER_MARGIN)) as i32;
+        let top = (PICKER_MARGIN + row * (self.cell_height + PICKER_MARGIN)) as i32;
+        RECT {
+            left,
+            top,
+            right: left + self.cell_width as i32,
+            bottom: top + self.cell_height as i32,
+        }
+    }
+
+    /// The cell under a point in the window, if any
+    fn cell_at(&self, x: i32, y: i32, shader_count: usize) -> Option<usize> {
+        (0..shader_count).find(|&index| {
+            let rect = self.cell_rect(index);
+            x >= rect.left && x < rect.right && y >= rect.top && y < rect.bottom
+        })
+    }
+}
+
+/// Open the shader picker on the current shader, or close it
+fn toggle_picker(state: &mut CaptureState) {
+    state.picker = match state.picker {
+        Some(_) => None,
+        None => Some(state.current_shader),
+    };
+    // Thumbnails render at the grid's cell size, the effect at the window's
+    release_shader_targets(state);
+    println!(
+        "Shader picker: {}",
+        if state.picker.is_some() {
+            "open, pick with the arrow keys and Enter or a click"
+        } else {
+            "closed"
+        }
+    );
+}
+
+/// Move around the open shader picker with the arrow keys or the mouse and pick with Enter
+/// or a click, returning whether the message was for the picker
+fn picker_input(state: &mut CaptureState, message: u32, wparam: WPARAM, lparam: LPARAM) -> bool {
+    let Some(highlighted) = state.picker else {
+        return false;
+    };
+    let count = state.shader_count();
+    let mut client_rect = RECT::default();
+    let _ = unsafe { GetClientRect(state.hwnd, &mut client_rect) };
+    let grid = PickerGrid::new(
+        count,
+        (client_rect.right - client_rect.left) as u32,
+        (client_rect.bottom - client_rect.top) as u32,
+    );
+    let columns = grid.columns as usize;
+    let x = (lparam.0 & 0xFFFF) as i16 as i32;
+    let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+
+    match message {
+        WM_KEYDOWN => {
+            let key = VIRTUAL_KEY(wparam.0 as u16);
+            let moved = match key {
+                VK_LEFT => highlighted.checked_sub(1),
+                VK_RIGHT => Some(highlighted + 1),
+                VK_UP => highlighted.checked_sub(columns),
+                VK_DOWN => Some(highlighted + columns),
+                VK_RETURN => {
+                    state.select_shader(highlighted);
+                    toggle_picker(state);
+                    return true;
+                }
+                VK_ESCAPE => {
+                    toggle_picker(state);
+                    return true;
+                }
+                _ => return false,
+            };
+            if let Some(index) = moved.filter(|&index| index < count) {
+                state.picker = Some(index);
+            }
+        }
+        WM_MOUSEMOVE => {
+            if let Some(index) = grid.cell_at(x, y, count) {
+                state.picker = Some(index);
+            }
+        }
+        WM_LBUTTONDOWN => {
+            if let Some(index) = grid.cell_at(x, y, count) {
+                state.select_shader(index);
+                toggle_picker(state);
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Draw every shader applied to the current frame as a grid of thumbnails, outlining the
+/// highlighted one
+fn draw_picker(
+    state: &mut CaptureState,
+    rtv: &ID3D11RenderTargetView,
+    frame: &CapturedFrame,
+    highlighted: usize,
+    target_width: u32,
+    target_height: u32,
+) -> Result<()> {
+    let grid = PickerGrid::new(state.shader_count(), target_width, target_height);
+    if state.picker_thumbnail.is_none() {
+        state.picker_thumbnail = Some(create_pass_target(
+            &state.device,
+            grid.cell_width,
+            grid.cell_height,
+            state.pass_format(),
+        )?);
+    }
+    let thumbnail = state.picker_thumbnail.as_ref().unwrap();
+    let (thumbnail_rtv, thumbnail_srv) = (thumbnail.rtv.clone(), thumbnail.srv.clone());
+
+    unsafe {
+        state
+            .context
+            .ClearRenderTargetView(rtv, &[0.0, 0.0, 0.0, 1.0]);
+        for index in 0..state.shader_count() {
+            draw_shader(
+                state,
+                index,
+                frame,
+                &thumbnail_rtv,
+                grid.cell_width,
+                grid.cell_height,
+            )?;
+
+            // Then copy it into its cell
+            let cell = grid.cell_rect(index);
+            state
+                .context
+                .OMSetRenderTargets(Some(&[Some(rtv.clone())]), None);
+            state.context.RSSetViewports(Some(&[D3D11_VIEWPORT {
+                TopLeftX: cell.left as f32,
+                TopLeftY: cell.top as f32,
+                Width: grid.cell_width as f32,
+                Height: grid.cell_height as f32,
+                MinDepth: 0.0,
+                MaxDepth: 1.0,
+            }]));
+            state.context.PSSetShader(&state.downsample_shader, None);
+            state.context.PSSetSamplers(
+                0,
+                Some(&[Some(state.samplers[SamplerMode::Clamp as usize].clone())]),
+            );
+            state
+                .context
+                .PSSetShaderResources(0, Some(&[Some(thumbnail_srv.clone())]));
+            state.context.Draw(4, 0);
+            // Unbind so the next shader can render into it
+            state.context.PSSetShaderResources(0, Some(&[None]));
+        }
+
+        let cell = grid.cell_rect(highlighted);
+        let outer = RECT {
+            left: (cell.left - PICKER_OUTLINE_WIDTH).max(0),
+            top: (cell.top - PICKER_OUTLINE_WIDTH).max(0),
+            right: cell.right + PICKER_OUTLINE_WIDTH,
+            bottom: cell.bottom + PICKER_OUTLINE_WIDTH,
+        };
+        let context1: ID3D11DeviceContext1 = state.context.cast()?;
+        context1.ClearView(
+            rtv,
+            &[1.0, 1.0, 1.0, 1.0],
+            Some(&[
+                RECT {
+                    bottom: cell.top,
+                    ..outer
+                },
+                RECT {
+                    top: cell.bottom,
+                    ..outer
+                },
+                RECT {
+                    right: cell.left,
+                    ..outer
+                },
+                RECT {
+                    left: cell.right,
+                    ..outer
+                },
+            ]),
+        );
+    }
+    Ok(())
+}
+
+/// The `/c` screensaver settings, which only describe what the screensaver does for now
+fn show_screensaver_settings(config: &Config, owner: Option<isize>) {
+    let text = format!(
+        "Scrim Shady shows the desktop through each of its shaders in turn, moving to the \
+         next every {} seconds.\n\nThere are no screensaver settings to change.",
+        config.slideshow_interval
+    );
+    let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        MessageBoxW(
+            owner.map(|owner| HWND(owner as *mut _)),
+            PCWSTR(text_wide.as_ptr()),
+            w!("Scrim Shady"),
+            MB_OK | MB_ICONINFORMATION,
+        );
+    }
+}
+
+const ID_SAVE: u16 = 1001;
+const ID_ALWAYS_ON_TOP: u16 = 1002;
+const ID_TOGGLE_PAUSE: u16 = 1003;
+const ID_CYCLE_RENDER_SCALE: u16 = 1004;
+const ID_PARAM_DECREASE: u16 = 1005;
+const ID_PARAM_INCREASE: u16 = 1006;
+const ID_PARAM_NEXT: u16 = 1007;
+const ID_PARAM_RESET: u16 = 1008;
+const ID_PARAM_RESET_ALL: u16 = 1009;
+const ID_CYCLE_TILES_CURVE: u16 = 1010;
+const ID_CYCLE_CLEAR_COLOR: u16 = 1011;
+const ID_CYCLE_LATENCY_MODE: u16 = 1012;
+const ID_FLIP_HORIZONTAL: u16 = 1013;
+const ID_FLIP_VERTICAL: u16 = 1014;
+const ID_TOGGLE_SSAA: u16 = 1015;
+const ID_SELECT_REGION: u16 = 1016;
+const ID_DELAYED_SAVE: u16 = 1017;
+const ID_NEW_VIEW: u16 = 1018;
+const ID_TOGGLE_SLIDESHOW: u16 = 1019;
+const ID_TOGGLE_CURSOR: u16 = 1020;
+const ID_TOGGLE_SPLIT_VIEW: u16 = 1021;
+const ID_TOGGLE_PASSTHRU: u16 = 1022;
+const ID_CYCLE_EDGE_MODE: u16 = 1023;
+const ID_FREEZE_SNAPSHOT: u16 = 1024;
+const ID_DUMP_BYTECODE: u16 = 1025;
+const ID_TOGGLE_PICKER: u16 = 1026;
+const ID_QUIT: u16 = 1027;
+const ID_CYCLE_MOTION_BLUR: u16 = 1028;
+const ID_TOGGLE_ADJUST: u16 = 1029;
+const ID_ADJUST_NEXT: u16 = 1030;
+const ID_ADJUST_DECREASE: u16 = 1031;
+const ID_ADJUST_INCREASE: u16 = 1032;
+const ID_ADJUST_RESET: u16 = 1033;
+const ID_OPEN_SCREENSHOT_DIR: u16 = 1034;
+const ID_TOGGLE_HELP: u16 = 1035;
+const ID_CYCLE_TILES_OPACITY: u16 = 1036;
+const ID_SAVE_TILES_TEXT: u16 = 1037;
+const ID_TOGGLE_LATENCY: u16 = 1038;
+const ID_DUMP_TEXTURES: u16 = 1039;
+const ID_TOGGLE_FREEZE: u16 = 1040;
+const ID_SHADER_BASE: u16 = 2000;
+const ID_SHADER_END: u16 = ID_SHADER_BASE + 10;
+
+/// A key binding, and what it does for the help overlay
+struct Hotkey {
+    ctrl: bool,
+    shift: bool,
+    key: u16,
+    command: u16,
+    action: &'static str,
+}
+
+impl Hotkey {
+    const fn plain(key: u16, command: u16, action: &'static str) -> Self {
+        Self {
+            ctrl: false,
+            shift: false,
+            key,
+            command,
+            action,
+        }
+    }
+
+    const fn ctrl(key: u16, command: u16, action: &'static str) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::plain(key, command, action)
+        }
+    }
+
+    const fn ctrl_shift(key: u16, command: u16, action: &'static str) -> Self {
+        Self {
+            ctrl: true,
+            shift: true,
+            ..Self::plain(key, command, action)
+        }
+    }
+
+    /// How the key is written, such as `Ctrl+Shift+S`
+    fn name(&self) -> String {
+        let key = match self.key {
+            0x08 => "Backspace".to_string(),
+            0x09 => "Tab".to_string(),
+            0x13 => "Pause".to_string(),
+            0x70 => "F1".to_string(),
+            0xC0 => "`".to_string(),
+            0xDB => "[".to_string(),
+            0xDC => "\\".to_string(),
+            0xDD => "]".to_string(),
+            key => char::from(key as u8).to_string(),
+        };
+        format!(
+            "{}{}{}",
+            if self.ctrl { "Ctrl+" } else { "" },
+            if self.shift { "Shift+" } else { "" },
+            key
+        )
+    }
+}
+
+/// Every key binding besides the number keys selecting shaders, in the order the help
+/// overlay lists them
+const HOTKEYS: [Hotkey; 40] = [
+    Hotkey::plain(0x70, ID_TOGGLE_HELP, "Show or hide this help"), // VK_F1
+    Hotkey::plain(
+        0xC0,
+        ID_TOGGLE_PASSTHRU,
+        "Toggle between the effect and passthru",
+    ), // VK_OEM_3 '`'
+    Hotkey::plain(0x09, ID_TOGGLE_PICKER, "Open the shader picker"), // VK_TAB
+    Hotkey::ctrl(b'T' as u16, ID_TOGGLE_SLIDESHOW, "Toggle the slideshow"),
+    Hotkey::plain(0xDB, ID_PARAM_DECREASE, "Decrease the selected parameter"), // VK_OEM_4 '['
+    Hotkey::plain(0xDD, ID_PARAM_INCREASE, "Increase the selected parameter"), // VK_OEM_6 ']'
+    Hotkey::plain(0xDC, ID_PARAM_NEXT, "Select the next parameter"),           // VK_OEM_5 '\'
+    Hotkey::plain(0x08, ID_PARAM_RESET, "Reset the shader's parameters"),      // VK_BACK
+    Hotkey::ctrl(0x08, ID_PARAM_RESET_ALL, "Reset every shader's parameters"), // VK_BACK
+    Hotkey::ctrl(
+        b'G' as u16,
+        ID_CYCLE_TILES_CURVE,
+        "Cycle the tiles brightness curve",
+    ),
+    Hotkey::ctrl_shift(
+        b'G' as u16,
+        ID_CYCLE_TILES_OPACITY,
+        "Cycle the tiles overlay opacity",
+    ),
+    Hotkey::ctrl(b'N' as u16, ID_NEW_VIEW, "Open another window"),
+    Hotkey::ctrl(b'A' as u16, ID_ALWAYS_ON_TOP, "Toggle always on top"),
+    Hotkey::ctrl(b'F' as u16, ID_SELECT_REGION, "Select a region to capture"),
+    Hotkey::plain(0x13, ID_TOGGLE_PAUSE, "Pause and make capturable"), // VK_PAUSE
+    Hotkey::ctrl(b'Q' as u16, ID_QUIT, "Exit"),
+    Hotkey::ctrl(
+        b'H' as u16,
+        ID_FLIP_HORIZONTAL,
+        "Mirror the source horizontally",
+    ),
+    Hotkey::ctrl(
+        b'V' as u16,
+        ID_FLIP_VERTICAL,
+        "Mirror the source vertically",
+    ),
+    Hotkey::ctrl(b'K' as u16, ID_CYCLE_CLEAR_COLOR, "Cycle the clear color"),
+    Hotkey::ctrl(b'E' as u16, ID_CYCLE_EDGE_MODE, "Cycle the edge padding"),
+    Hotkey::ctrl(
+        b'B' as u16,
+        ID_TOGGLE_SPLIT_VIEW,
+        "Toggle the before/after split view",
+    ),
+    Hotkey::ctrl(b'J' as u16, ID_TOGGLE_ADJUST, "Toggle color adjustment"),
+    Hotkey::ctrl(0xDC, ID_ADJUST_NEXT, "Select the next color adjustment"), // VK_OEM_5 '\'
+    Hotkey::ctrl(0xDB, ID_ADJUST_DECREASE, "Decrease the color adjustment"), // VK_OEM_4 '['
+    Hotkey::ctrl(0xDD, ID_ADJUST_INCREASE, "Increase the color adjustment"), // VK_OEM_6 ']'
+    Hotkey::ctrl_shift(b'J' as u16, ID_ADJUST_RESET, "Reset the color adjustments"),
+    Hotkey::ctrl(b'O' as u16, ID_CYCLE_MOTION_BLUR, "Cycle motion blur"),
+    Hotkey::ctrl(b'P' as u16, ID_TOGGLE_CURSOR, "Toggle capturing the cursor"),
+    Hotkey::ctrl(b'R' as u16, ID_CYCLE_RENDER_SCALE, "Cycle the render scale"),
+    Hotkey::ctrl(b'M' as u16, ID_TOGGLE_SSAA, "Cycle supersampling"),
+    Hotkey::ctrl(b'L' as u16, ID_CYCLE_LATENCY_MODE, "Cycle the latency mode"),
+    Hotkey::ctrl_shift(b'L' as u16, ID_TOGGLE_LATENCY, "Show or hide the latency"),
+    Hotkey::ctrl(b'S' as u16, ID_SAVE, "Save a screenshot"),
+    Hotkey::ctrl_shift(
+        b'S' as u16,
+        ID_OPEN_SCREENSHOT_DIR,
+        "Open the screenshot directory",
+    ),
+    Hotkey::ctrl_shift(b'T' as u16, ID_SAVE_TILES_TEXT, "Save the tiles as text"),
+    Hotkey::ctrl(
+        b'D' as u16,
+        ID_DELAYED_SAVE,
+        "Save a screenshot after a countdown",
+    ),
+    Hotkey::ctrl(b'U' as u16, ID_DUMP_BYTECODE, "Dump the shader's bytecode"),
+    Hotkey::ctrl_shift(
+        b'U' as u16,
+        ID_DUMP_TEXTURES,
+        "Dump the pipeline's textures",
+    ),
+    Hotkey::ctrl(b'Z' as u16, ID_FREEZE_SNAPSHOT, "Freeze the snapshot"),
+    Hotkey::ctrl_shift(
+        b'Z' as u16,
+        ID_TOGGLE_FREEZE,
+        "Freeze the source, keep shading",
+    ),
+];
+
+fn create_accelerators() -> Result<Owned<HACCEL>> {
+    let hotkeys = HOTKEYS.iter().map(|hotkey| ACCEL {
+        fVirt: FVIRTKEY
+            | if hotkey.ctrl {
+                FCONTROL
+            } else {
+                ACCEL_VIRT_FLAGS(0)
+            }
+            | if hotkey.shift {
+                FSHIFT
+            } else {
+                ACCEL_VIRT_FLAGS(0)
+            },
+        key: hotkey.key,
+        cmd: hotkey.command,
+    });
+    // 1-9 pick the shaders in order
+    let shader_keys = (0..9).map(|index| ACCEL {
+        fVirt: FVIRTKEY,
+        key: (b'1' + index) as u16,
+        cmd: ID_SHADER_BASE + index as u16,
+    });
+    let accels: Vec<ACCEL> = hotkeys.chain(shader_keys).collect();
+
+    unsafe { CreateAcceleratorTableW(&accels).map(|h| Owned::new(h)) }
+}
+
+extern "system" fn wndproc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    // A panic can't unwind out of a window procedure, so stop it here, note what the window
+    // was doing, and carry on with the next message
+    let handled = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        handle_message(hwnd, message, wparam, lparam)
+    }));
+    handled.unwrap_or_else(|_| {
+        let state_ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *const CaptureState;
+        let context = match unsafe { state_ptr.as_ref() } {
+            Some(state) => crash_summary(state),
+            None => "no capture state".to_string(),
+        };
+        crash_log::append(&format!(
+            "  while handling message 0x{:04X}: {}",
+            message, context
+        ));
+        LRESULT(0)
+    })
+}
+
+/// One line describing what a window was rendering, for the crash log
+fn crash_summary(state: &CaptureState) -> String {
+    let rect = state.source_rect;
+    format!(
+        "shader {}, source {},{} {}x{}{}, render scale {}, {}x supersampling, {}{}",
+        state.pixel_shaders[state.current_shader].name,
+        rect.left,
+        rect.top,
+        rect.right - rect.left,
+        rect.bottom - rect.top,
+        if state.fixed_source { " (fixed)" } else { "" },
+        state.render_scale,
+        state.ssaa_factor,
+        if state.duplication.is_some() {
+            "duplicating"
+        } else if state.gdi_capture.is_some() {
+            "GDI capture"
+        } else {
+            "not duplicating"
+        },
+        if state.paused { ", paused" } else { "" },
+    )
+}
+
+fn handle_message(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        match message {
+            WM_DESTROY => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if state_ptr.is_null() {
+                    remove_tray_icon(hwnd);
+                    PostQuitMessage(0);
+                } else {
+                    // Owned views are destroyed before the primary, so it always goes last
+                    SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+                    let state = Box::from_raw(state_ptr);
+                    let primary = state.primary;
+                    tear_down(state);
+                    if primary {
+                        PostQuitMessage(0);
+                    }
+                }
+                LRESULT(0)
+            }
+            WM_CLOSE => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() && (*state_ptr).close_to_tray {
+                    hide_to_tray(&mut *state_ptr);
+                    LRESULT(0)
+                } else {
+                    DefWindowProcW(hwnd, message, wparam, lparam)
+                }
+            }
+            WM_SYSCOMMAND if (wparam.0 & 0xFFF0) as u32 == SC_MINIMIZE => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() && (*state_ptr).primary {
+                    hide_to_tray(&mut *state_ptr);
+                    LRESULT(0)
+                } else {
+                    DefWindowProcW(hwnd, message, wparam, lparam)
+                }
+            }
+            WM_KEYDOWN | WM_SYSKEYDOWN | WM_LBUTTONDOWN | WM_LBUTTONUP | WM_RBUTTONDOWN
+            | WM_MBUTTONDOWN | WM_MOUSEMOVE | WM_ACTIVATEAPP => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if state_ptr.is_null() {
+                    return DefWindowProcW(hwnd, message, wparam, lparam);
+                }
+                let state = &mut *state_ptr;
+                if state.screensaver && ends_screensaver(state, message, wparam) {
+                    println!("Input received, closing the screensaver");
+                    let _ = DestroyWindow(hwnd);
+                    LRESULT(0)
+                } else if state.help && matches!(message, WM_KEYDOWN | WM_SYSKEYDOWN) {
+                    // Keys without a binding close the help too
+                    state.help = false;
+                    LRESULT(0)
+                } else if picker_input(state, message, wparam, lparam)
+                    || (state.split_view.is_some() && drag_divider(state, message, lparam))
+                {
+                    LRESULT(0)
+                } else {
+                    DefWindowProcW(hwnd, message, wparam, lparam)
+                }
+            }
+            WM_IPC_COMMAND => {
+                let request = Box::from_raw(lparam.0 as *mut ipc::Request);
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                let reply = match state_ptr.as_mut() {
+                    Some(state) => run_ipc_command(state, request.command),
+                    None => ipc::error("window is closing".to_string()),
+                };
+                // The client may have given up waiting
+                let _ = request.reply.send(reply);
+                LRESULT(0)
+            }
+            WM_EXIT => {
+                println!("Exiting");
+                let _ = DestroyWindow(hwnd);
+                LRESULT(0)
+            }
+            WM_TRAY_ICON => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    let state = &mut *state_ptr;
+                    match (lparam.0 & 0xFFFF) as u32 {
+                        WM_LBUTTONDBLCLK => restore_from_tray(state),
+                        WM_RBUTTONUP | WM_CONTEXTMENU => show_tray_menu(state),
+                        _ => {}
+                    }
+                }
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == FLASH_TIMER_ID => {
+                let _ = KillTimer(Some(hwnd), FLASH_TIMER_ID);
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    // Put the shaded frame back in place of the flash
+                    let _ = redraw_held_frame(&mut *state_ptr);
+                }
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == FRAME_PACING_TIMER_ID || wparam.0 == IDLE_POLL_TIMER_ID => {
+                let _ = KillTimer(Some(hwnd), wparam.0);
+                let _ = InvalidateRect(Some(hwnd), None, false);
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == SLIDESHOW_TIMER_ID => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    let state = &mut *state_ptr;
+                    state.select_shader((state.current_shader + 1) % state.shader_count());
+                }
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == SCREENSHOT_TIMER_ID => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    tick_screenshot_countdown(&mut *state_ptr);
+                }
+                LRESULT(0)
+            }
+            WM_REGION_SELECTED => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    set_fixed_source(&mut *state_ptr, *(lparam.0 as *const RECT));
+                }
+                LRESULT(0)
+            }
+            WM_SIZE | WM_MOVE => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    window_changed(&mut *state_ptr, hwnd, message == WM_SIZE);
+                }
+                LRESULT(0)
+            }
+            WM_DISPLAYCHANGE => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    let state = &mut *state_ptr;
+                    println!(
+                        "Display mode changed to {}x{}",
+                        lparam.0 & 0xFFFF,
+                        (lparam.0 >> 16) & 0xFFFF
+                    );
+                    reset_capture(state);
+                    window_changed(state, hwnd, false);
+                }
+                LRESULT(0)
+            }
+            WM_DPICHANGED => {
+                // Moved onto a monitor with a different scale factor. Take the suggested
+                // rect; the resulting WM_SIZE/WM_MOVE recomputes the source rect.
+                let suggested = &*(lparam.0 as *const RECT);
+                let _ = SetWindowPos(
+                    hwnd,
+                    None,
+                    suggested.left,
+                    suggested.top,
+                    suggested.right - suggested.left,
+                    suggested.bottom - suggested.top,
+                    SWP_NOZORDER | SWP_NOACTIVATE,
+                );
+                println!(
+                    "DPI changed: {}%",
+                    GetDpiForWindow(hwnd) * 100 / USER_DEFAULT_SCREEN_DPI
+                );
+                LRESULT(0)
+            }
+            WM_SETCURSOR => {
+                // If the cursor is in the client area, set it to the arrow
+                if (lparam.0 as u32 & 0xFFFF) == HTCLIENT {
+                    SetCursor(LoadCursorW(None, IDC_ARROW).ok());
+                    LRESULT(1) // TRUE - we handled it
+                } else {
+                    // Let Windows handle non-client areas (borders, title bar, etc.)
+                    DefWindowProcW(hwnd, message, wparam, lparam)
+                }
+            }
+            WM_PAINT => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    let state = &mut *state_ptr;
+                    update_title(state);
+                    if state.hidden {
+                        // Hidden windows shouldn't get WM_PAINT, but don't render if one arrives
+                        let _ = ValidateRect(Some(hwnd), None);
+                    } else if update_idle(state) || update_occluded(state) {
+                        // Nothing to render, but keep polling so resume is immediate, on a
+                        // timer so messages aren't held up in between
+                        let _ = ValidateRect(Some(hwnd), None);
+                        SetTimer(Some(hwnd), IDLE_POLL_TIMER_ID, IDLE_POLL_MILLIS, None);
+                    } else if !state.paused {
+                        if let Some(delay) = frame_pacing_delay(state) {
+                            // Wait out the shader's frame interval without holding up
+                            // messages, the timer asks for the next paint once it has passed
+                            let _ = ValidateRect(Some(hwnd), None);
+                            let millis = u32::try_from(delay.as_millis())
+                                .unwrap_or(u32::MAX)
+                                .max(USER_TIMER_MINIMUM);
+                            SetTimer(Some(hwnd), FRAME_PACING_TIMER_ID, millis, None);
+                        } else {
+                            if let Err(e) = capture_and_render_frame(state, hwnd) {
+                                // Handle error if needed
+                                println!("error {:?}", e);
+                                // Raised on mode changes, among others
+                                if e.code() == DXGI_ERROR_ACCESS_LOST {
+                                    reset_capture(state);
+                                }
+                            }
+                            // Nothing to shade until duplication is retried, so poll for it
+                            // rather than paint again straight away
+                            if waiting_for_retry(state) {
+                                let _ = ValidateRect(Some(hwnd), None);
+                                SetTimer(Some(hwnd), IDLE_POLL_TIMER_ID, IDLE_POLL_MILLIS, None);
+                            }
+                        }
+                    }
+                }
+                LRESULT(0)
+            }
+            WM_COMMAND => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut CaptureState;
+                if !state_ptr.is_null() {
+                    let state = &mut *state_ptr;
+                    let accel_id = (wparam.0 & 0xFFFF) as u16;
+                    // Any other hotkey closes the help on its way
+                    if state.help && accel_id != ID_TOGGLE_HELP {
+                        state.help = false;
+                    }
+                    match accel_id {
+                        ID_TOGGLE_LATENCY => {
+                            state.show_latency = !state.show_latency;
+                            // Start the average afresh, so it reflects the settings now
+                            state.latency_samples.clear();
+                        }
+                        ID_TOGGLE_HELP => {
+                            state.help = !state.help;
+                            println!("Help: {}", if state.help { "shown" } else { "hidden" });
+                        }
+                        ID_SAVE_TILES_TEXT => {
+                            if let Err(e) = save_tiles_text(state) {
+                                println!("Failed to save tiles as text: {:?}", e);
+                            }
+                        }
+                        ID_SAVE => {
+                            if let Err(e) = save_frame_to_png(state) {
+                                println!("Failed to save frame: {:?}", e);
+                            }
+                        }
+                        ID_DELAYED_SAVE => start_screenshot_countdown(state),
+                        ID_OPEN_SCREENSHOT_DIR => {
+                            if let Err(e) = open_screenshot_dir(state) {
+                                println!("Failed to open the screenshot directory: {:?}", e);
+                            }
+                        }
+                        ID_NEW_VIEW => {
+                            if let Err(e) = open_view(state) {
+                                println!("Failed to open a new view: {:?}", e);
+                            }
+                        }
+                        ID_ALWAYS_ON_TOP => {
+                            if let Err(e) = toggle_always_on_top(state) {
+                                println!("Failed to toggle always on top: {:?}", e);
+                            }
+                        }
+                        ID_TOGGLE_FREEZE => {
+                            state.frozen = !state.frozen;
+                            println!("Source: {}", if state.frozen { "frozen" } else { "live" });
+                        }
+                        ID_TOGGLE_PAUSE => {
+                            if let Err(e) = toggle_pause_and_hide(state) {
+                                println!("Failed to toggle pause and hide: {:?}", e);
+                            }
+                        }
+                        ID_CYCLE_RENDER_SCALE => cycle_render_scale(state),
+                        ID_PARAM_DECREASE => adjust_param(state, -1.0),
+                        ID_PARAM_INCREASE => adjust_param(state, 1.0),
+                        ID_PARAM_NEXT => select_next_param(state),
+                        ID_PARAM_RESET => {
+                            let config = &mut state.pixel_shaders[state.current_shader];
+                            reset_params(config);
+                            println!("Reset {} parameters to defaults", config.name);
+                        }
+                        ID_PARAM_RESET_ALL => {
+                            for config in state.pixel_shaders.iter_mut() {
+                                reset_params(config);
+                            }
+                            println!("Reset all shader parameters to defaults");
+                        }
+                        ID_CYCLE_TILES_CURVE => cycle_tiles_curve(state),
+                        ID_CYCLE_TILES_OPACITY => cycle_tiles_opacity(state),
+                        ID_CYCLE_CLEAR_COLOR => cycle_clear_color(state),
+                        ID_FLIP_HORIZONTAL => {
+                            state.flip_h = !state.flip_h;
+                            println!("Horizontal flip: {}", state.flip_h);
+                        }
+                        ID_FLIP_VERTICAL => {
+                            state.flip_v = !state.flip_v;
+                            println!("Vertical flip: {}", state.flip_v);
+                        }
+                        ID_TOGGLE_SSAA => {
+                            state.ssaa_factor = state.ssaa_factor % MAX_SSAA_FACTOR + 1;
+                            println!(
+                                "Supersampling: {}x, {} filter",
+                                state.ssaa_factor,
+                                state.ssaa_filter.name()
+                            );
+                            release_shader_targets(state);
+                        }
+                        ID_SELECT_REGION => {
+                            if let Err(e) = region_select::begin(hwnd) {
+                                println!("Failed to start region selection: {:?}", e);
+                            }
+                        }
+                        ID_CYCLE_LATENCY_MODE => {
+                            state.latency_mode = state.latency_mode.next();
+                            println!(
+                                "Capture latency: {} ({}ms frame timeout)",
+                                state.latency_mode.name(),
+                                state.latency_mode.timeout_millis()
+                            );
+                        }
+                        ID_TOGGLE_SLIDESHOW => toggle_slideshow(state),
+                        ID_TOGGLE_SPLIT_VIEW => {
+                            state.split_view = match state.split_view {
+                                Some(_) => None,
+                                None => Some(0.5),
+                            };
+                            println!(
+                                "Split view: {}",
+                                if state.split_view.is_some() {
+                                    "on, drag to move the divider"
+                                } else {
+                                    "off"
+                                }
+                            );
+                        }
+                        ID_TOGGLE_PICKER => toggle_picker(state),
+                        ID_QUIT => quit(state),
+                        ID_TOGGLE_ADJUST => {
+                            state.adjust_colors = !state.adjust_colors;
+                            println!(
+                                "Color adjustment: {}",
+                                if state.adjust_colors { "on" } else { "off" }
+                            );
+                        }
+                        ID_ADJUST_NEXT => {
+                            state.selected_adjustment =
+                                (state.selected_adjustment + 1) % COLOR_ADJUSTMENTS.len();
+                            print_adjustment(state);
+                        }
+                        ID_ADJUST_DECREASE | ID_ADJUST_INCREASE => {
+                            let adjustment = &COLOR_ADJUSTMENTS[state.selected_adjustment];
+                            let step = if accel_id == ID_ADJUST_INCREASE {
+                                adjustment.step
+                            } else {
+                                -adjustment.step
+                            };
+                            let value = &mut state.color_adjustments[state.selected_adjustment];
+                            *value = (*value + step).clamp(adjustment.min, adjustment.max);
+                            // Changing a value is no use while it can't be seen
+                            state.adjust_colors = true;
+                            print_adjustment(state);
+                        }
+                        ID_ADJUST_RESET => {
+                            state.color_adjustments =
+                                COLOR_ADJUSTMENTS.map(|adjustment| adjustment.identity);
+                            println!("Color adjustments reset");
+                        }
+                        ID_CYCLE_MOTION_BLUR => {
+                            // The next stronger blur, back to off after the strongest
+                            state.motion_blur = MOTION_BLUR_STEPS
+                                .into_iter()
+                                .find(|&decay| decay > state.motion_blur)
+                                .unwrap_or(0.0);
+                            if state.motion_blur > 0.0 {
+                                println!(
+                                    "Motion blur: keeping {} of each frame",
+                                    state.motion_blur
+                                );
+                            } else {
+                                println!("Motion blur: off");
+                            }
+                            release_shader_targets(state);
+                        }
+                        ID_FREEZE_SNAPSHOT => match freeze_snapshot(state) {
+                            Ok(true) => println!("Froze the current capture as the snapshot"),
+                            Ok(false) => println!("No captured frame to freeze yet"),
+                            Err(e) => println!("Failed to freeze a snapshot: {:?}", e),
+                        },
+                        ID_DUMP_TEXTURES => {
+                            if let Err(e) = dump_pipeline_textures(state) {
+                                println!("Failed to dump textures: {:?}", e);
+                            }
+                        }
+                        ID_DUMP_BYTECODE => {
+                            let shader = &state.pixel_shaders[state.current_shader];
+                            let filename = format!("scrimshady_{}.cso", shader.name);
+                            match std::fs::write(&filename, &shader.bytecode) {
+                                Ok(()) => println!(
+                                    "Wrote {} shader bytecode to {} ({} bytes)",
+                                    shader.name,
+                                    filename,
+                                    shader.bytecode.len()
+                                ),
+                                Err(e) => println!("Failed to write {}: {}", filename, e),
+                            }
+                        }
+                        ID_CYCLE_EDGE_MODE => {
+                            state.edge_mode = state.edge_mode.next();
+                            println!("Edge padding: {}", state.edge_mode.name());
+                        }
+                        ID_TOGGLE_CURSOR => {
+                            state.include_cursor = !state.include_cursor;
+                            println!(
+                                "Cursor in capture: {}",
+                                if state.include_cursor { "on" } else { "off" }
+                            );
+                        }
+                        ID_TOGGLE_PASSTHRU => {
+                            state.toggle_passthru();
+                            if state.slideshow {
+                                restart_slideshow_timer(state);
+                            }
+                        }
+                        ID_SHADER_BASE..ID_SHADER_END => {
+                            // Number keys for shader switching
+                            let switched =
+                                state.select_shader((accel_id - ID_SHADER_BASE) as usize);
+                            if switched && state.slideshow {
+                                // Stay on the chosen shader for a full interval before moving on
+                                restart_slideshow_timer(state);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(hwnd, message, wparam, lparam),
+        }
+    }
+}
+
+fn print_adjustment(state: &CaptureState) {
+    println!(
+        "Color adjustment {}: {:.2}",
+        COLOR_ADJUSTMENTS[state.selected_adjustment].name,
+        state.color_adjustments[state.selected_adjustment]
+    );
+}
+
+/// Carry out a command from the command endpoint, returning the reply for the client
+fn run_ipc_command(state: &mut CaptureState, command: ipc::Command) -> serde_json::Value {
+    match command {
+        ipc::Command::Status => {
+            let shader = &state.pixel_shaders[state.current_shader];
+            let params: serde_json::Map<_, _> = shader
+                .params
+                .iter()
+                .map(|param| (param.name.clone(), param.value.into()))
+                .collect();
+            serde_json::json!({
+                "ok": true,
+                "shader": shader.name,
+                "shaders": state.pixel_shaders.iter().map(|shader| &shader.name).collect::<Vec<_>>(),
+                "params": params,
+                "paused": state.paused,
+                "frozen": state.frozen,
+                "always_on_top": state.always_on_top,
+            })
+        }
+        ipc::Command::SetShader { name } => {
+            let Some(index) = state
+                .pixel_shaders
+                .iter()
+                .position(|shader| shader.name == name)
+            else {
+                return ipc::error(format!("no shader named {}", name));
+            };
+            state.select_shader(index);
+            if state.slideshow {
+                restart_slideshow_timer(state);
+            }
+            ipc::ok()
+        }
+        ipc::Command::SetParam {
+            shader,
+            name,
+            value,
+        } => {
+            let index = match &shader {
+                Some(shader) => match state.pixel_shaders.iter().position(|s| &s.name == shader) {
+                    Some(index) => index,
+                    None => return ipc::error(format!("no shader named {}", shader)),
+                },
+                None => state.current_shader,
+            };
+            let config = &mut state.pixel_shaders[index];
+            let Some(param) = config.params.iter_mut().find(|param| param.name == name) else {
+                return ipc::error(format!("{} has no parameter named {}", config.name, name));
+            };
+            param.value = value.clamp(param.min, param.max);
+            println!("{} {}: {}", config.name, param.name, param.value);
+            serde_json::json!({ "ok": true, "value": param.value })
+        }
+        ipc::Command::Screenshot => match save_frame_to_png(state) {
+            Ok(()) => ipc::ok(),
+            Err(e) => ipc::error(format!("failed to save frame: {}", e.message())),
+        },
+        ipc::Command::ToggleAlwaysOnTop => match toggle_always_on_top(state) {
+            Ok(()) => serde_json::json!({ "ok": true, "always_on_top": state.always_on_top }),
+            Err(e) => ipc::error(format!("failed to toggle always on top: {}", e.message())),
+        },
+        ipc::Command::Quit => {
+            quit(state);
+            ipc::ok()
+        }
+    }
+}
+
+/// Copy the current shader input into the snapshot texture, false if nothing has been
+/// captured yet
+fn freeze_snapshot(state: &mut CaptureState) -> Result<bool> {
+    let Some(frame) = &state.last_frame else {
+        return Ok(false);
+    };
+    unsafe {
+        let source: ID3D11Texture2D = frame.srv.GetResource()?.cast()?;
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        source.GetDesc(&mut desc);
+        // Only ever read by shaders, whatever else the source was for
+        desc.Usage = D3D11_USAGE_DEFAULT;
+        desc.BindFlags = D3D11_BIND_SHADER_RESOURCE.0 as u32;
+        desc.CPUAccessFlags = 0;
+        desc.MiscFlags = 0;
+
+        let mut texture = None;
+        state
+            .device
+            .CreateTexture2D(&desc, None, Some(&mut texture))?;
+        let texture = texture.ok_or(E_POINTER)?;
+        state.context.CopyResource(&texture, &source);
+
+        let mut srv = None;
+        state
+            .device
+            .CreateShaderResourceView(&texture, None, Some(&mut srv))?;
+        state.snapshot = srv;
+    }
+    Ok(true)
+}
+
+/// Open another window with its own region and shader, sharing the device of `state`
+fn open_view(state: &CaptureState) -> Result<()> {
+    unsafe {
+        // Views all hang off the primary window, whichever view this was opened from
+        let owner = if state.primary {
+            state.hwnd
+        } else {
+            GetWindow(state.hwnd, GW_OWNER)?
+        };
+        let hinstance: HINSTANCE = GetModuleHandleW(None)?.into();
+        let ex_style = if state.composition.is_some() {
+            WS_EX_NOREDIRECTIONBITMAP
+        } else {
+            Default::default()
+        };
+        let hwnd = CreateWindowExW(
+            ex_style,
+            WINDOW_CLASS,
+            w!("Screen Capture"),
+            WS_OVERLAPPEDWINDOW,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            1280,
+            720,
+            Some(owner),
+            None,
+            Some(hinstance),
+            None,
+        )?;
+
+        let view = SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE)
+            .and_then(|_| state.new_view(hwnd));
+        let view = match view {
+            Ok(view) => view,
+            Err(e) => {
+                let _ = DestroyWindow(hwnd);
+                return Err(e);
+            }
+        };
+        SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(Box::new(view)) as isize);
+
+        let _ = ShowWindow(hwnd, SW_SHOW);
+        let _ = UpdateWindow(hwnd);
+    }
+    println!("Opened a new view");
+    Ok(())
+}
+
+/// Print each shader with its number key, type, pass count and parameters, as they'd be
+/// loaded with `--shader-dir`, checking each compiles
+fn list_shaders(config: &Config) {
+    let EmbeddedShaders {
+        simple,
+        tiles,
+        heatmap,
+        dither,
+    } = EmbeddedShaders::spawn(config.shader_dir.as_deref());
+    let pending = simple.into_iter().map(|shader| (shader, "simple")).chain([
+        (tiles, "tiles"),
+        (heatmap, "history"),
+        (dither, "simple"),
+    ]);
+    for (index, (shader, kind)) in pending.enumerate() {
+        let status = match shader.compile.join() {
+            Ok(Ok(_)) => String::new(),
+            Ok(Err(e)) => format!(" (fails to compile: {})", e.message()),
+            Err(_) => " (compilation thread panicked)".to_string(),
+        };
+        let mut metadata = ShaderMetadata::parse(shader.source, shader.name);
+        if let Some(dir) = &config.shader_dir {
+            match ParamsSidecar::load(dir, shader.name) {
+                Ok(Some(overrides)) => {
+                    metadata.params = overrides.params.unwrap_or(metadata.params);
+                    metadata.max_fps = overrides.max_fps.or(metadata.max_fps);
+                }
+                Ok(None) => {}
+                Err(e) => println!("{}", e.message()),
+            }
+        }
+
+        println!(
+            "{} {} ({}, {} pass{}{}){}",
+            index + 1,
+            shader.name,
+            kind,
+            metadata.passes,
+            if metadata.passes == 1 { "" } else { "es" },
+            metadata
+                .max_fps
+                .map(|fps| format!(", max {} fps", fps))
+                .unwrap_or_default(),
+            status
+        );
+        for param in &metadata.params {
+            println!(
+                "    {} = {} ({} to {}, step {})",
+                param.name, param.default, param.min, param.max, param.step
+            );
+        }
+    }
+}
+
+/// Render every shader over the test source with time held at zero, then either write each
+/// output to `<dir>/<shader>.png` or compare it with the image there. Returns whether every
+/// shader's mean difference per channel was within the threshold.
+fn run_golden(state: &mut CaptureState, config: &Config, golden: &GoldenMode) -> Result<bool> {
+    state.fixed_time = Some(0.0);
+    let source = config.test_source.clone().unwrap_or(TestSource::ColorBars);
+    let (srv, width, height) = match &source {
+        TestSource::Image(path) => {
+            let (_, srv, width, height, _) = load_image_from_file(&state.device, path, false)?;
+            (srv, width, height)
+        }
+        TestSource::ColorBars | TestSource::Gradient => {
+            let pixels = test_pattern_pixels(&source, GOLDEN_WIDTH, GOLDEN_HEIGHT);
+            let texture = create_test_texture(&state.device, GOLDEN_WIDTH, GOLDEN_HEIGHT, &pixels)?;
+            let mut srv = None;
+            unsafe {
+                state
+                    .device
+                    .CreateShaderResourceView(&texture, None, Some(&mut srv))
+            }?;
+            (srv.ok_or(E_POINTER)?, GOLDEN_WIDTH, GOLDEN_HEIGHT)
+        }
+    };
+    let frame = CapturedFrame { srv, width, height };
+    let target = create_pass_target(&state.device, width, height, DXGI_FORMAT_B8G8R8A8_UNORM)?;
+
+    let mut passed = true;
+    for index in 0..state.shader_count() {
+        draw_shader(state, index, &frame, &target.rtv, width, height)?;
+        let (mut pixels, stride, ..) = read_back_bgra8(state, &target.texture)?;
+        // Compared as the straight alpha the golden PNGs hold
+        apply_save_background(
+            &mut pixels,
+            stride as usize,
+            width as usize,
+            SaveBackground::Alpha,
+            state.premultiplied_output(),
+        );
+        // Rows tightly packed, like decoded images
+        let pixels: Vec<u8> = pixels
+            .chunks(stride as usize)
+            .flat_map(|row| &row[..width as usize * 4])
+            .copied()
+            .collect();
+
+        let name = state.pixel_shaders[index].name.clone();
+        match golden {
+            GoldenMode::Update(dir) => {
+                std::fs::create_dir_all(dir).map_err(|e| {
+                    Error::new(E_FAIL, format!("Failed to create {}: {}", dir.display(), e))
+                })?;
+                let path = dir.join(format!("{}.png", name));
+                write_png(
+                    &path,
+                    &pixels,
+                    width * 4,
+                    width,
+                    height,
+                    USER_DEFAULT_SCREEN_DPI,
+                )?;
+                println!("Wrote {}", path.display());
+            }
+            GoldenMode::Check(dir) => {
+                let path = dir.join(format!("{}.png", name));
+                let expected = match load_image_from_file(&state.device, &path, false) {
+                    Ok((_, _, w, h, expected)) if (w, h) == (width, height) => expected,
+                    Ok((_, _, w, h, _)) => {
+                        println!(
+                            "FAIL {}: golden image is {}x{}, the output {}x{}",
+                            name, w, h, width, height
+                        );
+                        passed = false;
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("FAIL {}: {}", name, e.message());
+                        passed = false;
+                        continue;
+                    }
+                };
+                let total: u64 = pixels
+                    .iter()
+                    .zip(&expected)
+                    .map(|(&a, &b)| a.abs_diff(b) as u64)
+                    .sum();
+                let mean = total as f32 / pixels.len() as f32;
+                if mean > config.golden_threshold {
+                    println!(
+                        "FAIL {}: mean difference {:.3} exceeds {}",
+                        name, mean, config.golden_threshold
+                    );
+                    passed = false;
+                } else {
+                    println!("ok {}: mean difference {:.3}", name, mean);
+                }
+            }
+        }
+    }
+    Ok(passed)
+}
+
+/// Print the outputs `--capture-output` can duplicate and the monitors `--display-monitor`
+/// can cover, by index, in physical desktop pixels
+fn list_monitors() -> Result<()> {
+    unsafe {
+        // Coordinates in physical pixels, as capture and presentation use them
+        _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+
+        // The device is created on the default adapter, whose outputs these are
+        let factory: IDXGIFactory1 = CreateDXGIFactory1()?;
+        let adapter = factory.EnumAdapters1(0)?;
+        let adapter_name = String::from_utf16_lossy(&adapter.GetDesc1()?.Description);
+        println!(
+            "Capture outputs (--capture-output) on {}:",
+            adapter_name.trim_end_matches('\0')
+        );
+        let mut index = 0;
+        while let Ok(output) = adapter.EnumOutputs(index) {
+            let desc = output.GetDesc()?;
+            let rect = desc.DesktopCoordinates;
+            println!(
+                "{} {} {},{} {}x{}{}",
+                index,
+                String::from_utf16_lossy(&desc.DeviceName).trim_end_matches('\0'),
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                match desc.Rotation {
+                    DXGI_MODE_ROTATION_ROTATE90 => " rotated 90 degrees",
+                    DXGI_MODE_ROTATION_ROTATE180 => " rotated 180 degrees",
+                    DXGI_MODE_ROTATION_ROTATE270 => " rotated 270 degrees",
+                    _ => "",
+                }
+            );
+            index += 1;
+        }
+    }
+
+    println!("Display monitors (--display-monitor):");
+    for (index, rect) in monitor_rects().iter().enumerate() {
+        println!(
+            "{} {},{} {}x{}",
+            index,
+            rect.left,
+            rect.top,
+            rect.right - rect.left,
+            rect.bottom - rect.top
+        );
+    }
+    Ok(())
+}
+
+/// Monitor rectangles in desktop coordinates, in enumeration order
+fn monitor_rects() -> Vec<RECT> {
+    unsafe extern "system" fn collect(
+        monitor: HMONITOR,
+        _hdc: HDC,
+        _clip: *mut RECT,
+        data: LPARAM,
+    ) -> BOOL {
+        unsafe {
+            let rects = &mut *(data.0 as *mut Vec<RECT>);
+            let mut info = MONITORINFO {
+                cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+                ..Default::default()
+            };
+            if GetMonitorInfoW(monitor, &mut info).as_bool() {
+                rects.push(info.rcMonitor);
+            }
+        }
+        BOOL::from(true)
+    }
+
+    let mut rects: Vec<RECT> = Vec::new();
+    unsafe {
+        let _ = EnumDisplayMonitors(
+            None,
+            None,
+            Some(collect),
+            LPARAM(&mut rects as *mut Vec<RECT> as isize),
+        );
+    }
+    rects
+}
+
+/// Client area of `hwnd` in physical screen pixels, the space desktop duplication captures in
+fn client_rect_in_physical_pixels(hwnd: HWND) -> RECT {
+    unsafe {
+        let mut client_rect = RECT::default();
+        let _ = GetClientRect(hwnd, &mut client_rect);
+        let mut top_left = POINT {
+            x: client_rect.left,
+            y: client_rect.top,
+        };
+        let mut bottom_right = POINT {
+            x: client_rect.right,
+            y: client_rect.bottom,
+        };
+        let _ = ClientToScreen(hwnd, &mut top_left);
+        let _ = ClientToScreen(hwnd, &mut bottom_right);
+
+        // Per-monitor aware windows already see physical pixels. Any other awareness gets
+        // coordinates virtualized to the DPI it believes in, so map them back per monitor.
+        let awareness = GetAwarenessFromDpiAwarenessContext(GetWindowDpiAwarenessContext(hwnd));
+        if awareness != DPI_AWARENESS_PER_MONITOR_AWARE {
+            let _ = LogicalToPhysicalPointForPerMonitorDPI(Some(hwnd), &mut top_left);
+            let _ = LogicalToPhysicalPointForPerMonitorDPI(Some(hwnd), &mut bottom_right);
+        }
+
+        RECT {
+            left: top_left.x,
+            top: top_left.y,
+            right: bottom_right.x,
+            bottom: bottom_right.y,
+        }
+    }
+}
+
+/// The `size` box at `source_rect`'s top left in the texture space of an output of
+/// `output_size` at `output_origin`, and how far the box extends past the output's left, top,
+/// right and bottom edges. Both are physical desktop pixels, so this holds across mixed-DPI
+/// monitors.
+fn source_box(
+    source_rect: RECT,
+    output_origin: POINT,
+    size: (i32, i32),
+    output_size: (i32, i32),
+) -> (RECT, RECT) {
+    let left = source_rect.left - output_origin.x;
+    let top = source_rect.top - output_origin.y;
+    let right = left + size.0;
+    let bottom = top + size.1;
+    let extend = RECT {
+        left: (-left).max(0),
+        top: (-top).max(0),
+        right: (right - output_size.0).max(0),
+        bottom: (bottom - output_size.1).max(0),
+    };
+    (
+        RECT {
+            left,
+            top,
+            right,
+            bottom,
+        },
+        extend,
+    )
+}
+
+/// Quarter turns clockwise from a duplicated frame's layout to the desktop's
+fn quarter_turns(rotation: DXGI_MODE_ROTATION) -> u32 {
+    match rotation {
+        DXGI_MODE_ROTATION_ROTATE90 => 1,
+        DXGI_MODE_ROTATION_ROTATE180 => 2,
+        DXGI_MODE_ROTATION_ROTATE270 => 3,
+        _ => 0,
+    }
+}
+
+/// `rect` on an output of `output_size` on the desktop, in the layout of the output's frames,
+/// which are turned `quarter_turns` back from the desktop's
+fn unrotated_rect(quarter_turns: u32, output_size: (i32, i32), rect: RECT) -> RECT {
+    let (width, height) = output_size;
+    match quarter_turns {
+        1 => RECT {
+            left: rect.top,
+            top: width - rect.right,
+            right: rect.bottom,
+            bottom: width - rect.left,
+        },
+        2 => RECT {
+            left: width - rect.right,
+            top: height - rect.bottom,
+            right: width - rect.left,
+            bottom: height - rect.top,
+        },
+        3 => RECT {
+            left: height - rect.bottom,
+            top: rect.left,
+            right: height - rect.top,
+            bottom: rect.right,
+        },
+        _ => rect,
+    }
+}
+
+/// Save the back buffer as a timestamped PNG, with the `--save-background` under its
+/// transparent parts
+fn save_frame_to_png(state: &mut CaptureState) -> Result<()> {
+    redraw_without_overlays(state)?;
+    // Get the back buffer from the swap chain (this has the shaded output)
+    let back_buffer: ID3D11Texture2D = unsafe { state.swap_chain.GetBuffer(0) }?;
+    let (mut pixel_buffer, stride, width, height) = read_back_bgra8(state, &back_buffer)?;
+    apply_save_background(
+        &mut pixel_buffer,
+        stride as usize,
+        width as usize,
+        state.save_background,
+        state.premultiplied_output(),
+    );
+
+    let filename = screenshot_path(state, "png")?;
+
+    // Record the monitor's scaling so viewers show the image at its physical size
+    let dpi = match unsafe { GetDpiForWindow(state.hwnd) } {
+        0 => USER_DEFAULT_SCREEN_DPI,
+        dpi => dpi,
+    };
+    write_png(&filename, &pixel_buffer, stride, width, height, dpi)?;
+
+    println!("Screenshot saved: {}", filename.display());
+    confirm_save(state);
+    Ok(())
+}
+
+/// Save each stage of the capture pipeline as its own PNG labelled with its name and size,
+/// for seeing where an image goes wrong: the region copied from the frame, the padded and
+/// scaled shader input when there is one, and the presented output
+fn dump_pipeline_textures(state: &mut CaptureState) -> Result<()> {
+    redraw_without_overlays(state)?;
+    let back_buffer: ID3D11Texture2D = unsafe { state.swap_chain.GetBuffer(0) }?;
+    let stages = [
+        ("staging", state.staging_texture.clone()),
+        ("extended", state.extended_texture.clone()),
+        ("output", Some(back_buffer)),
+    ];
+    let base = screenshot_path(state, "png")?;
+    for (stage, texture) in stages {
+        let Some(texture) = texture else {
+            println!("No {} texture to dump", stage);
+            continue;
+        };
+        // Labelled on a copy, so the pipeline's own textures are left as they were
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+        let labelled = create_pass_target(&state.device, desc.Width, desc.Height, desc.Format)?;
+        unsafe { state.context.CopyResource(&labelled.texture, &texture) };
+        let label = format!("{} {}x{}", stage, desc.Width, desc.Height);
+        draw_text(state, &labelled.rtv, &label, TEXT_MARGIN, TEXT_MARGIN, 2)?;
+
+        let (mut pixels, stride, width, height) = read_back_bgra8(state, &labelled.texture)?;
+        // Only the output is drawn with the swap chain's alpha convention, the inputs are opaque
+        if stage == "output" {
+            apply_save_background(
+                &mut pixels,
+                stride as usize,
+                width as usize,
+                SaveBackground::Alpha,
+                state.premultiplied_output(),
+            );
+        }
+        let filename = base.with_file_name(format!(
+            "{}_{}.png",
+            base.file_stem().unwrap_or_default().to_string_lossy(),
+            stage
+        ));
+        write_png(
+            &filename,
+            &pixels,
+            stride,
+            width,
+            height,
+            USER_DEFAULT_SCREEN_DPI,
+        )?;
+        println!(
+            "Dumped {} texture ({}x{}): {}",
+            stage,
+            width,
+            height,
+            filename.display()
+        );
+    }
+    Ok(())
+}
+
+/// Timestamped file name with `extension` in the screenshot directory, which is created if
+/// it doesn't exist yet
+fn screenshot_path(state: &CaptureState, extension: &str) -> Result<PathBuf> {
+    let now = {
+        let t = time::OffsetDateTime::now_utc();
+        match time::UtcOffset::local_offset_at(t) {
+            Ok(offset) => t.to_offset(offset),
+            Err(_) => t,
+        }
+    };
+    let format: &[time::format_description::FormatItem<'_>] = time::macros::format_description!(
+        "[year]-[month]-[day]_[hour]_[minute]_[second]_[subsecond digits:3]"
+    );
+    let timestamp = now.format(format).unwrap();
+    let dir = screenshot_dir(state);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| Error::new(E_FAIL, format!("Failed to create {}: {}", dir.display(), e)))?;
+    Ok(dir.join(format!("scrimshady_{}.{}", timestamp, extension)))
+}
+
+/// Save the glyphs the tiles effect last drew as a timestamped text file, a line per row
+fn save_tiles_text(state: &mut CaptureState) -> Result<()> {
+    let config = &state.pixel_shaders[state.current_shader];
+    let ShaderType::Tiles { cells, .. } = &config.shader_type else {
+        println!("{} shader has no glyphs to save as text", config.name);
+        return Ok(());
+    };
+    let Some(cells) = cells.clone() else {
+        println!("Tiles haven't been drawn yet, nothing to save");
+        return Ok(());
+    };
+
+    let [columns, rows] = cells.constants.grid_size;
+    let glyphs = read_back_tile_cells(state, &cells.buffer, columns * rows)?;
+    let mut text = String::with_capacity(glyphs.len() + rows as usize);
+    for row in glyphs.chunks(columns.max(1) as usize) {
+        // The embedded sheet holds printable ASCII from the space character
+        text.extend(row.iter().map(|&cell| match u8::try_from(cell) {
+            Ok(cell) if cell <= b'~' - b' ' => char::from(b' ' + cell),
+            _ => '?',
+        }));
+        text.push('\n');
+    }
+
+    let filename = screenshot_path(state, "txt")?;
+    std::fs::write(&filename, text).map_err(|e| {
+        Error::new(
+            E_FAIL,
+            format!("Failed to write {}: {}", filename.display(), e),
+        )
+    })?;
+    println!(
+        "Tiles saved as {}x{} text: {}",
+        columns,
+        rows,
+        filename.display()
+    );
+    confirm_save(state);
+    Ok(())
+}
+
+/// Copy the first `count` values of a `uint` structured buffer back to the CPU
+fn read_back_tile_cells(
+    state: &CaptureState,
+    buffer: &ID3D11Buffer,
+    count: u32,
+) -> Result<Vec<u32>> {
+    unsafe {
+        let staging_desc = D3D11_BUFFER_DESC {
+            ByteWidth: count.max(1) * std::mem::size_of::<u32>() as u32,
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+            StructureByteStride: 0,
+        };
+        let mut staging_buffer = None;
+        state
+            .device
+            .CreateBuffer(&staging_desc, None, Some(&mut staging_buffer))?;
+        let staging_buffer = staging_buffer.ok_or(E_POINTER)?;
+
+        state.context.CopyResource(&staging_buffer, buffer);
+
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        state
+            .context
+            .Map(&staging_buffer, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+        let values =
+            std::slice::from_raw_parts(mapped.pData as *const u32, count as usize).to_vec();
+        state.context.Unmap(&staging_buffer, 0);
+        Ok(values)
+    }
+}
+
+/// Copy `texture` back to the CPU as 8-bit BGRA, returning the pixels, their row pitch in
+/// bytes, and the width and height
+fn read_back_bgra8(
+    state: &CaptureState,
+    texture: &ID3D11Texture2D,
+) -> Result<(Vec<u8>, u32, u32, u32)> {
+    unsafe {
+        // Get texture description
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        texture.GetDesc(&mut desc);
+
+        // Create a staging texture for CPU readback
+        let staging_desc = D3D11_TEXTURE2D_DESC {
+            Width: desc.Width,
+            Height: desc.Height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: desc.Format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+        };
+
+        let mut staging_texture = None;
+        state
+            .device
+            .CreateTexture2D(&staging_desc, None, Some(&mut staging_texture))?;
+        let staging_texture = staging_texture.ok_or(E_POINTER)?;
+
+        // Copy the texture to staging
+        state.context.CopyResource(&staging_texture, texture);
+
+        // Map the staging texture to read the pixels
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        state
+            .context
+            .Map(&staging_texture, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+
+        let stride = mapped.RowPitch;
+        let buffer_size = stride * desc.Height;
+        let mut pixel_buffer =
+            std::slice::from_raw_parts(mapped.pData as *const u8, buffer_size as usize).to_vec();
+
+        // Unmap the texture
+        state.context.Unmap(&staging_texture, 0);
+
+        // PNGs are written as 8-bit BGRA whatever the texture holds
+        if desc.Format == DXGI_FORMAT_R10G10B10A2_UNORM {
+            r10g10b10a2_to_bgra8(&mut pixel_buffer);
+        }
+        if desc.Format == DXGI_FORMAT_R16G16B16A16_FLOAT {
+            let pixels = rgba16f_to_bgra8(&pixel_buffer, stride as usize, desc.Width as usize);
+            return Ok((pixels, desc.Width * 4, desc.Width, desc.Height));
+        }
+
+        Ok((pixel_buffer, stride, desc.Width, desc.Height))
+    }
+}
+
+/// Encode BGRA `pixels` with straight alpha, rows `stride` bytes apart, as a PNG at `path`.
+/// WIC takes `dpi` and writes it to the PNG as pixels per meter.
+fn write_png(
+    path: &std::path::Path,
+    pixels: &[u8],
+    stride: u32,
+    width: u32,
+    height: u32,
+    dpi: u32,
+) -> Result<()> {
+    unsafe {
+        // Create WIC factory
+        let wic_factory: IWICImagingFactory =
+            CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER)?;
+
+        // Create stream for file
+        let stream = wic_factory.CreateStream()?;
+        stream.InitializeFromFilename(&HSTRING::from(path), GENERIC_WRITE.0)?;
+
+        // Create PNG encoder
+        let encoder = wic_factory.CreateEncoder(&GUID_ContainerFormatPng, std::ptr::null())?;
+        encoder.Initialize(&stream, WICBitmapEncoderNoCache)?;
+
+        // Create frame
+        let mut frame = None;
+        encoder.CreateNewFrame(&mut frame, std::ptr::null_mut())?;
+        let frame = frame.ok_or(E_POINTER)?;
+        frame.Initialize(None)?;
+        frame.SetSize(width, height)?;
+        frame.SetResolution(dpi as f64, dpi as f64)?;
+
+        // Set pixel format to BGRA (which matches our texture format, or its conversion)
+        let mut pixel_format = GUID_WICPixelFormat32bppBGRA;
+        frame.SetPixelFormat(&mut pixel_format)?;
+
+        // Write pixels
+        frame.WritePixels(height, stride, pixels)?;
+
+        // Commit frame and encoder
+        frame.Commit()?;
+        encoder.Commit()?;
+    }
+    Ok(())
+}
+
+/// Where screenshots are saved, the working directory unless one was configured
+fn screenshot_dir(state: &CaptureState) -> PathBuf {
+    state
+        .screenshot_dir
+        .clone()
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default()
+}
+
+/// Show the screenshot directory in Explorer, creating it first if it doesn't exist yet
+fn open_screenshot_dir(state: &CaptureState) -> Result<()> {
+    let dir = screenshot_dir(state);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| Error::new(E_FAIL, format!("Failed to create {}: {}", dir.display(), e)))?;
+    let opened = unsafe {
+        ShellExecuteW(
+            Some(state.hwnd),
+            w!("explore"),
+            &HSTRING::from(dir.as_path()),
+            PCWSTR::null(),
+            PCWSTR::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // Anything above 32 is success, the rest are error codes
+    if opened.0 as usize <= 32 {
+        return Err(Error::from_thread());
+    }
+    println!("Opened screenshot directory: {}", dir.display());
+    Ok(())
+}
+
+/// Track whether the user has been away longer than the idle timeout, returning the new state
+fn update_idle(state: &mut CaptureState) -> bool {
+    let Some(timeout) = state.idle_timeout else {
+        return false;
+    };
+
+    let mut input_info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if !unsafe { GetLastInputInfo(&mut input_info) }.as_bool() {
+        return false;
+    }
+    // Tick counts wrap after ~49 days, wrapping_sub keeps the difference correct
+    let idle_millis = unsafe { GetTickCount() }.wrapping_sub(input_info.dwTime);
+    let idle = idle_millis as u128 >= timeout.as_millis();
+
+    if idle != state.idle {
+        state.idle = idle;
+        println!(
+            "{}",
+            if idle {
+                "Idle: rendering suspended"
+            } else {
+                "Input detected: rendering resumed"
+            }
+        );
+    }
+    idle
+}
+
+/// While occluded, test-present to see whether the window has become visible again.
+/// Returns whether rendering should stay suspended.
+pub(crate) fn update_occluded(state: &mut CaptureState) -> bool {
+    if !state.occluded {
+        return false;
+    }
+    let test = unsafe { state.swap_chain.Present(0, DXGI_PRESENT_TEST) };
+    if test != DXGI_STATUS_OCCLUDED {
+        println!("Window visible again, resuming rendering");
+        state.occluded = false;
+    }
+    state.occluded
+}
+
+/// Show the current shader, frame rate and window state in the title bar, once a second
+fn update_title(state: &mut CaptureState) {
+    let elapsed = state.last_title_update.elapsed();
+    if elapsed < std::time::Duration::from_secs(1) {
+        return;
+    }
+    let fps = state.frames_since_title as f32 / elapsed.as_secs_f32();
+    state.frames_since_title = 0;
+    state.last_title_update = std::time::Instant::now();
+
+    let mut title = format!(
+        "Screen Capture - {} - {:.0} fps",
+        state.pixel_shaders[state.current_shader].name, fps
+    );
+    if state.paused {
+        title.push_str(" - paused");
+    } else if state.frozen {
+        title.push_str(" - frozen");
+    } else if state.idle {
+        title.push_str(" - idle");
+    } else if state.occluded {
+        title.push_str(" - occluded");
+    } else if state.waiting_for_display {
+        title.push_str(" - waiting for a display");
+    } else if state.duplication_retry.is_some() {
+        title.push_str(" - waiting for display access");
+    }
+    if state.gdi_capture.is_some() {
+        title.push_str(" - GDI capture");
+    }
+    if state.latency_mode != LatencyMode::LowLatency {
+        title.push_str(" - ");
+        title.push_str(state.latency_mode.name());
+    }
+    match (state.flip_h, state.flip_v) {
+        (true, true) => title.push_str(" - flipped"),
+        (true, false) => title.push_str(" - mirrored"),
+        (false, true) => title.push_str(" - upside down"),
+        (false, false) => {}
+    }
+    if state.always_on_top {
+        title.push_str(" - always on top");
+    }
+
+    let title_wide: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        let _ = SetWindowTextW(state.hwnd, PCWSTR(title_wide.as_ptr()));
+    }
+}
+
+/// Convert packed 10:10:10:2 RGBA pixels to 8-bit BGRA in place
+fn r10g10b10a2_to_bgra8(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let packed = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        let r = (packed & 0x3FF) >> 2;
+        let g = ((packed >> 10) & 0x3FF) >> 2;
+        let b = ((packed >> 20) & 0x3FF) >> 2;
+        let a = (packed >> 30) * 85; // 2-bit alpha to 0..255
+        pixel.copy_from_slice(&[b as u8, g as u8, r as u8, a as u8]);
+    }
+}
+
+/// Linear half float RGBA rows, `stride` bytes apart, as tightly packed sRGB encoded BGRA
+fn rgba16f_to_bgra8(pixels: &[u8], stride: usize, width: usize) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(pixels.len() / stride * width * 4);
+    for row in pixels.chunks(stride) {
+        for pixel in row[..width * 8].chunks_exact(8) {
+            let [r, g, b, a] =
+                [0, 2, 4, 6].map(|i| f16_to_f32(u16::from_le_bytes([pixel[i], pixel[i + 1]])));
+            let [r, g, b] = [r, g, b].map(linear_to_srgb);
+            converted.extend([b, g, r, a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+        }
+    }
+    converted
+}
+
+/// IEEE half precision `bits` widened to a float
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1F) as i32;
+    let mantissa = (bits & 0x3FF) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Scale each BGRA pixel's color by its alpha
+fn premultiply_bgra8(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel[..3] {
+            // Rounded, so opaque pixels are left exactly as they were
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Composite BGRA `pixels` over `background`, or turn them to straight alpha when keeping it.
+/// Only the first `width` pixels of each `stride` byte row are touched.
+fn apply_save_background(
+    pixels: &mut [u8],
+    stride: usize,
+    width: usize,
+    background: SaveBackground,
+    premultiplied: bool,
+) {
+    for row in pixels.chunks_mut(stride) {
+        for pixel in row[..width * 4].chunks_exact_mut(4) {
+            let alpha = pixel[3] as f32 / 255.0;
+            match background {
+                SaveBackground::Alpha => {
+                    if premultiplied && alpha > 0.0 {
+                        for channel in &mut pixel[..3] {
+                            *channel = (*channel as f32 / alpha).round().min(255.0) as u8;
+                        }
+                    }
+                }
+                SaveBackground::Color([r, g, b]) => {
+                    // BGRA order
+                    for (channel, backdrop) in pixel[..3].iter_mut().zip([b, g, r]) {
+                        let color = *channel as f32 / 255.0;
+                        let color = if premultiplied { color } else { color * alpha };
+                        *channel = ((color + backdrop * (1.0 - alpha)) * 255.0)
+                            .round()
+                            .clamp(0.0, 255.0) as u8;
+                    }
+                    pixel[3] = 255;
+                }
+            }
+        }
+    }
+}
+
+fn toggle_always_on_top(state: &mut CaptureState) -> Result<()> {
+    unsafe {
+        state.always_on_top = !state.always_on_top;
+
+        let hwnd_insert_after = if state.always_on_top {
+            HWND_TOPMOST
+        } else {
+            HWND_NOTOPMOST
+        };
+
+        SetWindowPos(
+            state.hwnd,
+            Some(hwnd_insert_after),
+            0,
+            0,
+            0,
+            0,
+            SWP_NOMOVE | SWP_NOSIZE,
+        )?;
+
+        println!(
+            "Always on top: {}",
+            if state.always_on_top {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+    }
+    Ok(())
+}
+
+// Posted by the shell for clicks on the tray icon
+const WM_TRAY_ICON: u32 = WM_APP + 1;
+// Posted by quit to the primary window to destroy it
+const WM_EXIT: u32 = WM_APP + 3;
+const TRAY_ICON_ID: u32 = 1;
+const ID_TRAY_SHOW: u16 = 3001;
+const ID_TRAY_EXIT: u16 = 3002;
+
+fn tray_icon_data(hwnd: HWND) -> NOTIFYICONDATAW {
+    NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: hwnd,
+        uID: TRAY_ICON_ID,
+        ..Default::default()
+    }
+}
+
+fn add_tray_icon(hwnd: HWND) -> Result<()> {
+    let mut data = tray_icon_data(hwnd);
+    data.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+    data.uCallbackMessage = WM_TRAY_ICON;
+    data.hIcon = unsafe { LoadIconW(None, IDI_APPLICATION)? };
+    for (dst, src) in data.szTip.iter_mut().zip("Scrim Shady".encode_utf16()) {
+        *dst = src;
+    }
+    unsafe { Shell_NotifyIconW(NIM_ADD, &data) }.ok()
+}
+
+fn remove_tray_icon(hwnd: HWND) {
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_DELETE, &tray_icon_data(hwnd));
+    }
+}
+
+/// Hide the window, leaving only the tray icon. Rendering stops while hidden.
+fn hide_to_tray(state: &mut CaptureState) {
+    state.hidden = true;
+    unsafe {
+        let _ = ShowWindow(state.hwnd, SW_HIDE);
+    }
+    println!("Hidden to tray");
+}
+
+fn restore_from_tray(state: &mut CaptureState) {
+    state.hidden = false;
+    unsafe {
+        let _ = ShowWindow(state.hwnd, SW_SHOW);
+        let _ = SetForegroundWindow(state.hwnd);
+    }
+    println!("Restored from tray");
+}
+
+fn show_tray_menu(state: &mut CaptureState) {
+    unsafe {
+        let Ok(menu) = CreatePopupMenu() else {
+            return;
+        };
+        let _ = AppendMenuW(menu, MF_STRING, ID_TRAY_SHOW as usize, w!("Show"));
+        let _ = AppendMenuW(menu, MF_STRING, ID_TRAY_EXIT as usize, w!("Exit"));
+
+        // The menu only dismisses on an outside click if our window is in the foreground
+        let mut cursor = POINT::default();
+        let _ = GetCursorPos(&mut cursor);
+        let _ = SetForegroundWindow(state.hwnd);
+        let command = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_RIGHTBUTTON,
+            cursor.x,
+            cursor.y,
+            None,
+            state.hwnd,
+            None,
+        );
+        let _ = DestroyMenu(menu);
+
+        match command.0 as u16 {
+            ID_TRAY_SHOW => restore_from_tray(state),
+            ID_TRAY_EXIT => quit(state),
+            _ => {}
+        }
+    }
+}
+
+const SCREENSHOT_TIMER_ID: usize = 1;
+const FLASH_TIMER_ID: usize = 2;
+const SLIDESHOW_TIMER_ID: usize = 3;
+const FRAME_PACING_TIMER_ID: usize = 4;
+const IDLE_POLL_TIMER_ID: usize = 5;
+// The screenshot countdown ticks once a second
+const SCREENSHOT_TICK_MILLIS: u32 = 1000;
+// How long the window stays white to confirm a saved screenshot
+const FLASH_DURATION_MILLIS: u32 = 80;
+// How often an idle or occluded window, or one waiting to retry duplication, checks again
+const IDLE_POLL_MILLIS: u32 = 50;
+
+fn toggle_slideshow(state: &mut CaptureState) {
+    state.slideshow = !state.slideshow;
+    if state.slideshow {
+        restart_slideshow_timer(state);
+        println!(
+            "Slideshow: next shader every {} seconds",
+            state.slideshow_interval
+        );
+    } else {
+        unsafe {
+            let _ = KillTimer(Some(state.hwnd), SLIDESHOW_TIMER_ID);
+        }
+        println!("Slideshow stopped");
+    }
+}
+
+/// Start the slideshow interval over, replacing any timer already running
+fn restart_slideshow_timer(state: &CaptureState) {
+    let millis = state.slideshow_interval.saturating_mul(1000);
+    if unsafe { SetTimer(Some(state.hwnd), SLIDESHOW_TIMER_ID, millis, None) } == 0 {
+        println!(
+            "Failed to start slideshow timer: {:?}",
+            Error::from_thread()
+        );
+    }
+}
+
+/// Let the user know a screenshot was saved without needing the console
+fn confirm_save(state: &mut CaptureState) {
+    unsafe {
+        if state.save_beep {
+            let _ = MessageBeep(MB_OK);
+        }
+        if state.save_flash
+            && let Some(rtv) = &state.render_target_view
+        {
+            // Present plain white, and restore the frame when the timer fires
+            state.context.ClearRenderTargetView(rtv, &[1.0; 4]);
+            if state.swap_chain.Present(1, DXGI_PRESENT(0)).is_ok() {
+                SetTimer(
+                    Some(state.hwnd),
+                    FLASH_TIMER_ID,
+                    FLASH_DURATION_MILLIS,
+                    None,
+                );
+            }
+        }
+    }
+}
+
+/// Save a screenshot after the configured delay, or cancel one that's counting down
+fn start_screenshot_countdown(state: &mut CaptureState) {
+    unsafe {
+        if state.screenshot_countdown.take().is_some() {
+            let _ = KillTimer(Some(state.hwnd), SCREENSHOT_TIMER_ID);
+            println!("Delayed screenshot cancelled");
+            let _ = redraw_held_frame(state);
+            return;
+        }
+        if state.screenshot_delay == 0 {
+            if let Err(e) = save_frame_to_png(state) {
+                println!("Failed to save frame: {:?}", e);
+            }
+            return;
+        }
+        if SetTimer(
+            Some(state.hwnd),
+            SCREENSHOT_TIMER_ID,
+            SCREENSHOT_TICK_MILLIS,
+            None,
+        ) == 0
+        {
+            println!(
+                "Failed to start screenshot timer: {:?}",
+                Error::from_thread()
+            );
+            return;
+        }
+    }
+    state.screenshot_countdown = Some(state.screenshot_delay);
+    println!("Saving a screenshot in {} seconds", state.screenshot_delay);
+    let _ = redraw_held_frame(state);
+}
+
+fn tick_screenshot_countdown(state: &mut CaptureState) {
+    let Some(seconds) = state.screenshot_countdown else {
+        return;
+    };
+    if seconds > 1 {
+        state.screenshot_countdown = Some(seconds - 1);
+        let _ = redraw_held_frame(state);
+        return;
+    }
+
+    unsafe {
+        let _ = KillTimer(Some(state.hwnd), SCREENSHOT_TIMER_ID);
+    }
+    state.screenshot_countdown = None;
+    // Present once without the countdown so it isn't in the saved frame
+    if let Err(e) = redraw_held_frame(state).and_then(|_| save_frame_to_png(state)) {
+        println!("Failed to save frame: {:?}", e);
+    }
+}
+
+/// Render the last captured frame again, for changes that don't wait on the desktop
+fn redraw_held_frame(state: &mut CaptureState) -> Result<()> {
+    let hwnd = state.hwnd;
+    render_frame(state, hwnd)
+}
+
+/// Render the last captured frame again without the latency and help overlays when they're
+/// drawn over the back buffer, so they aren't saved. They show again from the next frame.
+fn redraw_without_overlays(state: &mut CaptureState) -> Result<()> {
+    if !state.overlay_drawn {
+        return Ok(());
+    }
+    let show_latency = std::mem::replace(&mut state.show_latency, false);
+    let help = std::mem::replace(&mut state.help, false);
+    let redrawn = redraw_held_frame(state);
+    state.show_latency = show_latency;
+    state.help = help;
+    redrawn
+}
+
+fn toggle_pause_and_hide(state: &mut CaptureState) -> Result<()> {
+    state.paused = !state.paused;
+
+    if !state.pause_makes_capturable {
+        println!(
+            "Window: {}",
+            if state.paused { "paused" } else { "rendering" }
+        );
+        return Ok(());
+    }
+
+    let flags = if state.paused {
+        WINDOW_DISPLAY_AFFINITY(0)
+    } else {
+        WDA_EXCLUDEFROMCAPTURE
+    };
+    unsafe { SetWindowDisplayAffinity(state.hwnd, flags) }?;
+
+    println!(
+        "Window: {}",
+        if state.paused {
+            "paused and capturable"
+        } else {
+            "rendering and excluded from capture"
+        }
+    );
+    Ok(())
+}
+
+fn adjust_param(state: &mut CaptureState, direction: f32) {
+    let config = &mut state.pixel_shaders[state.current_shader];
+    let Some(param) = config.params.get_mut(state.selected_param) else {
+        println!("{} shader has no parameters", config.name);
+        return;
+    };
+    param.value = (param.value + direction * param.step).clamp(param.min, param.max);
+    println!("{} {}: {}", config.name, param.name, param.value);
+}
+
+fn select_next_param(state: &mut CaptureState) {
+    let config = &state.pixel_shaders[state.current_shader];
+    if config.params.is_empty() {
+        println!("{} shader has no parameters", config.name);
+        return;
+    }
+    state.selected_param = (state.selected_param + 1) % config.params.len();
+    let param = &config.params[state.selected_param];
+    println!("Selected {} {} ({})", config.name, param.name, param.value);
+}
+
+fn reset_params(config: &mut PixelShaderConfig) {
+    for param in config.params.iter_mut() {
+        param.value = param.default;
+    }
+}
+
+fn cycle_tiles_curve(state: &mut CaptureState) {
+    let config = &mut state.pixel_shaders[state.current_shader];
+    let ShaderType::Tiles {
+        curves,
+        current_curve,
+        ..
+    } = &mut config.shader_type
+    else {
+        println!("{} shader has no brightness curve", config.name);
+        return;
+    };
+    *current_curve = (*current_curve + 1) % curves.len();
+    let curve = &curves[*current_curve];
+    println!(
+        "Tiles brightness curve: {} (gamma {}, points {:?})",
+        curve.name, curve.gamma, curve.points
+    );
+}
+
+fn cycle_tiles_opacity(state: &mut CaptureState) {
+    let config = &mut state.pixel_shaders[state.current_shader];
+    let ShaderType::Tiles { opacity, .. } = &mut config.shader_type else {
+        println!("{} shader has no overlay opacity", config.name);
+        return;
+    };
+    // A custom opacity from the command line cycles on to the first preset
+    *opacity = TILES_OPACITIES
+        .iter()
+        .position(|preset| preset == opacity)
+        .map_or(TILES_OPACITIES[0], |current| {
+            TILES_OPACITIES[(current + 1) % TILES_OPACITIES.len()]
+        });
+    println!("Tiles overlay opacity: {:.0}%", *opacity * 100.0);
+}
+
+fn cycle_clear_color(state: &mut CaptureState) {
+    // A custom color from the command line cycles on to the first preset
+    let next = CLEAR_COLORS
+        .iter()
+        .position(|(_, color)| *color == state.clear_color)
+        .map_or(0, |current| (current + 1) % CLEAR_COLORS.len());
+    let (name, color) = CLEAR_COLORS[next];
+    state.clear_color = color;
+    println!("Clear color: {}", name);
+}
+
+const RENDER_SCALES: [f32; 4] = [1.0, 0.75, 0.5, 0.25];
+
+fn cycle_render_scale(state: &mut CaptureState) {
+    // Step down to the next listed scale, so a scale from the command line joins the cycle
+    // where it fits
+    let next = RENDER_SCALES
+        .iter()
+        .position(|&scale| scale < state.render_scale)
+        .unwrap_or(0);
+    state.render_scale = RENDER_SCALES[next];
+
+    // The extended texture is sized by the scale, so rebuild it next frame
+    state.extended_texture = None;
+    state.extended_srv = None;
+    state.extended_uav = None;
+
+    println!("Render scale: {}%", (state.render_scale * 100.0) as u32);
+}
+
+/// The sRGB transfer function's inverse, for a channel from 0 to 1
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The sRGB transfer function, for a channel from 0 to 1
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn load_image_from_file(
+    device: &ID3D11Device,
+    path: &std::path::Path,
+    srgb: bool,
+) -> Result<(ID3D11Texture2D, ID3D11ShaderResourceView, u32, u32, Vec<u8>)> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| Error::new(E_FAIL, format!("Failed to read {}: {}", path.display(), e)))?;
+    load_image_from_bytes(device, &bytes, &path.display().to_string(), srgb)
+}
+
+/// Decode any WIC-supported image (PNG, JPEG, BMP, GIF, TIFF, ...) into a BGRA texture.
+/// `srgb` textures are decoded to linear light when sampled.
+fn load_image_from_bytes(
+    device: &ID3D11Device,
+    image_bytes: &[u8],
+    name: &str,
+    srgb: bool,
+) -> Result<(ID3D11Texture2D, ID3D11ShaderResourceView, u32, u32, Vec<u8>)> {
+    unsafe {
+        // Create WIC factory
+        let wic_factory: IWICImagingFactory =
+            CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER)?;
+
+        // Create a stream from the encoded image bytes
+        let Some(stream) = SHCreateMemStream(Some(image_bytes)) else {
+            return Err(Error::from_thread());
+        };
+
+        // Create decoder from stream (WIC picks the codec from the data)
+        let decoder = wic_factory.CreateDecoderFromStream(
+            &stream,
+            std::ptr::null(),
+            WICDecodeMetadataCacheOnDemand,
+        )?;
+
+        // Get the first frame (animated GIFs and multi-page TIFFs only use frame 0)
+        let frame = decoder.GetFrame(0)?;
+
+        // Get frame dimensions
+        let mut width = 0u32;
+        let mut height = 0u32;
+        frame.GetSize(&mut width, &mut height)?;
+
+        // Convert to BGRA format
+        let target_format = GUID_WICPixelFormat32bppBGRA;
+        let converter = wic_factory.CreateFormatConverter()?;
+        converter.Initialize(
+            &frame,
+            &target_format,
+            WICBitmapDitherTypeNone,
+            None,
+            0.0,
+            WICBitmapPaletteTypeMedianCut,
+        )?;
+
+        // Calculate stride and buffer size
+        let stride = width * 4; // 4 bytes per pixel (BGRA)
+        let buffer_size = stride * height;
+
+        // Read pixels into buffer
+        let mut pixel_buffer = vec![0u8; buffer_size as usize];
+        converter.CopyPixels(std::ptr::null(), stride, &mut pixel_buffer)?;
+
+        // Create D3D11 texture with initial data
+        let format = if srgb {
+            DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+        } else {
+            DXGI_FORMAT_B8G8R8A8_UNORM
+        };
+        let texture_desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+
+        let texture_data = D3D11_SUBRESOURCE_DATA {
+            pSysMem: pixel_buffer.as_ptr() as *const _,
+            SysMemPitch: stride,
+            SysMemSlicePitch: 0,
+        };
+
+        let mut texture = None;
+        device.CreateTexture2D(&texture_desc, Some(&texture_data), Some(&mut texture))?;
+        let texture = texture.ok_or(E_POINTER)?;
+
+        // Create shader resource view
+        let srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+            Format: format,
+            ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2D,
+            Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+                Texture2D: D3D11_TEX2D_SRV {
+                    MostDetailedMip: 0,
+                    MipLevels: 1,
+                },
+            },
+        };
+
+        let mut srv = None;
+        device.CreateShaderResourceView(&texture, Some(&srv_desc), Some(&mut srv))?;
+        let srv = srv.ok_or(E_POINTER)?;
+
+        println!(
+            "Loaded {} ({}x{}, {} bytes)",
+            name, width, height, buffer_size
+        );
+
+        Ok((texture, srv, width, height, pixel_buffer))
+    }
+}
+
+/// A BGRA texture array with a `width` by `height` slice for each of `slices`, as tightly
+/// packed pixels
+fn create_texture_array_srv(
+    device: &ID3D11Device,
+    width: u32,
+    height: u32,
+    slices: &[Vec<u8>],
+) -> Result<ID3D11ShaderResourceView> {
+    unsafe {
+        let texture_desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: slices.len() as u32,
+            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_IMMUTABLE,
+            BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+        let slice_data = slices
+            .iter()
+            .map(|pixels| D3D11_SUBRESOURCE_DATA {
+                pSysMem: pixels.as_ptr() as *const _,
+                SysMemPitch: width * 4,
+                SysMemSlicePitch: 0,
+            })
+            .collect::<Vec<_>>();
+
+        let mut texture = None;
+        device.CreateTexture2D(&texture_desc, Some(slice_data.as_ptr()), Some(&mut texture))?;
+        let texture = texture.ok_or(E_POINTER)?;
+
+        let srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2DARRAY,
+            Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+                Texture2DArray: D3D11_TEX2D_ARRAY_SRV {
+                    MostDetailedMip: 0,
+                    MipLevels: 1,
+                    FirstArraySlice: 0,
+                    ArraySize: slices.len() as u32,
+                },
+            },
+        };
+        let mut srv = None;
+        device.CreateShaderResourceView(&texture, Some(&srv_desc), Some(&mut srv))?;
+        srv.ok_or_else(|| E_POINTER.into())
+    }
+}
+
+/// Whitespace or comma separated numbers from a shader's data file
+fn load_float_data(path: &std::path::Path) -> Result<Vec<f32>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::new(E_FAIL, format!("Failed to read {}: {}", path.display(), e)))?;
+    let values = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value.parse::<f32>().map_err(|_| {
+                Error::new(
+                    E_INVALIDARG,
+                    format!("{}: '{}' is not a number", path.display(), value),
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if values.is_empty() {
+        return Err(Error::new(
+            E_INVALIDARG,
+            format!("{} has no numbers", path.display()),
+        ));
+    }
+    Ok(values)
+}
+
+/// Immutable `StructuredBuffer` holding `values`, whose element type must match `T`'s layout
+fn create_structured_buffer_srv<T: Copy>(
+    device: &ID3D11Device,
+    values: &[T],
+) -> Result<ID3D11ShaderResourceView> {
+    unsafe {
+        let buffer_desc = D3D11_BUFFER_DESC {
+            ByteWidth: std::mem::size_of_val(values) as u32,
+            Usage: D3D11_USAGE_IMMUTABLE,
+            BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: D3D11_RESOURCE_MISC_BUFFER_STRUCTURED.0 as u32,
+            StructureByteStride: std::mem::size_of::<T>() as u32,
+        };
+
+        let buffer_data = D3D11_SUBRESOURCE_DATA {
+            pSysMem: values.as_ptr() as *const _,
+            SysMemPitch: 0,
+            SysMemSlicePitch: 0,
+        };
+
+        let mut buffer_out = None;
+        device.CreateBuffer(&buffer_desc, Some(&buffer_data), Some(&mut buffer_out))?;
+        let buffer = buffer_out.ok_or(E_POINTER)?;
+
+        let mut srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+            Format: DXGI_FORMAT_UNKNOWN,
+            ViewDimension: D3D11_SRV_DIMENSION_BUFFER,
+            Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+                Buffer: std::mem::zeroed(),
+            },
+        };
+
+        // Set buffer parameters through the union
+        srv_desc.Anonymous.Buffer.Anonymous1.FirstElement = 0;
+        srv_desc.Anonymous.Buffer.Anonymous2.NumElements = values.len() as u32;
+
+        let mut srv_out = None;
+        device.CreateShaderResourceView(&buffer, Some(&srv_desc), Some(&mut srv_out))?;
+        srv_out.ok_or_else(|| E_POINTER.into())
+    }
+}
+
+fn compute_tile_brightness(
+    pixels: &[u8],
+    width: u32,
+    layout: &GlyphLayout,
+    luma: LumaWeights,
+) -> Vec<TileGlyph> {
+    let [red_weight, green_weight, blue_weight] = luma.weights();
+    let mut brightness_values = Vec::with_capacity(layout.cells.len());
+
+    for (glyph, &cell) in layout.cells.iter().enumerate() {
+        let (origin_x, origin_y) = layout.glyph_origin(glyph);
+        let mut brightness_sum = 0.0f32;
+
+        // Sample the tile
+        for sy in 0..layout.tile_height {
+            for sx in 0..layout.tile_width {
+                let pixel_x = origin_x + sx;
+                let pixel_y = origin_y + sy;
+
+                // Get pixel index (BGRA format)
+                let pixel_index = ((pixel_y * width + pixel_x) * 4) as usize;
+
+                if pixel_index + 2 < pixels.len() {
+                    let b = pixels[pixel_index] as f32 / 255.0;
+                    let g = pixels[pixel_index + 1] as f32 / 255.0;
+                    let r = pixels[pixel_index + 2] as f32 / 255.0;
+
+                    let luminance = red_weight * r + green_weight * g + blue_weight * b;
+                    brightness_sum += luminance;
+                }
+            }
+        }
+
+        // Average brightness for this tile
+        let avg_brightness = brightness_sum / (layout.tile_width * layout.tile_height) as f32;
+        brightness_values.push(TileGlyph {
+            brightness: avg_brightness,
+            cell,
+        });
+    }
+
+    brightness_values
+}
+
+fn write_frame_constants(
+    context: &ID3D11DeviceContext,
+    buffer: &ID3D11Buffer,
+    constants: &FrameConstants,
+) -> Result<()> {
+    unsafe {
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        context.Map(buffer, 0, D3D11_MAP_WRITE_DISCARD, 0, Some(&mut mapped))?;
+        std::ptr::copy_nonoverlapping(
+            constants as *const _ as *const u8,
+            mapped.pData as *mut u8,
+            std::mem::size_of::<FrameConstants>(),
+        );
+        context.Unmap(buffer, 0);
+    }
+    Ok(())
+}
+
+/// Swap chain presenting to `hwnd`. Transparent swap chains keep premultiplied alpha and are
+/// shown through DirectComposition, since a plain HWND swap chain is always opaque.
+fn create_swap_chain(
+    device: &ID3D11Device,
+    adapter: &IDXGIAdapter,
+    hwnd: HWND,
+    format: DXGI_FORMAT,
+    buffers: u32,
+    effect: SwapEffect,
+    transparent: bool,
+) -> Result<(IDXGISwapChain1, Option<Composition>)> {
+    unsafe {
+        let factory: IDXGIFactory2 = adapter.GetParent()?;
+
+        let mut client_rect = RECT::default();
+        GetClientRect(hwnd, &mut client_rect)?;
+        let (width, height) = clamp_texture_size(
+            (client_rect.right - client_rect.left) as u32,
+            (client_rect.bottom - client_rect.top) as u32,
+            "Window",
+        );
+
+        let swap_chain_desc = DXGI_SWAP_CHAIN_DESC1 {
+            Width: width,
+            Height: height,
+            Format: format,
+            Stereo: BOOL::from(false),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+            BufferCount: buffers,
+            Scaling: DXGI_SCALING_STRETCH,
+            SwapEffect: match effect {
+                SwapEffect::FlipDiscard => DXGI_SWAP_EFFECT_FLIP_DISCARD,
+                SwapEffect::FlipSequential => DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+            },
+            AlphaMode: if transparent {
+                DXGI_ALPHA_MODE_PREMULTIPLIED
+            } else {
+                DXGI_ALPHA_MODE_UNSPECIFIED
+            },
+            Flags: 0,
+        };
+
+        if !transparent {
+            let swap_chain =
+                factory.CreateSwapChainForHwnd(device, hwnd, &swap_chain_desc, None, None)?;
+            println!(
+                "created swapchain ({:?}, {} buffers, {:?})",
+                format, buffers, effect
+            );
+            return Ok((swap_chain, None));
+        }
+
+        let swap_chain = factory.CreateSwapChainForComposition(device, &swap_chain_desc, None)?;
+        let composition_device: IDCompositionDevice =
+            DCompositionCreateDevice(&device.cast::<IDXGIDevice>()?)?;
+        let target = composition_device.CreateTargetForHwnd(hwnd, true)?;
+        let visual = composition_device.CreateVisual()?;
+        visual.SetContent(&swap_chain)?;
+        target.SetRoot(&visual)?;
+        composition_device.Commit()?;
+        println!(
+            "created transparent composition swapchain ({:?}, {} buffers, {:?})",
+            format, buffers, effect
+        );
+        Ok((
+            swap_chain,
+            Some(Composition {
+                _device: composition_device,
+                _target: target,
+                _visual: visual,
+            }),
+        ))
+    }
+}
+
+fn create_device(
+    driver_type: D3D_DRIVER_TYPE,
+    flags: D3D11_CREATE_DEVICE_FLAG,
+) -> Result<(ID3D11Device, ID3D11DeviceContext)> {
+    unsafe {
+        let mut device = None;
+        let mut context = None;
+        D3D11CreateDevice(
+            None,
+            driver_type,
+            HMODULE::default(),
+            flags,
+            None,
+            D3D11_SDK_VERSION,
+            Some(&mut device),
+            None,
+            Some(&mut context),
+        )?;
+        Ok((device.ok_or(E_POINTER)?, context.ok_or(E_POINTER)?))
+    }
+}
+
+/// Offscreen target an intermediate shader pass renders into and the next pass samples
+fn create_pass_target(
+    device: &ID3D11Device,
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+) -> Result<PassTarget> {
+    let desc = D3D11_TEXTURE2D_DESC {
+        Width: width.max(1),
+        Height: height.max(1),
+        MipLevels: 1,
+        ArraySize: 1,
+        Format: format,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Usage: D3D11_USAGE_DEFAULT,
+        BindFlags: (D3D11_BIND_RENDER_TARGET.0 | D3D11_BIND_SHADER_RESOURCE.0) as u32,
+        CPUAccessFlags: 0,
+        MiscFlags: 0,
+    };
+
+    unsafe {
+        let mut texture = None;
+        device.CreateTexture2D(&desc, None, Some(&mut texture))?;
+        let texture = texture.ok_or(E_POINTER)?;
+
+        let mut rtv = None;
+        device.CreateRenderTargetView(&texture, None, Some(&mut rtv))?;
+        let mut srv = None;
+        device.CreateShaderResourceView(&texture, None, Some(&mut srv))?;
+
+        Ok(PassTarget {
+            texture,
+            rtv: rtv.ok_or(E_POINTER)?,
+            srv: srv.ok_or(E_POINTER)?,
+        })
+    }
+}
+
+/// Render target with `samples` samples per pixel, viewed as `format`. sRGB targets also
+/// get a texture of the same format to resolve into on the way to the swap chain.
+fn create_msaa_target(
+    device: &ID3D11Device,
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    samples: u32,
+) -> Result<MsaaTarget> {
+    let quality_levels = unsafe { device.CheckMultisampleQualityLevels(format, samples) }?;
+    if quality_levels == 0 {
+        return Err(Error::new(
+            E_INVALIDARG,
+            format!(
+                "{}x multisampling isn't supported for {:?}",
+                samples, format
+            ),
+        ));
+    }
+
+    let desc = D3D11_TEXTURE2D_DESC {
+        Width: width.max(1),
+        Height: height.max(1),
+        MipLevels: 1,
+        ArraySize: 1,
+        Format: format,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: samples,
+            Quality: 0,
+        },
+        Usage: D3D11_USAGE_DEFAULT,
+        BindFlags: D3D11_BIND_RENDER_TARGET.0 as u32,
+        CPUAccessFlags: 0,
+        MiscFlags: 0,
+    };
+    let rtv_desc = D3D11_RENDER_TARGET_VIEW_DESC {
+        Format: format,
+        ViewDimension: D3D11_RTV_DIMENSION_TEXTURE2DMS,
+        ..Default::default()
+    };
+
+    unsafe {
+        let mut texture = None;
+        device.CreateTexture2D(&desc, None, Some(&mut texture))?;
+        let texture = texture.ok_or(E_POINTER)?;
+
+        let mut rtv = None;
+        device.CreateRenderTargetView(&texture, Some(&rtv_desc), Some(&mut rtv))?;
+
+        let mut srgb_resolve = None;
+        if format == DXGI_FORMAT_B8G8R8A8_UNORM_SRGB {
+            let resolve_desc = D3D11_TEXTURE2D_DESC {
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                BindFlags: 0,
+                ..desc
+            };
+            device.CreateTexture2D(&resolve_desc, None, Some(&mut srgb_resolve))?;
+        }
+
+        Ok(MsaaTarget {
+            texture,
+            rtv: rtv.ok_or(E_POINTER)?,
+            srgb_resolve,
+        })
+    }
+}
+
+fn create_history_buffer(device: &ID3D11Device, width: u32, height: u32) -> Result<HistoryBuffer> {
+    let element_count = width.max(1) * height.max(1);
+    unsafe {
+        let buffer_desc = D3D11_BUFFER_DESC {
+            ByteWidth: element_count * HISTORY_ELEMENT_SIZE,
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_UNORDERED_ACCESS.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: D3D11_RESOURCE_MISC_BUFFER_STRUCTURED.0 as u32,
+            StructureByteStride: HISTORY_ELEMENT_SIZE,
+        };
+
+        let mut buffer_out = None;
+        device.CreateBuffer(&buffer_desc, None, Some(&mut buffer_out))?;
+        let buffer = buffer_out.ok_or(E_POINTER)?;
+
+        let uav_desc = D3D11_UNORDERED_ACCESS_VIEW_DESC {
+            Format: DXGI_FORMAT_UNKNOWN,
+            ViewDimension: D3D11_UAV_DIMENSION_BUFFER,
+            Anonymous: D3D11_UNORDERED_ACCESS_VIEW_DESC_0 {
+                Buffer: D3D11_BUFFER_UAV {
+                    FirstElement: 0,
+                    NumElements: element_count,
+                    Flags: 0,
+                },
+            },
+        };
+
+        let mut uav_out = None;
+        device.CreateUnorderedAccessView(&buffer, Some(&uav_desc), Some(&mut uav_out))?;
+        let uav = uav_out.ok_or(E_POINTER)?;
+
+        println!(
+            "Created history buffer: {}x{} ({} bytes)",
+            width,
+            height,
+            element_count * HISTORY_ELEMENT_SIZE
+        );
+
+        Ok(HistoryBuffer {
+            _buffer: buffer,
+            uav,
+        })
+    }
+}
+
+/// Follow the window to its new position, and rebuild size-dependent resources if it was resized
+pub(crate) fn window_changed(state: &mut CaptureState, hwnd: HWND, resized: bool) {
+    // Update screen position (presentation mode always shows the whole output, and a fixed
+    // source stays where it was put)
+    if !state.presentation && !state.fixed_source {
+        state.source_rect = client_rect_in_physical_pixels(hwnd);
+    }
+
+    if resized {
+        state.render_target_view = None;
+        state.staging_texture = None; // Recreate on size change
+        state.extended_texture = None; // Recreate on size change
+        state.extended_srv = None;
+        state.extended_uav = None;
+        release_shader_targets(state);
+        if let Err(e) = resize_swapchain(state, hwnd) {
+            println!("Failed to resize swap chain: {:?}", e);
+        }
+    }
+}
+
+/// Start capture over after the desktop changed under it, such as a resolution change. The
+/// duplication is recreated with the output's new size, position and rotation, and everything
+/// sized from the old frames is rebuilt.
+pub(crate) fn reset_capture(state: &mut CaptureState) {
+    // Every window sharing the duplication lets go of it, not only this one
+    if let Some(shared) = state.duplication.take() {
+        shared.release_lost();
+    }
+    if state.gdi_capture.take().is_some()
+        && let Err(e) = start_gdi_capture(state)
+    {
+        println!("Failed to restart GDI capture: {:?}", e);
+    }
+    state.staging_texture = None;
+    state.shader_resource_view = None;
+    state.extended_texture = None;
+    state.extended_srv = None;
+    state.extended_uav = None;
+    release_shader_targets(state);
+}
+
+/// Drop everything sized to the shader pass, to be recreated at the next frame's size
+fn release_shader_targets(state: &mut CaptureState) {
+    state.pass_targets.clear();
+    state.ssaa_target = None;
+    state.msaa_target = None;
+    state.effect_output = None;
+    state.motion_blur_accumulation = None;
+    state.picker_thumbnail = None;
+    for config in state.pixel_shaders.iter_mut() {
+        if let ShaderType::History { history, .. } = &mut config.shader_type {
+            *history = None; // Layout depends on the render size
+        }
+    }
+}
+
+/// Capture `rect` from now on, wherever the window is
+fn set_fixed_source(state: &mut CaptureState, rect: RECT) {
+    state.source_rect = rect;
+    state.fixed_source = true;
+    // Both are sized to the captured region
+    state.staging_texture = None;
+    state.extended_texture = None;
+    state.extended_srv = None;
+    state.extended_uav = None;
+    println!(
+        "Capturing fixed region {},{} {}x{}",
+        rect.left,
+        rect.top,
+        rect.right - rect.left,
+        rect.bottom - rect.top
+    );
+}
+
+// Largest width or height of any texture, the swap chain's buffers included
+const MAX_TEXTURE_SIZE: u32 = D3D11_REQ_TEXTURE2D_U_OR_V_DIMENSION;
+
+/// `width` and `height` limited to the largest texture the device can create, which the swap
+/// chain then stretches to fill the window. `what` names the size when it's logged.
+fn clamp_texture_size(width: u32, height: u32, what: &str) -> (u32, u32) {
+    let clamped = (width.min(MAX_TEXTURE_SIZE), height.min(MAX_TEXTURE_SIZE));
+    if clamped != (width, height) {
+        println!(
+            "{} size {}x{} exceeds the {} pixel texture limit, rendering at {}x{}",
+            what, width, height, MAX_TEXTURE_SIZE, clamped.0, clamped.1
+        );
+    }
+    clamped
+}
+
+fn resize_swapchain(state: &mut CaptureState, hwnd: HWND) -> Result<()> {
+    // Release old views
+    state.render_target_view = None;
+    state.shader_resource_view = None;
+
+    unsafe {
+        // Get new size
+        let mut client_rect = RECT::default();
+        GetClientRect(hwnd, &mut client_rect)?;
+        let width = (client_rect.right - client_rect.left) as u32;
+        let height = (client_rect.bottom - client_rect.top) as u32;
+
+        // Minimized windows have an empty client area, which ResizeBuffers rejects. Leave the
+        // buffers alone; the WM_SIZE on restore resizes them and recreates the view.
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let (width, height) = clamp_texture_size(width, height, "Window");
+
+        // Resize the swap chain
+        state.swap_chain.ResizeBuffers(
+            state.swap_chain_buffers,
+            width,
+            height,
+            state.swap_chain_format,
+            DXGI_SWAP_CHAIN_FLAG(0),
+        )?;
+
+        // Recreate render target view, encoding to sRGB on write when shading in linear light
+        let buffer: ID3D11Texture2D = state.swap_chain.GetBuffer(0)?;
+        let rtv_desc = D3D11_RENDER_TARGET_VIEW_DESC {
+            Format: DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+            ViewDimension: D3D11_RTV_DIMENSION_TEXTURE2D,
+            Anonymous: D3D11_RENDER_TARGET_VIEW_DESC_0 {
+                Texture2D: D3D11_TEX2D_RTV { MipSlice: 0 },
+            },
+        };
+        let mut render_target_view = None;
+        state.device.CreateRenderTargetView(
+            &buffer,
+            state.linear.then_some(&rtv_desc as *const _),
+            Some(&mut render_target_view),
+        )?;
+        state.render_target_view = render_target_view;
+    }
+    Ok(())
+}
+
+/// Copy the source region out of `texture`, an output-sized frame, and render it. `rotation`
+/// is how the output's display is rotated; duplicated frames of a rotated display are stored
+/// in its unrotated layout. `dirty` is what changed in `texture` since the previous frame, if
+/// known.
+fn handle_frame(
+    state: &mut CaptureState,
+    texture: &ID3D11Texture2D,
+    rotation: DXGI_MODE_ROTATION,
+    dirty: Option<&[RECT]>,
+    hwnd: HWND,
+) -> Result<()> {
+    // No render target while minimized, nothing to draw until restored
+    if state.render_target_view.is_none() {
+        return Ok(());
+    }
+    state.new_frame = true;
+    state.frame_serial += 1;
+    state.advance_time();
+    // Other sources don't say when their image was presented, so count it as now
+    if state.duplication.is_none() {
+        let _ = unsafe { QueryPerformanceCounter(&mut state.present_qpc) };
+        state.present_count += 1;
+    }
+
+    unsafe {
+        // Size of the captured region in physical pixels
+        let region_width = state.source_rect.right - state.source_rect.left;
+        let region_height = state.source_rect.bottom - state.source_rect.top;
+        // No texture can be larger than the device allows, so a region beyond that is cropped
+        let width = region_width.min(MAX_TEXTURE_SIZE as i32);
+        let height = region_height.min(MAX_TEXTURE_SIZE as i32);
+        let cropped = width != region_width || height != region_height;
+
+        // Get screen texture dimensions
+        let mut screen_desc = D3D11_TEXTURE2D_DESC::default();
+        texture.GetDesc(&mut screen_desc);
+
+        // Quarter turns from the texture to the desktop, and the output's size on the desktop
+        let quarter_turns = quarter_turns(rotation);
+        let (output_width, output_height) = if quarter_turns % 2 == 1 {
+            (screen_desc.Height as i32, screen_desc.Width as i32)
+        } else {
+            (screen_desc.Width as i32, screen_desc.Height as i32)
+        };
+
+        // Calculate source box in the duplicated output's texture space (may extend beyond it)
+        let (
+            RECT {
+                left: src_left,
+                top: src_top,
+                right: src_right,
+                bottom: src_bottom,
+            },
+            RECT {
+                left: extend_left,
+                top: extend_top,
+                right: extend_right,
+                bottom: extend_bottom,
+            },
+        ) = source_box(
+            state.source_rect,
+            state.output_origin,
+            (width, height),
+            (output_width, output_height),
+        );
+
+        // Scale the extended texture down by the render scale, and further when it would
+        // still be larger than the cap
+        let unscaled_width = (width + extend_left + extend_right) as f32;
+        let unscaled_height = (height + extend_top + extend_bottom) as f32;
+        let mut scale = state.render_scale;
+        if let Some(max_pixels) = state.max_capture_pixels {
+            let pixels = unscaled_width * unscaled_height * scale * scale;
+            if pixels > max_pixels as f32 {
+                scale *= (max_pixels as f32 / pixels).sqrt();
+            }
+        }
+        // The padding can take the extended texture past the device's limit too
+        scale = scale
+            .min(MAX_TEXTURE_SIZE as f32 / unscaled_width)
+            .min(MAX_TEXTURE_SIZE as f32 / unscaled_height);
+        let capped = cropped || scale < state.render_scale;
+        if capped != state.capture_capped {
+            state.capture_capped = capped;
+            if capped {
+                println!(
+                    "Captured region of {}x{} exceeds the size limits, rendering {}x{} of it \
+                     at {:.0}%",
+                    region_width,
+                    region_height,
+                    width,
+                    height,
+                    scale * 100.0
+                );
+            } else {
+                println!("Captured region is within the size limits again");
+            }
+        }
+
+        // Calculate extended texture size
+        let extended_width = ((unscaled_width * scale).round() as u32).max(1);
+        let extended_height = ((unscaled_height * scale).round() as u32).max(1);
+
+        // The changed rects in the shader input, worked out only when it's a plain crop and
+        // scale of the frame
+        let plain = extend_left == 0
+            && extend_top == 0
+            && extend_right == 0
+            && extend_bottom == 0
+            && !state.flip_h
+            && !state.flip_v
+            && quarter_turns == 0;
+        let mapping = (state.source_rect, state.output_origin, scale);
+        let same_mapping = state.dirty_mapping.replace(mapping) == Some(mapping);
+        state.dirty_rects = dirty.filter(|_| plain && same_mapping).map(|rects| {
+            rects
+                .iter()
+                .filter_map(|rect| {
+                    let left = ((rect.left - src_left) as f32 * scale).floor().max(0.0);
+                    let top = ((rect.top - src_top) as f32 * scale).floor().max(0.0);
+                    let right = ((rect.right - src_left) as f32 * scale)
+                        .ceil()
+                        .min(extended_width as f32);
+                    let bottom = ((rect.bottom - src_top) as f32 * scale)
+                        .ceil()
+                        .min(extended_height as f32);
+                    (right > left && bottom > top).then_some(RECT {
+                        left: left as i32,
+                        top: top as i32,
+                        right: right as i32,
+                        bottom: bottom as i32,
+                    })
+                })
+                .collect()
+        });
+
+        // Fully on-screen at full scale, the extend pass would be a plain copy, so skip it and
+        // let the pixel shader read the staging texture directly
+        let needs_extend = extend_left != 0
+            || extend_top != 0
+            || extend_right != 0
+            || extend_bottom != 0
+            || scale != 1.0
+            || state.flip_h
+            || state.flip_v
+            || quarter_turns != 0;
+
+        // Drop the extended texture if the extents changed since it was made
+        if let Some(extended_tex) = &state.extended_texture {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            extended_tex.GetDesc(&mut desc);
+            if desc.Width != extended_width || desc.Height != extended_height {
+                state.extended_texture = None;
+                state.extended_srv = None;
+                state.extended_uav = None;
+            }
+        }
+
+        // Staging holds the region in the frame's own layout, turned on its side for a
+        // display rotated a quarter turn
+        let (staging_width, staging_height) = if quarter_turns % 2 == 1 {
+            (height as u32, width as u32)
+        } else {
+            (width as u32, height as u32)
+        };
+        if let Some(staging) = &state.staging_texture {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            staging.GetDesc(&mut desc);
+            if desc.Width != staging_width || desc.Height != staging_height {
+                state.staging_texture = None;
+                state.shader_resource_view = None;
+            }
+        }
+
+        // Create staging texture if needed (matches window size, since copies can't rescale;
+        // the render scale is applied by the extend pass). GDI compatible so the cursor can be
+        // drawn into it.
+        if state.staging_texture.is_none() {
+            let desc = D3D11_TEXTURE2D_DESC {
+                Width: staging_width,
+                Height: staging_height,
+                MipLevels: 1,
+                ArraySize: 1,
+                Format: state.source_format(),
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Usage: D3D11_USAGE_DEFAULT,
+                BindFlags: (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_RENDER_TARGET.0) as u32,
+                CPUAccessFlags: 0,
+                MiscFlags: D3D11_RESOURCE_MISC_GDI_COMPATIBLE.0 as u32,
+            };
+
+            let mut texture_out = None;
+            state
+                .device
+                .CreateTexture2D(&desc, None, Some(&mut texture_out))?;
+            state.staging_texture = texture_out;
+        }
+
+        // Create extended texture if needed
+        if needs_extend && state.extended_texture.is_none() {
+            let desc = D3D11_TEXTURE2D_DESC {
+                Width: extended_width,
+                Height: extended_height,
+                MipLevels: 1,
+                ArraySize: 1,
+                Format: state.extended_format(),
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Usage: D3D11_USAGE_DEFAULT,
+                BindFlags: (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_UNORDERED_ACCESS.0) as u32,
+                CPUAccessFlags: 0,
+                MiscFlags: 0,
+            };
+
+            let mut texture_out = None;
+            state
+                .device
+                .CreateTexture2D(&desc, None, Some(&mut texture_out))?;
+            state.extended_texture = texture_out;
+
+            // Create UAV for compute shader output
+            let extended_tex = state.extended_texture.as_ref().unwrap();
+            let uav_desc = D3D11_UNORDERED_ACCESS_VIEW_DESC {
+                Format: state.extended_format(),
+                ViewDimension: D3D11_UAV_DIMENSION_TEXTURE2D,
+                Anonymous: D3D11_UNORDERED_ACCESS_VIEW_DESC_0 {
+                    Texture2D: D3D11_TEX2D_UAV { MipSlice: 0 },
+                },
+            };
+
+            let mut uav_out = None;
+            state.device.CreateUnorderedAccessView(
+                extended_tex,
+                Some(&uav_desc),
+                Some(&mut uav_out),
+            )?;
+            state.extended_uav = uav_out;
+
+            // Create SRV for the extended texture
+            let srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+                Format: state.extended_format(),
+                ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2D,
+                Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+                    Texture2D: D3D11_TEX2D_SRV {
+                        MostDetailedMip: 0,
+                        MipLevels: 1,
+                    },
+                },
+            };
+
+            let mut srv_out = None;
+            state.device.CreateShaderResourceView(
+                extended_tex,
+                Some(&srv_desc),
+                Some(&mut srv_out),
+            )?;
+            state.extended_srv = srv_out;
+        }
+
+        // Clamp source box to valid screen coordinates
+        let clamped_left = src_left.max(0).min(output_width);
+        let clamped_top = src_top.max(0).min(output_height);
+        let clamped_right = src_right.max(0).min(output_width);
+        let clamped_bottom = src_bottom.max(0).min(output_height);
+
+        // Copy the valid region to staging texture
+        let dst_texture = state.staging_texture.as_ref().unwrap();
+
+        if clamped_right > clamped_left && clamped_bottom > clamped_top {
+            // The same region in the frame's unrotated layout
+            let unrotated = unrotated_rect(
+                quarter_turns,
+                (output_width, output_height),
+                RECT {
+                    left: clamped_left,
+                    top: clamped_top,
+                    right: clamped_right,
+                    bottom: clamped_bottom,
+                },
+            );
+            let src_box = D3D11_BOX {
+                left: unrotated.left as u32,
+                top: unrotated.top as u32,
+                front: 0,
+                right: unrotated.right as u32,
+                bottom: unrotated.bottom as u32,
+                back: 1,
+            };
+
+            // Destination offset should be zero - we're copying to a window-sized texture
+            // The extension happens in the compute shader
+            let dst_x = 0;
+            let dst_y = 0;
+
+            state.context.CopySubresourceRegion(
+                dst_texture,
+                0,
+                dst_x,
+                dst_y,
+                0,
+                texture,
+                0,
+                Some(&src_box),
+            );
+        }
+
+        // GDI can only draw the cursor upright, which would be on its side in a turned frame
+        if state.include_cursor && quarter_turns == 0 {
+            let origin = POINT {
+                x: state.output_origin.x + clamped_left,
+                y: state.output_origin.y + clamped_top,
+            };
+            if let Err(e) = composite_cursor(dst_texture, origin) {
+                println!("Failed to draw the cursor: {:?}", e);
+            }
+        }
+
+        // Create SRV for staging texture if needed
+        if state.shader_resource_view.is_none() {
+            let srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+                Format: state.source_format(),
+                ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2D,
+                Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+                    Texture2D: D3D11_TEX2D_SRV {
+                        MostDetailedMip: 0,
+                        MipLevels: 1,
+                    },
+                },
+            };
+
+            let mut srv_out = None;
+            state.device.CreateShaderResourceView(
+                dst_texture,
+                Some(&srv_desc),
+                Some(&mut srv_out),
+            )?;
+            state.shader_resource_view = srv_out;
+        }
+
+        // Run compute shader to extend the texture with edge padding
+        if needs_extend {
+            // Unbind pixel shader resources to avoid hazards
+            state.context.PSSetShaderResources(0, Some(&[None]));
+
+            let params = ExtendParams {
+                // Only the part of the region on the output was copied
+                src_size: [
+                    (clamped_right - clamped_left).max(1) as u32,
+                    (clamped_bottom - clamped_top).max(1) as u32,
+                ],
+                dst_size: [extended_width, extended_height],
+                src_offset: [extend_left, extend_top],
+                scale,
+                flip: if state.flip_h { FLIP_HORIZONTAL } else { 0 }
+                    | if state.flip_v { FLIP_VERTICAL } else { 0 },
+                rotation: quarter_turns,
+                edge_mode: match state.edge_mode {
+                    EdgeMode::Clamp => 0,
+                    EdgeMode::Mirror => 1,
+                    EdgeMode::Wrap => 2,
+                    EdgeMode::Fill => 3,
+                },
+                padding: [0; 2],
+                fill_color: {
+                    let [mut r, mut g, mut b, a] = state.edge_fill;
+                    if state.linear {
+                        // Written as is, so the encode on output gives back the chosen color
+                        [r, g, b] = [r, g, b].map(srgb_to_linear);
+                    }
+                    [r, g, b, a]
+                },
+            };
+
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            state.context.Map(
+                &state.extend_params_buffer,
+                0,
+                D3D11_MAP_WRITE_DISCARD,
+                0,
+                Some(&mut mapped),
+            )?;
+            std::ptr::copy_nonoverlapping(
+                &params as *const ExtendParams as *const u8,
+                mapped.pData as *mut u8,
+                std::mem::size_of::<ExtendParams>(),
+            );
+            state.context.Unmap(&state.extend_params_buffer, 0);
+
+            state.context.CSSetShader(&state.compute_shader, None);
+            state
+                .context
+                .CSSetConstantBuffers(0, Some(&[Some(state.extend_params_buffer.clone())]));
+            state.context.CSSetShaderResources(
+                0,
+                Some(&[Some(state.shader_resource_view.as_ref().unwrap().clone())]),
+            );
+            state.context.CSSetUnorderedAccessViews(
+                0,
+                1,
+                Some(&Some(state.extended_uav.as_ref().unwrap().clone())),
+                None,
+            );
+
+            let dispatch_x = extended_width.div_ceil(8);
+            let dispatch_y = extended_height.div_ceil(8);
+            state.context.Dispatch(dispatch_x, dispatch_y, 1);
+
+            // Clear compute shader resources
+            state.context.CSSetShader(None, None);
+            state.context.CSSetShaderResources(0, Some(&[None]));
+            state
+                .context
+                .CSSetUnorderedAccessViews(0, 1, Some(&None), None);
+        }
+
+        // Input the pixel shaders sample at t0: extended texture, or staging when no
+        // extension was needed
+        let srv = if needs_extend {
+            state.extended_srv.clone()
+        } else {
+            state.shader_resource_view.clone()
+        };
+        state.last_frame = srv.map(|srv| CapturedFrame {
+            srv,
+            width: extended_width,
+            height: extended_height,
+        });
+    }
+
+    let rendered = render_frame(state, hwnd);
+    // Anything drawn before the next capture is a redraw
+    state.new_frame = false;
+
+    // Only duplication says when the desktop presented the image, other sources count it as
+    // captured just now
+    if rendered.is_ok()
+        && state.duplication.is_some()
+        && let Some(age) = state.present_age()
+    {
+        if state.latency_samples.len() == LATENCY_SAMPLES {
+            state.latency_samples.pop_front();
+        }
+        state.latency_samples.push_back((age * 1000.0) as f32);
+    }
+    rendered
+}
+
+/// Run the current shader over the last captured frame and present it
+fn render_frame(state: &mut CaptureState, hwnd: HWND) -> Result<()> {
+    let Some(rtv) = state.render_target_view.clone() else {
+        return Ok(());
+    };
+    let Some(frame) = state.last_frame.clone() else {
+        return Ok(());
+    };
+
+    unsafe {
+        // Get current window size, limited like the swap chain's buffers are
+        let mut client_rect = RECT::default();
+        GetClientRect(hwnd, &mut client_rect)?;
+        let target_width = ((client_rect.right - client_rect.left) as u32).min(MAX_TEXTURE_SIZE);
+        let target_height = ((client_rect.bottom - client_rect.top) as u32).min(MAX_TEXTURE_SIZE);
+
+        // The picker stands in for the effect while it's open
+        if let Some(highlighted) = state.picker {
+            draw_picker(
+                state,
+                &rtv,
+                &frame,
+                highlighted,
+                target_width,
+                target_height,
+            )?;
+        } else {
+            // Supersampling draws the shader pass into a larger offscreen target first
+            if state.ssaa_factor > 1 && state.ssaa_target.is_none() {
+                match create_pass_target(
+                    &state.device,
+                    target_width * state.ssaa_factor,
+                    target_height * state.ssaa_factor,
+                    state.pass_format(),
+                ) {
+                    Ok(target) => state.ssaa_target = Some(target),
+                    Err(e) => {
+                        println!(
+                            "Failed to allocate {}x supersampling target, falling back to 1x: {:?}",
+                            state.ssaa_factor, e
+                        );
+                        state.ssaa_factor = 1;
+                    }
+                }
+            }
+            let (shader_rtv, render_width, render_height) = match &state.ssaa_target {
+                Some(target) => (
+                    target.rtv.clone(),
+                    target_width * state.ssaa_factor,
+                    target_height * state.ssaa_factor,
+                ),
+                None => (rtv.clone(), target_width, target_height),
+            };
+
+            if state.msaa_samples > 1 && state.msaa_target.is_none() {
+                match create_msaa_target(
+                    &state.device,
+                    render_width,
+                    render_height,
+                    state.pass_format(),
+                    state.msaa_samples,
+                ) {
+                    Ok(target) => state.msaa_target = Some(target),
+                    Err(e) => {
+                        println!(
+                            "Failed to allocate {}x multisampling target, falling back to none: {:?}",
+                            state.msaa_samples, e
+                        );
+                        state.msaa_samples = 1;
+                    }
+                }
+            }
+            // Motion blur and color adjustment need the shader pass's output to sample, so it
+            // draws offscreen for them
+            let mut fresh_accumulation = false;
+            if state.motion_blur > 0.0 && state.motion_blur_accumulation.is_none() {
+                // Half floats, so small differences between frames still add up when the
+                // decay is close to 1 instead of rounding away
+                state.motion_blur_accumulation = Some(create_pass_target(
+                    &state.device,
+                    render_width,
+                    render_height,
+                    DXGI_FORMAT_R16G16B16A16_FLOAT,
+                )?);
+                fresh_accumulation = true;
+            }
+            let post_process = state.motion_blur > 0.0 || state.adjust_colors;
+            if post_process && state.effect_output.is_none() {
+                state.effect_output = Some(create_pass_target(
+                    &state.device,
+                    render_width,
+                    render_height,
+                    state.pass_format(),
+                )?);
+            }
+            let effect_output = state
+                .effect_output
+                .as_ref()
+                .filter(|_| post_process)
+                .map(|target| (target.rtv.clone(), target.texture.clone()));
+            let msaa_rtv = state.msaa_target.as_ref().map(|target| target.rtv.clone());
+            let pass_rtv = match &effect_output {
+                Some((rtv, _)) => rtv.clone(),
+                None => shader_rtv.clone(),
+            };
+
+            draw_shader(
+                state,
+                state.current_shader,
+                &frame,
+                msaa_rtv.as_ref().unwrap_or(&pass_rtv),
+                render_width,
+                render_height,
+            )?;
+
+            // Average the samples into whichever target the shader pass would have drawn to
+            if let Some(msaa_target) = &state.msaa_target {
+                let destination = match (&effect_output, &state.ssaa_target) {
+                    (Some((_, texture)), _) => Some(texture.clone()),
+                    (None, Some(target)) => Some(target.texture.clone()),
+                    (None, None) => None,
+                };
+                match (destination, &msaa_target.srgb_resolve) {
+                    (Some(destination), _) => state.context.ResolveSubresource(
+                        &destination,
+                        0,
+                        &msaa_target.texture,
+                        0,
+                        state.pass_format(),
+                    ),
+                    // Resolving straight into the plain swap chain buffer would average the
+                    // encoded values, so resolve as sRGB and copy the bytes across
+                    (None, Some(srgb_resolve)) => {
+                        state.context.ResolveSubresource(
+                            srgb_resolve,
+                            0,
+                            &msaa_target.texture,
+                            0,
+                            DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+                        );
+                        let back_buffer = state.swap_chain.GetBuffer::<ID3D11Texture2D>(0)?;
+                        state.context.CopyResource(&back_buffer, srgb_resolve);
+                    }
+                    (None, None) => state.context.ResolveSubresource(
+                        &state.swap_chain.GetBuffer::<ID3D11Texture2D>(0)?,
+                        0,
+                        &msaa_target.texture,
+                        0,
+                        state.swap_chain_format,
+                    ),
+                }
+            }
+
+            if effect_output.is_some() {
+                post_process_effect(
+                    state,
+                    &shader_rtv,
+                    render_width,
+                    render_height,
+                    fresh_accumulation,
+                )?;
+            }
+
+            // Filter the supersampled result down to the window
+            if let Some(ssaa_target) = &state.ssaa_target {
+                state
+                    .context
+                    .OMSetRenderTargets(Some(&[Some(rtv.clone())]), None);
+                let viewport = D3D11_VIEWPORT {
+                    TopLeftX: 0.0,
+                    TopLeftY: 0.0,
+                    Width: target_width as f32,
+                    Height: target_height as f32,
+                    MinDepth: 0.0,
+                    MaxDepth: 1.0,
+                };
+                state.context.RSSetViewports(Some(&[viewport]));
+                let constants = SupersampleConstants {
+                    factor: state.ssaa_factor,
+                    gaussian: (state.ssaa_filter == SsaaFilter::Gaussian) as u32,
+                    padding: [0; 2],
+                };
+                let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+                state.context.Map(
+                    &state.supersample_constants_buffer,
+                    0,
+                    D3D11_MAP_WRITE_DISCARD,
+                    0,
+                    Some(&mut mapped),
+                )?;
+                std::ptr::copy_nonoverlapping(
+                    &constants as *const _ as *const u8,
+                    mapped.pData as *mut u8,
+                    std::mem::size_of::<SupersampleConstants>(),
+                );
+                state.context.Unmap(&state.supersample_constants_buffer, 0);
+                state.context.PSSetShader(&state.supersample_shader, None);
+                state.context.PSSetConstantBuffers(
+                    0,
+                    Some(&[Some(state.supersample_constants_buffer.clone())]),
+                );
+                state
+                    .context
+                    .PSSetShaderResources(0, Some(&[Some(ssaa_target.srv.clone())]));
+                state.context.Draw(4, 0);
+                // Unbind so the next frame can render into it again
+                state.context.PSSetShaderResources(0, Some(&[None]));
+            }
+
+            // Before the shared output, so comparisons can be streamed too
+            if let Some(divider) = state.split_view {
+                draw_split_view(
+                    state,
+                    &rtv,
+                    &frame.srv,
+                    divider,
+                    target_width,
+                    target_height,
+                )?;
+            }
+        }
+
+        // Hand the finished frame to other apps before it's presented
+        #[cfg(feature = "shared-output")]
+        if let Some(shared_output) = &mut state.shared_output {
+            let back_buffer: ID3D11Texture2D = state.swap_chain.GetBuffer(0)?;
+            if let Err(e) = shared_output.publish(&state.device, &state.context, &back_buffer) {
+                println!("Failed to publish shared output: {:?}", e);
+            }
+        }
+
+        // Only on screen, so it's left out of the saved and shared frames
+        if let Some(seconds) = state.screenshot_countdown {
+            draw_countdown(state, &rtv, target_width, seconds)?;
+        }
+        if state.show_latency {
+            draw_latency(state, &rtv, target_height)?;
+        }
+        if state.help {
+            draw_help(state, &rtv, target_height)?;
+        }
+        state.overlay_drawn = state.show_latency || state.help;
+
+        // Present, noting whether anything on screen can still see the result
+        let present = state.swap_chain.Present(1, DXGI_PRESENT(0));
+        present.ok()?;
+        let occluded = present == DXGI_STATUS_OCCLUDED;
+        if occluded && !state.occluded {
+            println!("Window occluded, pausing rendering");
+        }
+        state.occluded = occluded;
+        state.frames_since_title += 1;
+
+        //InvalidateRect(hwnd, None, false);
+    }
+    Ok(())
+}
+
+/// Take the shader pass's output from `effect_output` to `target`. With motion blur it's
+/// blended into the accumulation first, keeping `motion_blur` of what was there, and the
+/// accumulation is what's taken; a `fresh` accumulation is overwritten instead, so the blur
+/// doesn't fade in from black. The color adjustments are applied on the way to `target`.
+fn post_process_effect(
+    state: &CaptureState,
+    target: &ID3D11RenderTargetView,
+    width: u32,
+    height: u32,
+    fresh: bool,
+) -> Result<()> {
+    let Some(frame) = &state.effect_output else {
+        return Ok(());
+    };
+    unsafe {
+        state.context.RSSetViewports(Some(&[D3D11_VIEWPORT {
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+            Width: width as f32,
+            Height: height as f32,
+            MinDepth: 0.0,
+            MaxDepth: 1.0,
+        }]));
+        // Everything but the adjustment is a plain copy, blended or not
+        state.context.PSSetShader(&state.downsample_shader, None);
+        state.context.PSSetSamplers(
+            0,
+            Some(&[Some(state.samplers[SamplerMode::Clamp as usize].clone())]),
+        );
+
+        let mut source = frame.srv.clone();
+        if let Some(accumulation) = &state.motion_blur_accumulation
+            && state.motion_blur > 0.0
+        {
+            state
+                .context
+                .OMSetRenderTargets(Some(&[Some(accumulation.rtv.clone())]), None);
+            if !fresh {
+                let decay = state.motion_blur;
+                state.context.OMSetBlendState(
+                    &state.blend_factor_state,
+                    Some(&[decay, decay, decay, decay]),
+                    0xFFFF_FFFF,
+                );
+            }
+            state.context.PSSetShaderResources(0, Some(&[Some(source)]));
+            state.context.Draw(4, 0);
+            state.context.OMSetBlendState(None, None, 0xFFFF_FFFF);
+            state.context.PSSetShaderResources(0, Some(&[None]));
+            source = accumulation.srv.clone();
+        }
+
+        if state.adjust_colors {
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            state.context.Map(
+                &state.adjust_constants_buffer,
+                0,
+                D3D11_MAP_WRITE_DISCARD,
+                0,
+                Some(&mut mapped),
+            )?;
+            std::ptr::copy_nonoverlapping(
+                state.color_adjustments.as_ptr(),
+                mapped.pData as *mut f32,
+                state.color_adjustments.len(),
+            );
+            state.context.Unmap(&state.adjust_constants_buffer, 0);
+            state.context.PSSetShader(&state.adjust_shader, None);
+            state
+                .context
+                .PSSetConstantBuffers(0, Some(&[Some(state.adjust_constants_buffer.clone())]));
+        }
+        state
+            .context
+            .OMSetRenderTargets(Some(&[Some(target.clone())]), None);
+        state.context.PSSetShaderResources(0, Some(&[Some(source)]));
+        state.context.Draw(4, 0);
+        // Unbind so the next frame can render into it again
+        state.context.PSSetShaderResources(0, Some(&[None]));
+    }
+    Ok(())
+}
+
+impl ShaderType {
+    /// The tiles effect's constants for `frame`, with the update pass covering the whole grid
+    fn tiles_constants(&self, frame: &CapturedFrame) -> Option<TilesConstants> {
+        let ShaderType::Tiles {
+            sheet_width,
+            sheet_height,
+            layout,
+            curves,
+            current_curve,
+            luma,
+            columns,
+            ..
+        } = self
+        else {
+            return None;
+        };
+
+        // A fixed column count spreads each glyph over more (or fewer) source pixels, with as
+        // many rows as keep the glyphs their own shape
+        let source_resolution = match columns {
+            Some(columns) => {
+                let grid_width = (*columns * layout.tile_width) as f32;
+                let glyph_scale = frame.width as f32 / grid_width;
+                let rows = (frame.height as f32 / (layout.tile_height as f32 * glyph_scale))
+                    .round()
+                    .max(1.0);
+                [grid_width, rows * layout.tile_height as f32]
+            }
+            None => [frame.width as f32, frame.height as f32],
+        };
+
+        Some(TilesConstants {
+            source_resolution,
+            tile_size: [layout.tile_width as f32, layout.tile_height as f32],
+            tiles_per_row: layout.cells_per_row as i32,
+            total_tiles: layout.cells.len() as i32,
+            spritesheet_resolution: [*sheet_width as f32, *sheet_height as f32],
+            glyph_padding: layout.padding as f32,
+            curve_gamma: curves[*current_curve].gamma,
+            padding: [0.0; 2],
+            curve_points: curves[*current_curve].points,
+            luma_weights: {
+                let [r, g, b] = luma.weights();
+                [r, g, b, 0.0]
+            },
+            grid_size: [
+                (source_resolution[0] / layout.tile_width as f32).ceil() as u32,
+                (source_resolution[1] / layout.tile_height as f32).ceil() as u32,
+            ],
+            grid_offset: [0; 2],
+            // These only affect drawing, so they're left out of what the glyphs depend on
+            overlay_opacity: 0.0,
+            frame_count: 0,
+            frame_rate: 0.0,
+            time: 0.0,
+        })
+    }
+}
+
+/// Bring the tiles shader at `index` up to date with `frame`, choosing glyphs again only for
+/// the tiles whose source changed since they were last chosen
+fn update_tile_cells(state: &mut CaptureState, index: usize, frame: &CapturedFrame) -> Result<()> {
+    let Some(constants) = state.pixel_shaders[index]
+        .shader_type
+        .tiles_constants(frame)
+    else {
+        return Ok(());
+    };
+    let sampler = state.samplers[state.pixel_shaders[index].sampler_mode as usize].clone();
+    let time = state.shader_time();
+    let ShaderType::Tiles {
+        update_shader,
+        brightness_srv,
+        constants_buffer,
+        layout,
+        opacity,
+        frame_count,
+        frame_rate,
+        cells,
+        ..
+    } = &mut state.pixel_shaders[index].shader_type
+    else {
+        return Ok(());
+    };
+
+    // Debug: print constants once
+    if !state.printed_tiles_constants {
+        println!("Tiles shader constants:");
+        println!("  source_resolution: {:?}", constants.source_resolution);
+        println!("  tile_size: {:?}", constants.tile_size);
+        println!("  tiles_per_row: {}", constants.tiles_per_row);
+        println!("  total_tiles: {}", constants.total_tiles);
+        println!(
+            "  spritesheet_resolution: {:?}",
+            constants.spritesheet_resolution
+        );
+        println!("  glyph_padding: {}", constants.glyph_padding);
+        state.printed_tiles_constants = true;
+    }
+
+    let [columns, rows] = constants.grid_size;
+    let whole_grid = vec![[0, 0, columns, rows]];
+    // Tile ranges as left, top, right and bottom, the last two exclusive
+    let ranges = match cells {
+        Some(cells) if cells.constants == constants && cells.source == frame.srv => {
+            if cells.serial == state.frame_serial {
+                // A redraw of the frame the cells were worked out from
+                Vec::new()
+            } else if cells.serial + 1 == state.frame_serial
+                && let Some(rects) = &state.dirty_rects
+                && rects.len() <= MAX_DIRTY_TILE_RANGES
+            {
+                // The tile grid spans the source image, which a fixed column count stretches
+                let tile_width =
+                    layout.tile_width as f32 * frame.width as f32 / constants.source_resolution[0];
+                let tile_height = layout.tile_height as f32 * frame.height as f32
+                    / constants.source_resolution[1];
+                // Each tile's brightness samples reach a little into its neighbours
+                rects
+                    .iter()
+                    .map(|rect| {
+                        [
+                            ((rect.left as f32 / tile_width) as u32).saturating_sub(1),
+                            ((rect.top as f32 / tile_height) as u32).saturating_sub(1),
+                            ((rect.right as f32 / tile_width).ceil() as u32 + 1).min(columns),
+                            ((rect.bottom as f32 / tile_height).ceil() as u32 + 1).min(rows),
+                        ]
+                    })
+                    .collect()
+            } else {
+                whole_grid
+            }
+        }
+        _ => {
+            *cells = Some(Box::new(create_tile_cells(
+                &state.device,
+                constants,
+                frame.srv.clone(),
+            )?));
+            whole_grid
+        }
+    };
+    let cells = cells.as_mut().unwrap();
+    cells.serial = state.frame_serial;
+
+    unsafe {
+        if !ranges.is_empty() {
+            // The pixel shader reads the cells, which can't be bound while written
+            state
+                .context
+                .PSSetShaderResources(0, Some(&[None, None, None]));
+            state.context.CSSetShader(&*update_shader, None);
+            state
+                .context
+                .CSSetConstantBuffers(0, Some(&[Some(constants_buffer.clone())]));
+            state.context.CSSetShaderResources(
+                0,
+                Some(&[Some(frame.srv.clone()), Some(brightness_srv.clone())]),
+            );
+            state.context.CSSetSamplers(0, Some(&[Some(sampler)]));
+            state
+                .context
+                .CSSetUnorderedAccessViews(0, 1, Some(&Some(cells.uav.clone())), None);
+        }
+        for [left, top, right, bottom] in ranges {
+            if right <= left || bottom <= top {
+                continue;
+            }
+            let range_constants = TilesConstants {
+                grid_offset: [left, top],
+                ..constants
+            };
+            write_tiles_constants(&state.context, constants_buffer, &range_constants)?;
+            state
+                .context
+                .Dispatch((right - left).div_ceil(8), (bottom - top).div_ceil(8), 1);
+        }
+        state.context.CSSetShader(None, None);
+        state.context.CSSetShaderResources(0, Some(&[None, None]));
+        state
+            .context
+            .CSSetUnorderedAccessViews(0, 1, Some(&None), None);
+
+        let draw_constants = TilesConstants {
+            overlay_opacity: *opacity,
+            frame_count: *frame_count,
+            frame_rate: *frame_rate,
+            time,
+            ..constants
+        };
+        write_tiles_constants(&state.context, constants_buffer, &draw_constants)
+    }
+}
+
+fn write_tiles_constants(
+    context: &ID3D11DeviceContext,
+    buffer: &ID3D11Buffer,
+    constants: &TilesConstants,
+) -> Result<()> {
+    unsafe {
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        context.Map(buffer, 0, D3D11_MAP_WRITE_DISCARD, 0, Some(&mut mapped))?;
+        std::ptr::copy_nonoverlapping(
+            constants as *const _ as *const u8,
+            mapped.pData as *mut u8,
+            std::mem::size_of::<TilesConstants>(),
+        );
+        context.Unmap(buffer, 0);
+    }
+    Ok(())
+}
+
+/// Per-tile glyph buffer for a grid of `constants.grid_size`, to be filled from `source`
+fn create_tile_cells(
+    device: &ID3D11Device,
+    constants: TilesConstants,
+    source: ID3D11ShaderResourceView,
+) -> Result<TileCells> {
+    let [columns, rows] = constants.grid_size;
+    let element_count = columns.max(1) * rows.max(1);
+    let element_size = std::mem::size_of::<u32>() as u32;
+    unsafe {
+        let buffer_desc = D3D11_BUFFER_DESC {
+            ByteWidth: element_count * element_size,
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_UNORDERED_ACCESS.0) as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: D3D11_RESOURCE_MISC_BUFFER_STRUCTURED.0 as u32,
+            StructureByteStride: element_size,
+        };
+
+        let mut buffer_out = None;
+        device.CreateBuffer(&buffer_desc, None, Some(&mut buffer_out))?;
+        let buffer = buffer_out.ok_or(E_POINTER)?;
+
+        let uav_desc = D3D11_UNORDERED_ACCESS_VIEW_DESC {
+            Format: DXGI_FORMAT_UNKNOWN,
+            ViewDimension: D3D11_UAV_DIMENSION_BUFFER,
+            Anonymous: D3D11_UNORDERED_ACCESS_VIEW_DESC_0 {
+                Buffer: D3D11_BUFFER_UAV {
+                    FirstElement: 0,
+                    NumElements: element_count,
+                    Flags: 0,
+                },
+            },
+        };
+        let mut uav_out = None;
+        device.CreateUnorderedAccessView(&buffer, Some(&uav_desc), Some(&mut uav_out))?;
+
+        let mut srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+            Format: DXGI_FORMAT_UNKNOWN,
+            ViewDimension: D3D11_SRV_DIMENSION_BUFFER,
+            Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+                Buffer: std::mem::zeroed(),
+            },
+        };
+        srv_desc.Anonymous.Buffer.Anonymous1.FirstElement = 0;
+        srv_desc.Anonymous.Buffer.Anonymous2.NumElements = element_count;
+        let mut srv_out = None;
+        device.CreateShaderResourceView(&buffer, Some(&srv_desc), Some(&mut srv_out))?;
+
+        println!("Created tile glyph buffer: {}x{} tiles", columns, rows);
+
+        Ok(TileCells {
+            buffer,
+            srv: srv_out.ok_or(E_POINTER)?,
+            uav: uav_out.ok_or(E_POINTER)?,
+            constants,
+            source,
+            serial: 0,
+        })
+    }
+}
+
+/// Draw the shader at `index` over `frame` into `target`, which is `width` by `height`
+fn draw_shader(
+    state: &mut CaptureState,
+    index: usize,
+    frame: &CapturedFrame,
+    target: &ID3D11RenderTargetView,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let source_srv = Some(frame.srv.clone());
+    unsafe {
+        // Set up rendering pipeline
+        state
+            .context
+            .OMSetRenderTargets(Some(&[Some(target.clone())]), None);
+
+        // update time buffer
+        let bindings = state.pixel_shaders[index].bindings;
+        let mut frame_constants = FrameConstants {
+            time: state.shader_time(),
+            resolution: [width as f32, height as f32],
+            pass: 0,
+            params: [0.0; MAX_SHADER_PARAMS],
+            present_time: state.present_time(),
+            present_count: state.present_count as u32,
+            new_frame: state.new_frame as u32,
+            padding: 0,
+        };
+        {
+            for (slot, param) in frame_constants
+                .params
+                .iter_mut()
+                .zip(&state.pixel_shaders[index].params)
+            {
+                *slot = param.value;
+            }
+            write_frame_constants(&state.context, &state.time_buffer, &frame_constants)?;
+
+            state.context.PSSetConstantBuffers(
+                bindings.frame_constants,
+                Some(&[Some(state.time_buffer.clone())]),
+            );
+        }
+
+        {
+            let viewport = D3D11_VIEWPORT {
+                TopLeftX: 0.0,
+                TopLeftY: 0.0,
+                Width: width as f32,
+                Height: height as f32,
+                MinDepth: 0.0,
+                MaxDepth: 1.0,
+            };
+            state.context.RSSetViewports(Some(&[viewport]));
+        };
+
+        // Clear render target, in the premultiplied form composition expects when transparent
+        let [mut r, mut g, mut b, a] = state.clear_color;
+        if state.linear {
+            // Clears are written as is, so the encode on output gives back the chosen color
+            [r, g, b] = [r, g, b].map(srgb_to_linear);
+        }
+        let clear_color = match &state.composition {
+            Some(_) => [r * a, g * a, b * a, a],
+            None => [r, g, b, a],
+        };
+        state.context.ClearRenderTargetView(target, &clear_color);
+
+        // Set shaders and resources, with the address mode the effect asked for. An effect's
+        // own vertex shader gets the frame constants too.
+        let vertex_stage = state.pixel_shaders[index].vertex_stage.clone();
+        match &vertex_stage {
+            Some(stage) => {
+                state.context.VSSetShader(&stage.shader, None);
+                state
+                    .context
+                    .VSSetConstantBuffers(0, Some(&[Some(state.time_buffer.clone())]));
+            }
+            None => state.context.VSSetShader(&state.vertex_shader, None),
+        }
+        let sampler_mode = state.pixel_shaders[index].sampler_mode;
+        state.context.PSSetSamplers(
+            bindings.sampler,
+            Some(&[Some(state.samplers[sampler_mode as usize].clone())]),
+        );
+
+        // Allocate shader-owned per-pixel history on first use at the current size
+        if let ShaderType::History { history, .. } = &mut state.pixel_shaders[index].shader_type
+            && history.is_none()
+        {
+            let history_buffer = create_history_buffer(&state.device, width, height)?;
+            // Start from a blank history rather than whatever the allocation held
+            state
+                .context
+                .ClearUnorderedAccessViewUint(&history_buffer.uav, &[0; 4]);
+            *history = Some(history_buffer);
+        }
+
+        if matches!(
+            state.pixel_shaders[index].shader_type,
+            ShaderType::Tiles { .. }
+        ) {
+            update_tile_cells(state, index, frame)?;
+        }
+
+        // Bind resources based on shader type
+        let mut history_bound = false;
+        match &state.pixel_shaders[index].shader_type {
+            ShaderType::Simple(shader) => {
+                state.context.PSSetShader(shader, None);
+                // Extended texture, or staging when no extension was needed
+                state
+                    .context
+                    .PSSetShaderResources(bindings.source, Some(std::slice::from_ref(&source_srv)));
+            }
+            ShaderType::Tiles {
+                shader,
+                spritesheet_srv,
+                constants_buffer,
+                cells,
+                ..
+            } => {
+                state.context.PSSetShader(shader, None);
+
+                // t0 = source to overlay, t1 = spritesheet, t2 = each tile's glyph
+                state.context.PSSetShaderResources(
+                    0,
+                    Some(&[
+                        source_srv.clone(),
+                        Some(spritesheet_srv.clone()),
+                        cells.as_ref().map(|cells| cells.srv.clone()),
+                    ]),
+                );
+
+                // Bind constant buffer to b0
+                state
+                    .context
+                    .PSSetConstantBuffers(0, Some(&[Some(constants_buffer.clone())]));
+            }
+            ShaderType::History {
+                shader,
+                constants_buffer,
+                decay,
+                history,
+            } => {
+                state.context.PSSetShader(shader, None);
+                state
+                    .context
+                    .PSSetShaderResources(bindings.source, Some(std::slice::from_ref(&source_srv)));
+                let history_uav = history.as_ref().unwrap().uav.clone();
+
+                let constants = HistoryConstants {
+                    time: state.shader_time(),
+                    decay: *decay,
+                    resolution: [width, height],
+                };
+
+                let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+                state.context.Map(
+                    constants_buffer,
+                    0,
+                    D3D11_MAP_WRITE_DISCARD,
+                    0,
+                    Some(&mut mapped),
+                )?;
+                std::ptr::copy_nonoverlapping(
+                    &constants as *const _ as *const u8,
+                    mapped.pData as *mut u8,
+                    std::mem::size_of::<HistoryConstants>(),
+                );
+                state.context.Unmap(constants_buffer, 0);
+
+                state
+                    .context
+                    .PSSetConstantBuffers(0, Some(&[Some(constants_buffer.clone())]));
+
+                // UAV slots share numbering with render targets, so u0 is taken by the RTV
+                state.context.OMSetRenderTargetsAndUnorderedAccessViews(
+                    Some(&[Some(target.clone())]),
+                    None,
+                    1,
+                    1,
+                    Some(&Some(history_uav)),
+                    None,
+                );
+                history_bound = true;
+            }
+        }
+
+        // Bind the shader's user-supplied texture (gradient map, noise, LUT...) to t3
+        let user_texture_srv = match &state.pixel_shaders[index].user_texture {
+            Some(path) => state
+                .user_textures
+                .entry(path.clone())
+                .or_insert_with(
+                    || match load_image_from_file(&state.device, path, state.linear) {
+                        Ok((_texture, srv, ..)) => Some(srv),
+                        Err(e) => {
+                            println!("Failed to load user texture: {:?}", e);
+                            None
+                        }
+                    },
+                )
+                .clone(),
+            None => None,
+        };
+        state
+            .context
+            .PSSetShaderResources(bindings.user_texture, Some(&[user_texture_srv]));
+
+        // And its sidecar data, if any, to t4
+        let data_srv = state.pixel_shaders[index].data_srv.clone();
+        state
+            .context
+            .PSSetShaderResources(bindings.data, Some(&[data_srv]));
+
+        // And the frozen snapshot to t5, empty until one is taken
+        state.context.PSSetShaderResources(
+            bindings.snapshot,
+            Some(std::slice::from_ref(&state.snapshot)),
+        );
+
+        // Set vertex buffer
+        let stride = std::mem::size_of::<Vertex>() as u32;
+        let offset = 0;
+        state.context.IASetVertexBuffers(
+            0,
+            1,
+            Some(&Some(state.vertex_buffer.clone())),
+            Some(&stride),
+            Some(&offset),
+        );
+        state
+            .context
+            .IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP);
+
+        state.context.IASetInputLayout(match &vertex_stage {
+            Some(stage) => &stage.input_layout,
+            None => &state.input_layout,
+        });
+
+        // Simple shaders may run several passes, each sampling the previous pass's output
+        let passes = match &state.pixel_shaders[index].shader_type {
+            ShaderType::Simple(_) => state.pixel_shaders[index].passes,
+            _ => 1,
+        };
+        if passes > 1 && state.pass_targets.is_empty() {
+            for _ in 0..2 {
+                state.pass_targets.push(create_pass_target(
+                    &state.device,
+                    width,
+                    height,
+                    state.pass_format(),
+                )?);
+            }
+        }
+
+        // Draw
+        for pass in 0..passes {
+            if passes > 1 {
+                // Intermediate passes alternate between the offscreen targets, the last
+                // one draws to the window (or the supersampling target)
+                let target = if pass + 1 == passes {
+                    target.clone()
+                } else {
+                    state.pass_targets[pass as usize % 2].rtv.clone()
+                };
+                // Unbind the previous input first, it may be the texture we're about to draw to
+                state
+                    .context
+                    .PSSetShaderResources(bindings.source, Some(&[None]));
+                state
+                    .context
+                    .OMSetRenderTargets(Some(&[Some(target)]), None);
+                if pass > 0 {
+                    let previous = state.pass_targets[(pass as usize - 1) % 2].srv.clone();
+                    state
+                        .context
+                        .PSSetShaderResources(bindings.source, Some(&[Some(previous)]));
+                    frame_constants.pass = pass;
+                    write_frame_constants(&state.context, &state.time_buffer, &frame_constants)?;
+                }
+            }
+            state.context.Draw(4, 0);
+        }
+        if passes > 1 {
+            state
+                .context
+                .PSSetShaderResources(bindings.source, Some(&[None]));
+        }
+
+        // Later draws are all fullscreen passthroughs
+        if vertex_stage.is_some() {
+            state.context.VSSetShader(&state.vertex_shader, None);
+            state.context.IASetInputLayout(&state.input_layout);
+        }
+
+        // Release the history UAV so it isn't left bound when switching shaders
+        if history_bound {
+            state.context.OMSetRenderTargetsAndUnorderedAccessViews(
+                Some(&[Some(target.clone())]),
+                None,
+                1,
+                1,
+                Some(&None),
+                None,
+            );
+        }
+    }
+    Ok(())
+}
+
+const GLYPH_SCALE: u32 = 4;
+// Gap between overlay text and the edges of the window
+const TEXT_MARGIN: u32 = 16;
+
+/// Draw the seconds left before the delayed screenshot in the top right corner
+fn draw_countdown(
+    state: &CaptureState,
+    rtv: &ID3D11RenderTargetView,
+    target_width: u32,
+    seconds: u32,
+) -> Result<()> {
+    let text = seconds.to_string();
+    let glyph_width = state.font_layout.tile_width * GLYPH_SCALE;
+    let left = target_width.saturating_sub(TEXT_MARGIN + glyph_width * text.len() as u32);
+    draw_text(state, rtv, &text, left, TEXT_MARGIN, GLYPH_SCALE)
+}
+
+/// The average capture latency in the bottom left corner
+fn draw_latency(
+    state: &CaptureState,
+    rtv: &ID3D11RenderTargetView,
+    target_height: u32,
+) -> Result<()> {
+    let text = if state.latency_samples.is_empty() {
+        "Latency: no duplicated frames".to_string()
+    } else {
+        let samples = state.latency_samples.len();
+        let average = state.latency_samples.iter().sum::<f32>() / samples as f32;
+        format!("Latency: {:.1} ms (last {} frames)", average, samples)
+    };
+    let top =
+        target_height.saturating_sub(TEXT_MARGIN + state.font_layout.tile_height * GLYPH_SCALE);
+    draw_text(state, rtv, &text, TEXT_MARGIN, top, GLYPH_SCALE)
+}
+
+/// List every hotkey over the output, in text as large as fits the window's height
+fn draw_help(state: &CaptureState, rtv: &ID3D11RenderTargetView, target_height: u32) -> Result<()> {
+    let mut lines = vec![
+        "Hotkeys, any key closes".to_string(),
+        String::new(),
+        format!("{:<16}Select a shader", "1-9"),
+    ];
+    lines.extend(
+        HOTKEYS
+            .iter()
+            .map(|hotkey| format!("{:<16}{}", hotkey.name(), hotkey.action)),
+    );
+
+    let line_height = state.font_layout.tile_height;
+    let scale = ((target_height.saturating_sub(TEXT_MARGIN * 2))
+        / (line_height * lines.len() as u32))
+        .clamp(1, 2);
+    for (index, line) in lines.iter().enumerate() {
+        let top = TEXT_MARGIN + line_height * scale * index as u32;
+        draw_text(state, rtv, line, TEXT_MARGIN, top, scale)?;
+    }
+    Ok(())
+}
+
+/// Clear the window and present `message` in place of a frame, for when there's nothing
+/// captured to show
+fn present_waiting_screen(state: &mut CaptureState, message: &str) -> Result<()> {
+    let Some(rtv) = state.render_target_view.clone() else {
+        return Ok(());
+    };
+    unsafe {
+        // Nothing may have been drawn yet to leave the pipeline set up for the quad
+        let stride = std::mem::size_of::<Vertex>() as u32;
+        let offset = 0;
+        state.context.IASetVertexBuffers(
+            0,
+            1,
+            Some(&Some(state.vertex_buffer.clone())),
+            Some(&stride),
+            Some(&offset),
+        );
+        state
+            .context
+            .IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP);
+        state.context.IASetInputLayout(&state.input_layout);
+        state.context.VSSetShader(&state.vertex_shader, None);
+
+        state
+            .context
+            .ClearRenderTargetView(&rtv, &[0.0, 0.0, 0.0, 1.0]);
+        draw_text(state, &rtv, message, TEXT_MARGIN, TEXT_MARGIN, 2)?;
+        state.swap_chain.Present(1, DXGI_PRESENT(0)).ok()
+    }
+}
+
+/// Draw `text` on one line from the font spritesheet, with its top left corner at `left`,
+/// `top` and each glyph `scale` times its size in the sheet
+fn draw_text(
+    state: &CaptureState,
+    rtv: &ID3D11RenderTargetView,
+    text: &str,
+    left: u32,
+    top: u32,
+    scale: u32,
+) -> Result<()> {
+    let layout = &state.font_layout;
+    let glyph_width = layout.tile_width * scale;
+    let glyph_height = layout.tile_height * scale;
+
+    unsafe {
+        state
+            .context
+            .OMSetRenderTargets(Some(&[Some(rtv.clone())]), None);
+        state.context.PSSetShader(&state.glyph_shader, None);
+        state
+            .context
+            .PSSetShaderResources(0, Some(&[Some(state.font_srv.clone())]));
+        state
+            .context
+            .PSSetConstantBuffers(0, Some(&[Some(state.glyph_constants_buffer.clone())]));
+
+        for (i, ch) in text.bytes().enumerate() {
+            // The embedded sheet holds printable ASCII from the space character
+            let (x, y) = layout.cell_origin((ch.clamp(b' ', b'~') - b' ') as u32);
+            let constants = GlyphConstants {
+                glyph_origin: [x, y],
+                glyph_size: [layout.tile_width, layout.tile_height],
+            };
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            state.context.Map(
+                &state.glyph_constants_buffer,
+                0,
+                D3D11_MAP_WRITE_DISCARD,
+                0,
+                Some(&mut mapped),
+            )?;
+            std::ptr::copy_nonoverlapping(
+                &constants as *const _ as *const u8,
+                mapped.pData as *mut u8,
+                std::mem::size_of::<GlyphConstants>(),
+            );
+            state.context.Unmap(&state.glyph_constants_buffer, 0);
+
+            let viewport = D3D11_VIEWPORT {
+                TopLeftX: (left + glyph_width * i as u32) as f32,
+                TopLeftY: top as f32,
+                Width: glyph_width as f32,
+                Height: glyph_height as f32,
+                MinDepth: 0.0,
+                MaxDepth: 1.0,
+            };
+            state.context.RSSetViewports(Some(&[viewport]));
+            state.context.Draw(4, 0);
+        }
+        state.context.PSSetShaderResources(0, Some(&[None]));
+    }
+    Ok(())
+}
+
+/// Balances a successful CoInitializeEx with CoUninitialize when dropped
+pub(crate) struct ComInitScope {
+    initialized: bool,
+}
+
+impl ComInitScope {
+    pub(crate) fn new() -> Result<Self> {
+        let hr = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+        if hr == RPC_E_CHANGED_MODE {
+            // Someone already initialized this thread as an STA, which WIC and D3D are fine with.
+            // That call owns the matching CoUninitialize, not us.
+            println!("COM already initialized in a different mode, continuing");
+            return Ok(Self { initialized: false });
+        }
+        // S_FALSE (already initialized the same way) still needs balancing
+        hr.ok()?;
+        Ok(Self { initialized: true })
+    }
+}
+
+impl Drop for ComInitScope {
+    fn drop(&mut self) {
+        if self.initialized {
+            unsafe { CoUninitialize() };
+        }
+    }
+}
+
+/// Desktop duplication of one output, shared by every window on the device capturing it.
+/// An output can only be duplicated once per device, so windows take turns with it.
+///
+/// A duplication hands out one frame at a time, and the frame's texture is only valid until
+/// it's released. The frame is kept acquired after the window that acquired it has drawn, so
+/// the other windows can each copy their own source region out of it when they paint. It's
+/// released just before the next frame is acquired, which happens when a window that has
+/// already drawn it wants a newer one, or when the last window lets go of the duplication.
+/// A window that paints less often than the others simply misses the frames in between.
+/// Everything happens on the thread that owns the windows, so nothing is released mid-copy.
+///
+/// When access is lost the duplication is released right away, for every window holding it,
+/// so the output is free to duplicate again. Each window then lets go of this one when it
+/// next paints and joins, or creates, its replacement.
+pub(crate) struct SharedDuplication {
+    // `None` once access is lost
+    duplication: RefCell<Option<IDXGIOutputDuplication>>,
+    output_index: u32,
+    // Where the output is on the desktop, which its frames are relative to
+    desktop_coordinates: RECT,
+    // How the output's display is rotated, its frames arrive unrotated
+    rotation: DXGI_MODE_ROTATION,
+    // The acquired frame, `None` between releasing one and acquiring the next
+    held: RefCell<Option<HeldFrame>>,
+    // Counts acquired frames, and the ones among them with a new desktop image
+    serial: Cell<u64>,
+    image_serial: Cell<u64>,
+    // When the latest image was presented, and every present so far including ones
+    // between acquired frames
+    present_qpc: Cell<i64>,
+    present_count: Cell<u64>,
+}
+
+struct HeldFrame {
+    // Absent for some frames, seen around desktop switches
+    texture: Option<ID3D11Texture2D>,
+    // What changed since the previous frame, `None` when the duplication couldn't say
+    dirty: Option<Vec<RECT>>,
+}
+
+impl SharedDuplication {
+    /// The live duplication of `output_index` in `registry`, or a new one added to it
+    fn get_or_create(
+        registry: &DuplicationRegistry,
+        adapter: &IDXGIAdapter,
+        device: &ID3D11Device,
+        output_index: u32,
+    ) -> Result<Rc<Self>> {
+        let mut registry = registry.borrow_mut();
+        registry.retain(|shared| shared.strong_count() > 0);
+        if let Some(shared) = registry
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|shared| shared.output_index == output_index && !shared.is_lost())
+        {
+            return Ok(shared);
+        }
+
+        let output = unsafe { adapter.EnumOutputs(output_index) }?;
+        let duplication = unsafe { output.cast::<IDXGIOutput1>()?.DuplicateOutput(device) }?;
+        let shared = Rc::new(Self {
+            rotation: unsafe { duplication.GetDesc() }.Rotation,
+            duplication: RefCell::new(Some(duplication)),
+            output_index,
+            desktop_coordinates: unsafe { output.GetDesc() }?.DesktopCoordinates,
+            held: RefCell::new(None),
+            serial: Cell::new(0),
+            image_serial: Cell::new(0),
+            present_qpc: Cell::new(0),
+            present_count: Cell::new(0),
+        });
+        registry.push(Rc::downgrade(&shared));
+        Ok(shared)
+    }
+
+    /// Release the held frame, if any, and wait up to `timeout_millis` for the next one.
+    /// Returns false on timeout.
+    fn next_frame(&self, timeout_millis: u32) -> Result<bool> {
+        self.release_held()?;
+        let Some(duplication) = self.duplication.borrow().clone() else {
+            return Err(DXGI_ERROR_ACCESS_LOST.into());
+        };
+
+        let mut frame_resource = None;
+        let mut frame_info = DXGI_OUTDUPL_FRAME_INFO::default();
+        let acquired = unsafe {
+            duplication.AcquireNextFrame(timeout_millis, &mut frame_info, &mut frame_resource)
+        };
+        match acquired {
+            Ok(()) => {}
+            Err(e) if e.code() == DXGI_ERROR_WAIT_TIMEOUT => return Ok(false),
+            Err(e) => {
+                if e.code() == DXGI_ERROR_ACCESS_LOST {
+                    self.release_lost();
+                }
+                return Err(e);
+            }
+        }
+
+        let texture = frame_resource.and_then(|resource| resource.cast().ok());
+        self.serial.set(self.serial.get() + 1);
+        let mut dirty = Some(Vec::new());
+        if frame_info.LastPresentTime != 0 {
+            self.image_serial.set(self.image_serial.get() + 1);
+            self.present_qpc.set(frame_info.LastPresentTime);
+            self.present_count
+                .set(self.present_count.get() + frame_info.AccumulatedFrames as u64);
+            dirty = Self::changed_rects(&duplication, frame_info.TotalMetadataBufferSize);
+        }
+        *self.held.borrow_mut() = Some(HeldFrame { texture, dirty });
+        Ok(true)
+    }
+
+    /// The rects of the just acquired frame that differ from the previous one, including
+    /// where moved content landed
+    fn changed_rects(
+        duplication: &IDXGIOutputDuplication,
+        metadata_size: u32,
+    ) -> Option<Vec<RECT>> {
+        if metadata_size == 0 {
+            return None;
+        }
+        unsafe {
+            let mut moves = vec![
+                DXGI_OUTDUPL_MOVE_RECT::default();
+                metadata_size as usize
+                    / std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>()
+            ];
+            let mut moves_size = 0;
+            duplication
+                .GetFrameMoveRects(
+                    std::mem::size_of_val(moves.as_slice()) as u32,
+                    moves.as_mut_ptr(),
+                    &mut moves_size,
+                )
+                .ok()?;
+            moves.truncate(moves_size as usize / std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>());
+
+            let mut rects =
+                vec![RECT::default(); metadata_size as usize / std::mem::size_of::<RECT>()];
+            let mut rects_size = 0;
+            duplication
+                .GetFrameDirtyRects(
+                    std::mem::size_of_val(rects.as_slice()) as u32,
+                    rects.as_mut_ptr(),
+                    &mut rects_size,
+                )
+                .ok()?;
+            rects.truncate(rects_size as usize / std::mem::size_of::<RECT>());
+            rects.extend(moves.iter().map(|moved| moved.DestinationRect));
+            Some(rects)
+        }
+    }
+
+    fn release_held(&self) -> Result<()> {
+        // Let go of the frame's surface before returning it to the duplication
+        if self.held.borrow_mut().take().is_some()
+            && let Some(duplication) = &*self.duplication.borrow()
+        {
+            unsafe { duplication.ReleaseFrame() }?;
+        }
+        Ok(())
+    }
+
+    fn is_lost(&self) -> bool {
+        self.duplication.borrow().is_none()
+    }
+
+    /// Release the duplication for every window sharing it, after access to it was lost
+    fn release_lost(&self) {
+        _ = self.release_held();
+        *self.duplication.borrow_mut() = None;
+    }
+}
+
+impl Drop for SharedDuplication {
+    fn drop(&mut self) {
+        _ = self.release_held();
+    }
+}
+
+/// The duplications made on one device, by every window sharing it
+type DuplicationRegistry = Rc<RefCell<Vec<Weak<SharedDuplication>>>>;
+
+/// How long until the current shader's frame rate cap allows another frame, `None` if now
+fn frame_pacing_delay(state: &CaptureState) -> Option<std::time::Duration> {
+    let fps = state.pixel_shaders[state.current_shader].max_fps?;
+    // A cap so low its interval doesn't fit a Duration is as good as none
+    let interval = std::time::Duration::try_from_secs_f32(1.0 / fps).ok()?;
+    interval
+        .checked_sub(state.last_render.elapsed())
+        .filter(|delay| !delay.is_zero())
+}
+
+pub(crate) fn capture_and_render_frame(state: &mut CaptureState, hwnd: HWND) -> Result<()> {
+    // Nothing until the current shader's frame interval has passed, then take the newest frame
+    if frame_pacing_delay(state).is_some() {
+        return Ok(());
+    }
+    state.last_render = std::time::Instant::now();
+
+    // A frozen window shades the frame it froze on, with time still moving
+    if state.frozen && state.last_frame.is_some() {
+        state.advance_time();
+        return render_frame(state, hwnd);
+    }
+
+    if state.test_source.is_some() {
+        return render_test_source(state, hwnd);
+    }
+
+    let result = if state.gdi_capture.is_some() {
+        capture_gdi_frame(state, hwnd)
+    } else {
+        capture_desktop_frame(state, hwnd)
+    };
+    // Keep shading the last good frame while duplication is lost or being recreated, rather
+    // than leaving the window blank until a new frame arrives
+    if (result.is_err() || (state.duplication.is_none() && state.gdi_capture.is_none()))
+        && state.last_frame.is_some()
+        && let Err(e) = render_frame(state, hwnd)
+    {
+        println!("Failed to render held frame: {:?}", e);
+    }
+    result
+}
+
+/// Whether duplication is waiting to be retried with no held frame to shade in the meantime
+fn waiting_for_retry(state: &CaptureState) -> bool {
+    state.last_frame.is_none()
+        && state.duplication.is_none()
+        && state.gdi_capture.is_none()
+        && state
+            .duplication_retry
+            .as_ref()
+            .is_some_and(|retry| std::time::Instant::now() < retry.next_attempt)
+}
+
+fn capture_desktop_frame(state: &mut CaptureState, hwnd: HWND) -> Result<()> {
+    // Another window found the shared duplication lost, let go so it's recreated
+    if state
+        .duplication
+        .as_ref()
+        .is_some_and(|shared| shared.is_lost())
+    {
+        state.duplication = None;
+    }
+
+    if state.duplication.is_none() {
+        if let Some(retry) = &state.duplication_retry
+            && std::time::Instant::now() < retry.next_attempt
+        {
+            // Without a held frame to re-present, say what's going on rather than leave the
+            // window blank. WM_PAINT sees `waiting_for_retry` and polls until the retry is due.
+            if state.last_frame.is_none() && state.waiting_for_display {
+                present_waiting_screen(state, "Waiting for a display...")?;
+            }
+            return Ok(());
+        }
+
+        // Set up screen capture, or join another window's capture of the same output
+        let shared = match SharedDuplication::get_or_create(
+            &state.duplications,
+            &state.dxgi_adapter,
+            &state.device,
+            state.capture_output,
+        ) {
+            Ok(shared) => shared,
+            // Unsupported for good here, rather than held by someone else or failing for now.
+            // An adapter that has never had an output, such as a render-only GPU, won't get one
+            // by waiting either.
+            Err(e)
+                if e.code() == DXGI_ERROR_UNSUPPORTED
+                    || (e.code() == DXGI_ERROR_NOT_FOUND
+                        && !state.had_output
+                        && unsafe { state.dxgi_adapter.EnumOutputs(0) }.is_err()) =>
+            {
+                println!(
+                    "Desktop duplication is unavailable ({:?}), falling back to GDI \
+                     capture, which is much slower",
+                    e
+                );
+                start_gdi_capture(state)?;
+                return capture_gdi_frame(state, hwnd);
+            }
+            // Another duplication client or a fullscreen exclusive app holds the output,
+            // there's no such output right now, or duplicating it failed for some other
+            // reason that may pass, so try again after a while
+            Err(e) => {
+                let missing = e.code() == DXGI_ERROR_NOT_FOUND;
+                let delay = match &state.duplication_retry {
+                    Some(retry) if state.waiting_for_display == missing => {
+                        (retry.delay * 2).min(DUPLICATION_RETRY_MAX)
+                    }
+                    _ if missing => {
+                        println!(
+                            "Output {} doesn't exist, waiting for a display to appear",
+                            state.capture_output
+                        );
+                        DUPLICATION_RETRY_INITIAL
+                    }
+                    _ if e.code() == E_ACCESSDENIED
+                        || e.code() == DXGI_ERROR_NOT_CURRENTLY_AVAILABLE =>
+                    {
+                        println!(
+                            "Output {} is unavailable ({:?}), waiting for display access",
+                            state.capture_output, e
+                        );
+                        DUPLICATION_RETRY_INITIAL
+                    }
+                    _ => {
+                        println!(
+                            "Failed to duplicate output {} ({:?}), trying again",
+                            state.capture_output, e
+                        );
+                        DUPLICATION_RETRY_INITIAL
+                    }
+                };
+                state.waiting_for_display = missing;
+                state.duplication_retry = Some(DuplicationRetry {
+                    next_attempt: std::time::Instant::now() + delay,
+                    delay,
+                });
+                return Ok(());
+            }
+        };
+        state.duplication_retry = None;
+        state.waiting_for_display = false;
+        state.had_output = true;
+        println!(
+            "using dxgi duplication of output {} (rotation {:?}, shared by {} windows)",
+            state.capture_output,
+            shared.rotation,
+            Rc::strong_count(&shared)
+        );
+
+        // Duplicated frames are addressed relative to the output's own top left corner
+        state.output_origin = POINT {
+            x: shared.desktop_coordinates.left,
+            y: shared.desktop_coordinates.top,
+        };
+        state.output_rotation = shared.rotation;
+        if state.presentation && !state.fixed_source {
+            state.source_rect = shared.desktop_coordinates;
+        }
+        // Whatever frame is held now is new to this window
+        state.seen_serial = None;
+        state.duplication = Some(shared);
+    }
+    let shared = state.duplication.clone().unwrap();
+
+    // A frame another window acquired that this one hasn't drawn is used as it is, otherwise
+    // it's time for the next one
+    let holding = shared.held.borrow().is_some();
+    if (!holding || state.seen_serial == Some(shared.serial.get()))
+        && !shared.next_frame(state.latency_mode.timeout_millis())?
+    {
+        return Ok(());
+    }
+
+    let serial = shared.serial.get();
+    let image_serial = shared.image_serial.get();
+    // A cursor drawn into the source needs redrawing when only the pointer moved
+    let new_image = state.seen_image_serial != Some(image_serial)
+        || (state.include_cursor && state.seen_serial != Some(serial));
+    // The duplication only says what changed since the frame before, which is all this
+    // window missed when it drew that one. A drawn-in cursor may have moved anywhere.
+    let dirty = match state.seen_image_serial {
+        Some(seen) if seen + 1 == image_serial && !state.include_cursor => shared
+            .held
+            .borrow()
+            .as_ref()
+            .and_then(|held| held.dirty.clone()),
+        _ => None,
+    };
+    state.seen_serial = Some(serial);
+    state.seen_image_serial = Some(image_serial);
+    if !new_image {
+        return Ok(());
+    }
+    state.present_qpc = shared.present_qpc.get();
+    state.present_count = shared.present_count.get();
+
+    let texture = shared
+        .held
+        .borrow()
+        .as_ref()
+        .and_then(|held| held.texture.clone());
+    match texture {
+        Some(texture) => handle_frame(
+            state,
+            &texture,
+            state.output_rotation,
+            dirty.as_deref(),
+            hwnd,
+        ),
+        None => {
+            println!("Duplicated frame has a new image but no resource, skipping");
+            Ok(())
+        }
+    }
+}
+
+/// Switch the window over to GDI capture of its output, or of the whole desktop when DXGI
+/// doesn't know the output
+fn start_gdi_capture(state: &mut CaptureState) -> Result<()> {
+    let bounds = unsafe {
+        state
+            .dxgi_adapter
+            .EnumOutputs(state.capture_output)
+            .and_then(|output| output.GetDesc())
+    }
+    .map(|desc| desc.DesktopCoordinates)
+    .unwrap_or_else(|_| unsafe {
+        let left = GetSystemMetrics(SM_XVIRTUALSCREEN);
+        let top = GetSystemMetrics(SM_YVIRTUALSCREEN);
+        RECT {
+            left,
+            top,
+            right: left + GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            bottom: top + GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        }
+    });
+
+    state.gdi_capture = Some(GdiCapture::new(&state.device, bounds)?);
+    state.duplication_retry = None;
+    state.output_origin = POINT {
+        x: bounds.left,
+        y: bounds.top,
+    };
+    if state.presentation && !state.fixed_source {
+        state.source_rect = bounds;
+    }
+    Ok(())
+}
+
+fn capture_gdi_frame(state: &mut CaptureState, hwnd: HWND) -> Result<()> {
+    let Some(gdi) = &state.gdi_capture else {
+        return Ok(());
+    };
+
+    // Only the part of the desktop the pipeline copies out of the frame needs refreshing
+    let mut region = RECT::default();
+    let overlaps = unsafe { IntersectRect(&mut region, &state.source_rect, &gdi.bounds) };
+    if overlaps.as_bool() {
+        gdi.capture(&state.context, region)?;
+    }
+
+    let texture = gdi.texture.clone();
+    handle_frame(state, &texture, DXGI_MODE_ROTATION_IDENTITY, None, hwnd)
+}
+
+/// Draw the mouse cursor into `texture`, whose top left pixel is at `origin` on the desktop
+fn composite_cursor(texture: &ID3D11Texture2D, origin: POINT) -> Result<()> {
+    unsafe {
+        let mut info = CURSORINFO {
+            cbSize: std::mem::size_of::<CURSORINFO>() as u32,
+            ..Default::default()
+        };
+        GetCursorInfo(&mut info)?;
+        if info.flags.0 & CURSOR_SHOWING.0 == 0 || info.hCursor.is_invalid() {
+            return Ok(());
+        }
+
+        let cursor = HICON(info.hCursor.0);
+        let mut icon = ICONINFO::default();
+        GetIconInfo(cursor, &mut icon)?;
+        // These are copies made for the call, only the hotspot is needed
+        if !icon.hbmMask.is_invalid() {
+            let _ = DeleteObject(icon.hbmMask.into());
+        }
+        if !icon.hbmColor.is_invalid() {
+            let _ = DeleteObject(icon.hbmColor.into());
+        }
+
+        let surface: IDXGISurface1 = texture.cast()?;
+        let hdc = surface.GetDC(false)?;
+        let drawn = DrawIconEx(
+            hdc,
+            info.ptScreenPos.x - icon.xHotspot as i32 - origin.x,
+            info.ptScreenPos.y - icon.yHotspot as i32 - origin.y,
+            cursor,
+            0,
+            0,
+            0,
+            None,
+            DI_NORMAL,
+        );
+        surface.ReleaseDC(None)?;
+        drawn
+    }
+}
+
+/// Feed a generated or loaded image through the pipeline in place of a captured frame
+fn render_test_source(state: &mut CaptureState, hwnd: HWND) -> Result<()> {
+    let Some(source) = state.test_source.clone() else {
+        return Ok(());
+    };
+
+    if state.test_texture.is_none() {
+        let texture = match &source {
+            TestSource::Image(path) => {
+                let (texture, ..) = load_image_from_file(&state.device, path, false)?;
+                texture
+            }
+            TestSource::ColorBars | TestSource::Gradient => {
+                // Stand in for the output we would have captured, so the window's source
+                // rect maps onto the pattern the same way it would onto the desktop
+                let bounds = unsafe {
+                    state
+                        .dxgi_adapter
+                        .EnumOutputs(state.capture_output)
+                        .and_then(|output| output.GetDesc())
+                }
+                .map(|desc| desc.DesktopCoordinates)
+                .unwrap_or(RECT {
+                    left: 0,
+                    top: 0,
+                    right: 1920,
+                    bottom: 1080,
+                });
+                state.output_origin = POINT {
+                    x: bounds.left,
+                    y: bounds.top,
+                };
+                let width = (bounds.right - bounds.left) as u32;
+                let height = (bounds.bottom - bounds.top) as u32;
+                let pixels = test_pattern_pixels(&source, width, height);
+                if source == TestSource::Gradient {
+                    // The gradient repeats across the width, so a doubled copy holds every
+                    // scroll position
+                    let row = (width * 4) as usize;
+                    let doubled: Vec<u8> = pixels
+                        .chunks_exact(row)
+                        .flat_map(|line| line.iter().chain(line).copied())
+                        .collect();
+                    state.test_gradient = Some(create_test_texture(
+                        &state.device,
+                        width * 2,
+                        height,
+                        &doubled,
+                    )?);
+                }
+                create_test_texture(&state.device, width, height, &pixels)?
+            }
+        };
+        if state.presentation && !state.fixed_source {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { texture.GetDesc(&mut desc) };
+            state.source_rect = RECT {
+                left: state.output_origin.x,
+                top: state.output_origin.y,
+                right: state.output_origin.x + desc.Width as i32,
+                bottom: state.output_origin.y + desc.Height as i32,
+            };
+        }
+        println!("created test source texture");
+        state.test_texture = Some(texture);
+    }
+    let texture = state.test_texture.clone().unwrap();
+
+    if let Some(gradient) = &state.test_gradient {
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+        let time = state.start_time.elapsed().as_secs_f32();
+        let offset = ((time * 0.1).fract() * desc.Width as f32) as u32;
+        let scrolled = D3D11_BOX {
+            left: offset,
+            top: 0,
+            front: 0,
+            right: offset + desc.Width,
+            bottom: desc.Height,
+            back: 1,
+        };
+        unsafe {
+            state
+                .context
+                .CopySubresourceRegion(&texture, 0, 0, 0, 0, gradient, 0, Some(&scrolled));
+        }
+    }
+
+    handle_frame(state, &texture, DXGI_MODE_ROTATION_IDENTITY, None, hwnd)
+}
+
+fn create_test_texture(
+    device: &ID3D11Device,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+) -> Result<ID3D11Texture2D> {
+    let desc = D3D11_TEXTURE2D_DESC {
+        Width: width,
+        Height: height,
+        MipLevels: 1,
+        ArraySize: 1,
+        Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Usage: D3D11_USAGE_DEFAULT,
+        BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+        CPUAccessFlags: 0,
+        MiscFlags: 0,
+    };
+
+    let data = D3D11_SUBRESOURCE_DATA {
+        pSysMem: pixels.as_ptr() as *const _,
+        SysMemPitch: width * 4,
+        SysMemSlicePitch: 0,
+    };
+
+    unsafe {
+        let mut texture_out = None;
+        device.CreateTexture2D(&desc, Some(&data), Some(&mut texture_out))?;
+        texture_out.ok_or(E_POINTER.into())
+    }
+}
+
+/// BGRA pixels for a generated test pattern, the gradient at its starting scroll position
+fn test_pattern_pixels(source: &TestSource, width: u32, height: u32) -> Vec<u8> {
+    // White, yellow, cyan, green, magenta, red, blue, black as BGR
+    const BARS: [[u8; 3]; 8] = [
+        [255, 255, 255],
+        [0, 255, 255],
+        [255, 255, 0],
+        [0, 255, 0],
+        [255, 0, 255],
+        [0, 0, 255],
+        [255, 0, 0],
+        [0, 0, 0],
+    ];
+
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    for (y, row) in pixels.chunks_exact_mut((width * 4) as usize).enumerate() {
+        let fade = 1.0 - 0.8 * y as f32 / height as f32;
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let [b, g, r] = match source {
+                TestSource::Gradient => {
+                    let hue = x as f32 / width as f32;
+                    hue_to_bgr(hue).map(|c| (c as f32 * fade) as u8)
+                }
+                _ => BARS[x * BARS.len() / width as usize],
+            };
+            pixel.copy_from_slice(&[b, g, r, 255]);
+        }
+    }
+    pixels
+}
+
+/// Fully saturated color for a hue in [0, 1), as BGR
+fn hue_to_bgr(hue: f32) -> [u8; 3] {
+    let h = hue * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [(b * 255.0) as u8, (g * 255.0) as u8, (r * 255.0) as u8]
+}
+
+/// The built-in pixel shaders, compiling in the background
+pub(crate) struct EmbeddedShaders {
+    simple: [PendingShader; 4],
+    tiles: PendingShader,
+    heatmap: PendingShader,
+    dither: PendingShader,
+}
+
+impl EmbeddedShaders {
+    /// Start on every shader, taking precompiled bytecode from `shader_dir` where it's there
+    pub(crate) fn spawn(shader_dir: Option<&std::path::Path>) -> Self {
+        let spawn = |name, source, sm5| {
+            PendingShader::spawn(name, source, sm5, shader_dir.map(|dir| dir.to_path_buf()))
+        };
+        Self {
+            simple: [
+                spawn("passthru", PIXEL_SHADER_PASSTHRU, false),
+                spawn("wobbly", PIXEL_SHADER_WOBBLY, false),
+                spawn("lightning", PIXEL_SHADER_LIGHTNING, false),
+                spawn("sorty", PIXEL_SHADER_SORTY, false),
+            ],
+            tiles: spawn("tiles", PIXEL_SHADER_TILES, true),
+            heatmap: spawn("heatmap", PIXEL_SHADER_HEATMAP, true),
+            dither: spawn("dither", PIXEL_SHADER_DITHER, false),
+        }
+    }
+}
+
+/// Pixel shader bytecode compiling on a worker thread, or loading when there's a
+/// precompiled `<name>.cso` for it in the shader directory. Only the selectable shaders
+/// load this way; the built-in passes always compile, so D3DCompiler is still required.
+struct PendingShader {
+    name: &'static str,
+    source: &'static [u8],
+    compile: std::thread::JoinHandle<Result<Vec<u8>>>,
+}
+
+impl PendingShader {
+    /// `sm5` targets shader model 5.0, needed for structured buffers and UAVs
+    fn spawn(
+        name: &'static str,
+        source: &'static [u8],
+        sm5: bool,
+        shader_dir: Option<PathBuf>,
+    ) -> Self {
+        let compile = std::thread::spawn(move || {
+            let precompiled = shader_dir
+                .map(|dir| dir.join(format!("{}.cso", name)))
+                .filter(|path| path.exists());
+            if let Some(path) = precompiled {
+                match load_precompiled_pixel_shader(&path, sm5) {
+                    Ok(bytecode) => {
+                        println!("Loaded {} shader from {}", name, path.display());
+                        return Ok(bytecode);
+                    }
+                    Err(e) => println!(
+                        "Failed to load {}, compiling the {} shader instead: {}",
+                        path.display(),
+                        name,
+                        e.message()
+                    ),
+                }
+            }
+            compile_pixel_shader(source, name, sm5)
+        });
+        Self {
+            name,
+            source,
+            compile,
+        }
+    }
+
+    /// Wait for the bytecode and create the shader, on the thread that owns the device.
+    /// Warns about resources the shader declares that nothing will bind, other than the
+    /// `own_resources` its shader type binds itself.
+    fn finish(
+        self,
+        device: &ID3D11Device,
+        own_resources: &[&str],
+    ) -> Result<(ID3D11PixelShader, ShaderBindings, Rc<[u8]>)> {
+        let bytecode = self.compile.join().map_err(|_| {
+            Error::new(
+                E_FAIL,
+                format!("{} shader compilation thread panicked", self.name),
+            )
+        })??;
+
+        let bindings = match ShaderBindings::reflect(&bytecode) {
+            Ok((bindings, unbound)) => {
+                for resource in unbound
+                    .iter()
+                    .filter(|resource| !own_resources.iter().any(|own| resource.starts_with(own)))
+                {
+                    println!(
+                        "Warning: {} shader declares {}, which nothing binds",
+                        self.name, resource
+                    );
+                }
+                bindings
+            }
+            Err(e) => {
+                println!(
+                    "Failed to reflect {} shader, assuming the usual registers: {:?}",
+                    self.name, e
+                );
+                ShaderBindings::CONVENTIONAL
+            }
+        };
+
+        unsafe {
+            let mut shader_out = None;
+            device.CreatePixelShader(&bytecode, None, Some(&mut shader_out))?;
+            Ok((shader_out.ok_or(E_POINTER)?, bindings, bytecode.into()))
+        }
+    }
+}
+
+/// Read compiled pixel shader bytecode, such as a Ctrl+U dump, checking it's for the pixel
+/// stage and, when `sm5`, shader model 5.0 or later
+fn load_precompiled_pixel_shader(path: &std::path::Path, sm5: bool) -> Result<Vec<u8>> {
+    let bytecode = std::fs::read(path)
+        .map_err(|e| Error::new(E_FAIL, format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let reflection = reflect_shader(&bytecode)?;
+    let mut shader_desc = D3D11_SHADER_DESC::default();
+    unsafe { reflection.GetDesc(&mut shader_desc) }?;
+    // The version packs the stage above the major and minor shader model
+    let stage = D3D11_SHADER_VERSION_TYPE((shader_desc.Version >> 16) as i32);
+    let major = (shader_desc.Version >> 4) & 0xF;
+    let minor = shader_desc.Version & 0xF;
+    if stage != D3D11_SHVER_PIXEL_SHADER {
+        return Err(Error::new(
+            E_INVALIDARG,
+            format!("not a pixel shader (stage {})", stage.0),
+        ));
+    }
+    if sm5 && major < 5 {
+        return Err(Error::new(
+            E_INVALIDARG,
+            format!(
+                "compiled for ps_{}_{}, this shader needs ps_5_0",
+                major, minor
+            ),
+        ));
+    }
+    Ok(bytecode)
+}
+
+fn reflect_shader(bytecode: &[u8]) -> Result<ID3D11ShaderReflection> {
+    unsafe {
+        let mut reflection = std::ptr::null_mut();
+        D3DReflect(
+            bytecode.as_ptr() as *const _,
+            bytecode.len(),
+            &ID3D11ShaderReflection::IID,
+            &mut reflection,
+        )?;
+        Ok(ID3D11ShaderReflection::from_raw(reflection))
+    }
+}
+
+fn compile_compute_shader(
+    device: &ID3D11Device,
+    shader_source: &[u8],
+    name: &str,
+) -> Result<ID3D11ComputeShader> {
+    unsafe {
+        let (shader_blob, error_blob, res) = d3d_compile(
+            shader_source,
+            None,                                            // source name (optional)
+            None,                                            // defines (optional)
+            None,                                            // include handler (optional)
+            s!("main"),                                      // entry point
+            s!("cs_5_0"),                                    // target profile
+            D3DCOMPILE_DEBUG | D3DCOMPILE_SKIP_OPTIMIZATION, // compilation flags
+            0,                                               // secondary flags
+        );
+        println!("{} compute shader compilation complete {:?}", name, res);
+
+        if let Some(error) = error_blob {
+            let error_message =
+                std::str::from_utf8(blob_as_slice(&error)).unwrap_or("Unknown error");
+            println!(
+                "{} compute shader compilation error: {}",
+                name, error_message
+            );
+        }
+
+        res?;
+
+        let Some(blob) = shader_blob else {
+            return Err(Error::new(
+                E_FAIL,
+                format!("Failed to compile {} compute shader", name),
+            ));
+        };
+
+        let mut shader_out = None;
+        device.CreateComputeShader(blob_as_slice(&blob), None, Some(&mut shader_out))?;
+        shader_out.ok_or_else(|| E_POINTER.into())
+    }
+}
+
+fn compile_pixel_shader(shader_source: &[u8], name: &str, sm5: bool) -> Result<Vec<u8>> {
+    unsafe {
+        let (shader_blob, error_blob, res) = d3d_compile(
+            shader_source,
+            None, // source name (optional)
+            None, // defines (optional)
+            None, // include handler (optional)
+            s!("main"),
+            if sm5 { s!("ps_5_0") } else { s!("ps_4_0") },
+            D3DCOMPILE_DEBUG | D3DCOMPILE_SKIP_OPTIMIZATION,
+            0, // secondary flags
+        );
+
+        if let Some(error) = error_blob {
+            let error_message =
+                std::str::from_utf8(blob_as_slice(&error)).unwrap_or("Unknown error");
+            println!("{} shader compilation error: {}", name, error_message);
+        }
+
+        res?;
+
+        let Some(blob) = shader_blob else {
+            return Err(Error::new(
+                E_FAIL,
+                format!("Failed to compile {} pixel shader", name),
+            ));
+        };
+        Ok(blob_as_slice(&blob).to_vec())
+    }
+}
+
+unsafe fn d3d_compile<P0, P1, P2, P3>(
+    sourcedata: &[u8],
+    psourcename: P0,
+    pdefines: Option<*const D3D_SHADER_MACRO>,
+    pinclude: P1,
+    pentrypoint: P2,
+    ptarget: P3,
+    flags1: u32,
+    flags2: u32,
+) -> (Option<ID3DBlob>, Option<ID3DBlob>, Result<()>)
+where
+    P0: windows::core::Param<PCSTR>,
+    P1: windows::core::Param<ID3DInclude>,
+    P2: windows::core::Param<PCSTR>,
+    P3: windows::core::Param<PCSTR>,
+{
+    let mut shader_blob: Option<ID3DBlob> = None;
+    let mut error_blob = None;
+    let res = unsafe {
+        D3DCompile(
+            sourcedata.as_ptr() as *const _,
+            sourcedata.len(),
+            psourcename,
+            pdefines,
+            pinclude,
+            pentrypoint,
+            ptarget,
+            flags1,
+            flags2,
+            &mut shader_blob,
+            Some(&mut error_blob),
+        )
+    };
+    (shader_blob, error_blob, res)
+}
+
+fn blob_as_slice(blob: &ID3DBlob) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_box_spans_dpi_boundary() {
+        // A 1920x1080 monitor at 100% with a 3840x2160 one at 150% to its right, and a region
+        // straddling the boundary. Physical pixels don't change at the boundary, so each
+        // output sees the region's overhang past its own edge and nothing else.
+        let source_rect = RECT {
+            left: 1800,
+            top: 100,
+            right: 2400,
+            bottom: 500,
+        };
+
+        let (src, extend) = source_box(source_rect, POINT { x: 0, y: 0 }, (600, 400), (1920, 1080));
+        assert_eq!(
+            src,
+            RECT {
+                left: 1800,
+                top: 100,
+                right: 2400,
+                bottom: 500,
+            }
+        );
+        assert_eq!(
+            extend,
+            RECT {
+                left: 0,
+                top: 0,
+                right: 480,
+                bottom: 0,
+            }
+        );
+
+        let (src, extend) = source_box(
+            source_rect,
+            POINT { x: 1920, y: 0 },
+            (600, 400),
+            (3840, 2160),
+        );
+        assert_eq!(
+            src,
+            RECT {
+                left: -120,
+                top: 100,
+                right: 480,
+                bottom: 500,
+            }
+        );
+        assert_eq!(
+            extend,
+            RECT {
+                left: 120,
+                top: 0,
+                right: 0,
+                bottom: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn unrotated_rect_for_each_rotation() {
+        // A 1920x1080 output on the desktop, with a region near its top left
+        let output_size = (1920, 1080);
+        let region = RECT {
+            left: 100,
+            top: 200,
+            right: 400,
+            bottom: 300,
+        };
+        let rect = |left, top, right, bottom| RECT {
+            left,
+            top,
+            right,
+            bottom,
+        };
+        let cases = [
+            (DXGI_MODE_ROTATION_IDENTITY, rect(100, 200, 400, 300)),
+            // The frame is 1080x1920, the desktop's left edge along its bottom
+            (DXGI_MODE_ROTATION_ROTATE90, rect(200, 1520, 300, 1820)),
+            (DXGI_MODE_ROTATION_ROTATE180, rect(1520, 780, 1820, 880)),
+            // The frame is 1080x1920, the desktop's left edge along its top
+            (DXGI_MODE_ROTATION_ROTATE270, rect(780, 100, 880, 400)),
+        ];
+        for (rotation, expected) in cases {
+            let turns = quarter_turns(rotation);
+            let unrotated = unrotated_rect(turns, output_size, region);
+            assert_eq!(unrotated, expected, "{:?}", rotation);
+            // The region keeps its size, turned on its side for a quarter turn
+            let size = (
+                unrotated.right - unrotated.left,
+                unrotated.bottom - unrotated.top,
+            );
+            assert_eq!(
+                size,
+                if turns % 2 == 1 {
+                    (100, 300)
+                } else {
+                    (300, 100)
+                }
+            );
+        }
+        assert_eq!(quarter_turns(DXGI_MODE_ROTATION_UNSPECIFIED), 0);
+    }
+
+    #[test]
+    fn premultiply_bgra8_scales_color_by_alpha() {
+        // A half transparent pixel, then an opaque one that must come through unchanged
+        let mut pixels = [200, 100, 50, 128, 200, 100, 50, 255];
+        premultiply_bgra8(&mut pixels);
+        assert_eq!(pixels, [100, 50, 25, 128, 200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn pure_green_tile_brightness_by_standard() {
+        // One 2x2 cell of pure green in BGRA
+        let pixels = [0, 255, 0, 255].repeat(4);
+        let layout = GlyphLayout {
+            tile_width: 2,
+            tile_height: 2,
+            padding: 0,
+            cells_per_row: 1,
+            cells: vec![0],
+        };
+        let brightness = |luma| compute_tile_brightness(&pixels, 2, &layout, luma)[0].brightness;
+
+        let rec601 = brightness(LumaWeights::Rec601);
+        let rec709 = brightness(LumaWeights::Rec709);
+        assert!((rec601 - 0.587).abs() < 1e-6);
+        assert!((rec709 - 0.7152).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,853 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use windows::{Win32::Foundation::E_INVALIDARG, core::*};
+
+/// Options supplied on the command line
+pub struct Config {
+    /// Image bound at t3 for a given shader, as (shader name, path)
+    pub shader_textures: Vec<(String, PathBuf)>,
+    /// DXGI output index to duplicate
+    pub capture_output: u32,
+    /// Desktop region to capture as [x, y, width, height], instead of following the window
+    pub source_rect: Option<[i32; 4]>,
+    /// Show the effect full-screen on this monitor instead of over the captured region
+    pub display_monitor: Option<u32>,
+    /// Stop rendering after this long without user input, `None` to never idle
+    pub idle_timeout: Option<Duration>,
+    /// Gap between glyph cells in the tiles spritesheet, in pixels
+    pub glyph_padding: u32,
+    /// First spritesheet cell used by the tiles effect, counted row-major
+    pub glyph_offset: u32,
+    /// Number of usable glyphs from `glyph_offset`, `None` for the rest of the sheet
+    pub glyph_count: Option<u32>,
+    /// Explicit list of spritesheet cells for the tiles effect, replacing `glyph_offset`
+    /// and `glyph_count`
+    pub glyph_cells: Option<Vec<u32>>,
+    /// Prefer a 10-bit swap chain when the display supports it
+    pub ten_bit: bool,
+    /// Decode sRGB on sampling and encode it on output, so shaders work in linear light
+    pub linear: bool,
+    /// Present through DirectComposition with premultiplied alpha, so transparent output
+    /// shows the desktop through the window
+    pub transparent: bool,
+    /// Back buffers in the swap chain, 2 to 4
+    pub swap_chain_buffers: u32,
+    pub swap_effect: SwapEffect,
+    /// Render from a pattern or image instead of capturing the desktop
+    pub test_source: Option<TestSource>,
+    /// Brightness remap for the tiles effect, in effect at startup
+    pub tiles_curve: Option<BrightnessCurve>,
+    /// How the tiles effect weighs red, green and blue when comparing brightness
+    pub tiles_luma: LumaWeights,
+    /// Glyph columns the tiles effect fits across the window, instead of one glyph per tile
+    /// of source pixels
+    pub tiles_columns: Option<u32>,
+    /// How much the tiles effect's glyphs cover the source, 1 replacing it entirely
+    pub tiles_opacity: f32,
+    /// More spritesheets the tiles effect's glyphs animate through, after the embedded one
+    pub tiles_frames: Vec<PathBuf>,
+    /// Spritesheet frames the tiles effect shows per second
+    pub tiles_frame_rate: f32,
+    /// Hide to the tray when the window is closed, exiting only from the tray menu
+    pub close_to_tray: bool,
+    /// Directory of `<shader>.params.json` files that seed each shader's parameters, and
+    /// `<shader>.cso` bytecode used instead of compiling its source
+    pub shader_dir: Option<PathBuf>,
+    /// Color behind the shader output as RGBA, `None` for opaque black
+    pub clear_color: Option<[f32; 4]>,
+    /// Render the shader pass at this multiple of the window size and downsample, 1 to 4
+    pub ssaa_factor: u32,
+    /// How the supersampled pass is filtered down to the window size
+    pub ssaa_filter: SsaaFilter,
+    /// Samples per pixel the shader pass renders with, 1 (off), 2, 4 or 8
+    pub msaa_samples: u32,
+    /// How much of the previous output each frame keeps for motion blur, 0 for none
+    pub motion_blur: f32,
+    /// Fraction of the window resolution the capture and shader passes start at
+    pub render_scale: f32,
+    /// Most pixels the capture is processed at, scaled down to fit beyond that, `None` for
+    /// no cap
+    pub max_capture_pixels: Option<u32>,
+    /// How the capture is padded where the region runs off the captured output
+    pub edge_mode: EdgeMode,
+    /// Color the fill edge mode pads with, as RGBA
+    pub edge_fill: [f32; 4],
+    /// How shader time advances
+    pub time_mode: TimeMode,
+    /// Advance to the next shader on a timer from startup
+    pub slideshow: bool,
+    /// Seconds each shader shows for in the slideshow
+    pub slideshow_interval: u32,
+    /// Seconds the delayed screenshot hotkey counts down before saving
+    pub screenshot_delay: u32,
+    /// Directory screenshots are saved to, created when needed, `None` for the working
+    /// directory
+    pub screenshot_dir: Option<PathBuf>,
+    /// Confirm each saved screenshot with a beep and/or a white flash of the window
+    pub save_beep: bool,
+    pub save_flash: bool,
+    /// What saved screenshots show where the output is transparent
+    pub save_background: SaveBackground,
+    /// Pausing also clears the window's capture exclusion, so other tools can screenshot it
+    pub pause_makes_capturable: bool,
+    /// Name to publish the rendered output under as a DXGI shared texture
+    #[cfg(feature = "shared-output")]
+    pub shared_output: Option<String>,
+    /// Localhost port to accept JSON commands on, `None` for no command endpoint
+    pub ipc_port: Option<u16>,
+    /// Render with the WARP software rasterizer even when there's a hardware device
+    pub force_warp: bool,
+    /// How Windows started the app when it's installed as a `.scr` screensaver
+    pub screensaver: Option<ScreensaverMode>,
+    /// Print this information and exit instead of opening the window
+    pub list: Option<ListMode>,
+    /// Render each shader offscreen and write or check its golden image, then exit
+    pub golden: Option<GoldenMode>,
+    /// Largest mean difference per channel, out of 255, a golden image check allows
+    pub golden_threshold: f32,
+}
+
+/// Informational modes that print what the other flags can refer to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListMode {
+    /// The shaders, in the order the number keys select them
+    Shaders,
+    /// The outputs `--capture-output` and monitors `--display-monitor` take the index of
+    Monitors,
+}
+
+/// Regression testing of the shaders' output against images committed to a directory
+#[derive(Clone, Debug, PartialEq)]
+pub enum GoldenMode {
+    /// Compare each shader's output with `<dir>/<shader>.png`
+    Check(PathBuf),
+    /// Write each shader's output to `<dir>/<shader>.png`
+    Update(PathBuf),
+}
+
+/// The standard screensaver command lines
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScreensaverMode {
+    /// `/s`: full screen until there's keyboard or mouse input
+    Run,
+    /// `/p <hwnd>`: draw into the preview in the screensaver settings dialog
+    Preview(isize),
+    /// `/c[:hwnd]`: show the settings, owned by the settings dialog when given
+    Configure(Option<isize>),
+}
+
+/// Flip-model swap effect for the swap chain, the legacy blit models aren't offered
+#[derive(Clone, Copy, Debug)]
+pub enum SwapEffect {
+    FlipDiscard,
+    FlipSequential,
+}
+
+/// Stand-in for desktop capture, for machines where duplication is denied
+#[derive(Clone, PartialEq)]
+pub enum TestSource {
+    ColorBars,
+    /// Hue gradient that scrolls over time
+    Gradient,
+    Image(PathBuf),
+}
+
+/// Maps source brightness through `gamma`, then piecewise-linearly through `points`,
+/// the outputs at inputs 0, 1/3, 2/3 and 1
+#[derive(Clone)]
+pub struct BrightnessCurve {
+    pub name: &'static str,
+    pub gamma: f32,
+    pub points: [f32; 4],
+}
+
+impl BrightnessCurve {
+    pub const fn gamma(name: &'static str, gamma: f32) -> Self {
+        Self {
+            name,
+            gamma,
+            points: [0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0],
+        }
+    }
+
+    /// Either a single gamma value or four comma-separated control points
+    fn parse(value: &str, flag: &str) -> Result<Self> {
+        let numbers = value
+            .split(',')
+            .map(|n| parse_float(n.trim(), flag))
+            .collect::<Result<Vec<_>>>()?;
+        match numbers[..] {
+            [gamma] if gamma > 0.0 => Ok(Self::gamma("custom", gamma)),
+            [p0, p1, p2, p3] => Ok(Self {
+                name: "custom",
+                gamma: 1.0,
+                points: [p0, p1, p2, p3],
+            }),
+            _ => Err(invalid_arg(format!(
+                "{} expects a positive gamma or four control points, got '{}'",
+                flag, value
+            ))),
+        }
+    }
+}
+
+/// Padding for the parts of the capture region off the edge of the output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeMode {
+    /// Repeat the nearest edge pixel
+    Clamp,
+    /// Reflect the captured pixels back from the edge
+    Mirror,
+    /// Tile the captured pixels
+    Wrap,
+    /// A solid color, the edge fill
+    Fill,
+}
+
+impl EdgeMode {
+    pub fn next(self) -> Self {
+        match self {
+            EdgeMode::Clamp => EdgeMode::Mirror,
+            EdgeMode::Mirror => EdgeMode::Wrap,
+            EdgeMode::Wrap => EdgeMode::Fill,
+            EdgeMode::Fill => EdgeMode::Clamp,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeMode::Clamp => "clamp",
+            EdgeMode::Mirror => "mirror",
+            EdgeMode::Wrap => "wrap",
+            EdgeMode::Fill => "fill",
+        }
+    }
+}
+
+/// How the supersampled shader pass is filtered down to the window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SsaaFilter {
+    /// Average the block of samples under each pixel
+    Box,
+    /// Weigh the samples by distance from the pixel's center, reaching into its neighbours
+    Gaussian,
+}
+
+impl SsaaFilter {
+    pub fn name(self) -> &'static str {
+        match self {
+            SsaaFilter::Box => "box",
+            SsaaFilter::Gaussian => "gaussian",
+        }
+    }
+}
+
+/// What saved screenshots show where the output is transparent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveBackground {
+    /// Keep the output's alpha in the PNG, as straight alpha
+    Alpha,
+    /// Composite the output over this RGB color, leaving the PNG opaque
+    Color([f32; 3]),
+}
+
+/// Luminance coefficients for turning a color into a single brightness
+#[derive(Clone, Copy, Debug)]
+pub enum LumaWeights {
+    Rec601,
+    /// Matches the primaries of modern (sRGB) displays
+    Rec709,
+    Average,
+}
+
+impl LumaWeights {
+    /// Red, green and blue weights, summing to 1
+    pub fn weights(self) -> [f32; 3] {
+        match self {
+            LumaWeights::Rec601 => [0.299, 0.587, 0.114],
+            LumaWeights::Rec709 => [0.2126, 0.7152, 0.0722],
+            LumaWeights::Average => [1.0 / 3.0; 3],
+        }
+    }
+}
+
+/// How the time shaders see advances from frame to frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeMode {
+    /// The time since startup when the frame is drawn
+    Realtime,
+    /// The wall clock's pace averaged over recent frames, so uneven gaps between frames
+    /// don't make animations judder
+    Smooth,
+    /// Exactly 1/60 of a second per captured frame, for deterministic recordings
+    Fixed,
+}
+
+/// Cells in the largest spritesheet Direct3D 11 loads, at the tiles effect's 8x16 glyph size
+const MAX_GLYPH_CELLS: u32 = (16384 / 8) * (16384 / 16);
+
+/// A glyph per pixel across the widest window Direct3D 11 can render
+const MAX_TILES_COLUMNS: u32 = 16384;
+
+/// Enough for a full 8K display
+const DEFAULT_MAX_CAPTURE_MEGAPIXELS: u32 = 34;
+
+/// Named clear colors, cycled in this order with Ctrl+K
+pub const CLEAR_COLORS: [(&str, [f32; 4]); 4] = [
+    ("black", [0.0, 0.0, 0.0, 1.0]),
+    ("green", [0.0, 1.0, 0.0, 1.0]),
+    ("blue", [0.0, 0.0, 1.0, 1.0]),
+    ("transparent", [0.0, 0.0, 0.0, 0.0]),
+];
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shader_textures: Vec::new(),
+            capture_output: 0,
+            source_rect: None,
+            display_monitor: None,
+            idle_timeout: None,
+            glyph_padding: 0,
+            glyph_offset: 0,
+            glyph_count: None,
+            glyph_cells: None,
+            ten_bit: false,
+            linear: false,
+            transparent: false,
+            swap_chain_buffers: 2,
+            swap_effect: SwapEffect::FlipDiscard,
+            test_source: None,
+            tiles_curve: None,
+            tiles_luma: LumaWeights::Rec601,
+            tiles_columns: None,
+            tiles_opacity: 1.0,
+            tiles_frames: Vec::new(),
+            tiles_frame_rate: 8.0,
+            close_to_tray: false,
+            shader_dir: None,
+            clear_color: None,
+            ssaa_factor: 1,
+            ssaa_filter: SsaaFilter::Box,
+            msaa_samples: 1,
+            motion_blur: 0.0,
+            render_scale: 1.0,
+            max_capture_pixels: Some(DEFAULT_MAX_CAPTURE_MEGAPIXELS * 1_000_000),
+            edge_mode: EdgeMode::Clamp,
+            edge_fill: [0.0, 0.0, 0.0, 1.0],
+            time_mode: TimeMode::Realtime,
+            slideshow: false,
+            slideshow_interval: 30,
+            screenshot_delay: 3,
+            screenshot_dir: None,
+            save_beep: true,
+            save_flash: true,
+            save_background: SaveBackground::Alpha,
+            pause_makes_capturable: true,
+            #[cfg(feature = "shared-output")]
+            shared_output: None,
+            ipc_port: None,
+            force_warp: false,
+            screensaver: None,
+            list: None,
+            golden: None,
+            golden_threshold: 1.0,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> Result<Self> {
+        let mut config = Self::parse(std::env::args().skip(1))?;
+
+        // Windows starts a screensaver without arguments for "Configure" in its context menu
+        let is_scr = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.extension().map(|ext| ext.eq_ignore_ascii_case("scr")))
+            .unwrap_or(false);
+        if is_scr && std::env::args().len() == 1 {
+            config.screensaver = Some(ScreensaverMode::Configure(None));
+        }
+
+        if let Some(mode) = config.screensaver {
+            // Nobody is at the keyboard while a screensaver runs, so it mustn't idle, and the
+            // passthru shader alone wouldn't show anything happening
+            config.idle_timeout = None;
+            config.slideshow = true;
+            config.close_to_tray = false;
+            if mode == ScreensaverMode::Run && config.display_monitor.is_none() {
+                config.display_monitor = Some(0);
+            }
+        }
+        Ok(config)
+    }
+
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--texture" => {
+                    let value = expect_value(&mut args, &arg)?;
+                    let Some((shader, path)) = value.split_once('=') else {
+                        return Err(invalid_arg(format!(
+                            "--texture expects <shader>=<path>, got '{}'",
+                            value
+                        )));
+                    };
+                    config
+                        .shader_textures
+                        .push((shader.to_string(), PathBuf::from(path)));
+                }
+                "--capture-output" => {
+                    config.capture_output = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                }
+                "--source-rect" => {
+                    config.source_rect = Some(parse_rect(&expect_value(&mut args, &arg)?, &arg)?);
+                }
+                "--display-monitor" => {
+                    config.display_monitor =
+                        Some(parse_number(&expect_value(&mut args, &arg)?, &arg)?);
+                }
+                "--idle-timeout" => {
+                    let seconds = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    config.idle_timeout =
+                        (seconds > 0).then(|| Duration::from_secs(seconds as u64));
+                }
+                "--glyph-padding" => {
+                    config.glyph_padding = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                }
+                "--glyph-offset" => {
+                    config.glyph_offset = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                }
+                "--glyph-count" => {
+                    config.glyph_count = Some(parse_number(&expect_value(&mut args, &arg)?, &arg)?);
+                }
+                "--glyphs" => {
+                    config.glyph_cells =
+                        Some(parse_cell_list(&expect_value(&mut args, &arg)?, &arg)?);
+                }
+                "--10bit" => config.ten_bit = true,
+                "--linear" => config.linear = true,
+                "--transparent" => config.transparent = true,
+                "--buffers" => {
+                    let buffers = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !(2..=4).contains(&buffers) {
+                        return Err(invalid_arg(format!(
+                            "{} expects 2 to 4 buffers, got {}",
+                            arg, buffers
+                        )));
+                    }
+                    config.swap_chain_buffers = buffers;
+                }
+                "--swap-effect" => {
+                    config.swap_effect = match expect_value(&mut args, &arg)?.as_str() {
+                        "discard" => SwapEffect::FlipDiscard,
+                        "sequential" => SwapEffect::FlipSequential,
+                        other => {
+                            return Err(invalid_arg(format!(
+                                "{} expects discard or sequential, got '{}'",
+                                arg, other
+                            )));
+                        }
+                    };
+                }
+                "--test-source" => {
+                    config.test_source = Some(match expect_value(&mut args, &arg)?.as_str() {
+                        "bars" => TestSource::ColorBars,
+                        "gradient" => TestSource::Gradient,
+                        path if Path::new(path).is_file() => TestSource::Image(PathBuf::from(path)),
+                        other => {
+                            return Err(invalid_arg(format!(
+                                "{} expects bars, gradient or an image file, got '{}'",
+                                arg, other
+                            )));
+                        }
+                    });
+                }
+                "--tiles-curve" => {
+                    config.tiles_curve = Some(BrightnessCurve::parse(
+                        &expect_value(&mut args, &arg)?,
+                        &arg,
+                    )?);
+                }
+                "--tiles-luma" => {
+                    config.tiles_luma = match expect_value(&mut args, &arg)?.as_str() {
+                        "601" => LumaWeights::Rec601,
+                        "709" => LumaWeights::Rec709,
+                        "average" => LumaWeights::Average,
+                        other => {
+                            return Err(invalid_arg(format!(
+                                "{} expects 601, 709 or average, got '{}'",
+                                arg, other
+                            )));
+                        }
+                    };
+                }
+                "--time-mode" => {
+                    config.time_mode = match expect_value(&mut args, &arg)?.as_str() {
+                        "realtime" => TimeMode::Realtime,
+                        "smooth" => TimeMode::Smooth,
+                        "fixed" => TimeMode::Fixed,
+                        other => {
+                            return Err(invalid_arg(format!(
+                                "{} expects realtime, smooth or fixed, got '{}'",
+                                arg, other
+                            )));
+                        }
+                    };
+                }
+                "--edge-mode" => {
+                    config.edge_mode = match expect_value(&mut args, &arg)?.as_str() {
+                        "clamp" => EdgeMode::Clamp,
+                        "mirror" => EdgeMode::Mirror,
+                        "wrap" => EdgeMode::Wrap,
+                        "fill" => EdgeMode::Fill,
+                        other => {
+                            return Err(invalid_arg(format!(
+                                "{} expects clamp, mirror, wrap or fill, got '{}'",
+                                arg, other
+                            )));
+                        }
+                    };
+                }
+                "--edge-fill" => {
+                    config.edge_fill = parse_color(&expect_value(&mut args, &arg)?, &arg)?;
+                }
+                "--tiles-columns" => {
+                    let columns = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !(1..=MAX_TILES_COLUMNS).contains(&columns) {
+                        return Err(invalid_arg(format!(
+                            "{} expects 1 to {} columns, got {}",
+                            arg, MAX_TILES_COLUMNS, columns
+                        )));
+                    }
+                    config.tiles_columns = Some(columns);
+                }
+                "--tiles-opacity" => {
+                    let opacity = parse_float(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !(0.0..=1.0).contains(&opacity) {
+                        return Err(invalid_arg(format!("{} expects a value from 0 to 1", arg)));
+                    }
+                    config.tiles_opacity = opacity;
+                }
+                "--tiles-frame" => {
+                    config
+                        .tiles_frames
+                        .push(PathBuf::from(expect_value(&mut args, &arg)?));
+                }
+                "--tiles-frame-rate" => {
+                    let fps = parse_float(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !fps.is_finite() || fps <= 0.0 {
+                        return Err(invalid_arg(format!("{} expects a positive rate", arg)));
+                    }
+                    config.tiles_frame_rate = fps;
+                }
+                "--close-to-tray" => config.close_to_tray = true,
+                "--force-warp" => config.force_warp = true,
+                "--pause-stays-excluded" => config.pause_makes_capturable = false,
+                "--list-shaders" => config.list = Some(ListMode::Shaders),
+                "--list-monitors" => config.list = Some(ListMode::Monitors),
+                "--check-golden" => {
+                    config.golden = Some(GoldenMode::Check(PathBuf::from(expect_value(
+                        &mut args, &arg,
+                    )?)));
+                }
+                "--update-golden" => {
+                    config.golden = Some(GoldenMode::Update(PathBuf::from(expect_value(
+                        &mut args, &arg,
+                    )?)));
+                }
+                "--golden-threshold" => {
+                    let threshold = parse_float(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !threshold.is_finite() || threshold < 0.0 {
+                        return Err(invalid_arg(format!(
+                            "{} expects a difference of 0 or more",
+                            arg
+                        )));
+                    }
+                    config.golden_threshold = threshold;
+                }
+                "--shader-dir" => {
+                    config.shader_dir = Some(PathBuf::from(expect_value(&mut args, &arg)?));
+                }
+                "--clear-color" => {
+                    config.clear_color = Some(parse_color(&expect_value(&mut args, &arg)?, &arg)?);
+                }
+                "--ssaa" => {
+                    let factor = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !(1..=4).contains(&factor) {
+                        return Err(invalid_arg(format!(
+                            "{} expects 1 to 4, got {}",
+                            arg, factor
+                        )));
+                    }
+                    config.ssaa_factor = factor;
+                }
+                "--ssaa-filter" => {
+                    config.ssaa_filter = match expect_value(&mut args, &arg)?.as_str() {
+                        "box" => SsaaFilter::Box,
+                        "gaussian" => SsaaFilter::Gaussian,
+                        other => {
+                            return Err(invalid_arg(format!(
+                                "{} expects box or gaussian, got '{}'",
+                                arg, other
+                            )));
+                        }
+                    };
+                }
+                "--msaa" => {
+                    let samples = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if ![1, 2, 4, 8].contains(&samples) {
+                        return Err(invalid_arg(format!(
+                            "{} expects 1, 2, 4 or 8, got {}",
+                            arg, samples
+                        )));
+                    }
+                    config.msaa_samples = samples;
+                }
+                "--motion-blur" => {
+                    let decay = parse_float(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !(0.0..1.0).contains(&decay) {
+                        return Err(invalid_arg(format!(
+                            "{} expects a decay from 0 up to but not including 1, got {}",
+                            arg, decay
+                        )));
+                    }
+                    config.motion_blur = decay;
+                }
+                "--render-scale" => {
+                    let percent = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !(10..=100).contains(&percent) {
+                        return Err(invalid_arg(format!(
+                            "{} expects 10 to 100 percent, got {}",
+                            arg, percent
+                        )));
+                    }
+                    config.render_scale = percent as f32 / 100.0;
+                }
+                "--max-capture-megapixels" => {
+                    let megapixels: u32 = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if megapixels > 4000 {
+                        return Err(invalid_arg(format!(
+                            "{} expects at most 4000, got {}",
+                            arg, megapixels
+                        )));
+                    }
+                    config.max_capture_pixels = (megapixels > 0).then(|| megapixels * 1_000_000);
+                }
+                "--slideshow" => {
+                    let seconds = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if seconds == 0 {
+                        return Err(invalid_arg(format!("{} expects at least 1 second", arg)));
+                    }
+                    config.slideshow = true;
+                    config.slideshow_interval = seconds;
+                }
+                "--screenshot-delay" => {
+                    config.screenshot_delay = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                }
+                "--screenshot-dir" => {
+                    config.screenshot_dir = Some(PathBuf::from(expect_value(&mut args, &arg)?));
+                }
+                "--save-background" => {
+                    let value = expect_value(&mut args, &arg)?;
+                    config.save_background = if value == "alpha" {
+                        SaveBackground::Alpha
+                    } else {
+                        let [r, g, b, _] = parse_color(&value, &arg)?;
+                        SaveBackground::Color([r, g, b])
+                    };
+                }
+                "--save-feedback" => {
+                    (config.save_beep, config.save_flash) =
+                        match expect_value(&mut args, &arg)?.as_str() {
+                            "both" => (true, true),
+                            "beep" => (true, false),
+                            "flash" => (false, true),
+                            "none" => (false, false),
+                            other => {
+                                return Err(invalid_arg(format!(
+                                    "{} expects both, beep, flash or none, got '{}'",
+                                    arg, other
+                                )));
+                            }
+                        };
+                }
+                #[cfg(feature = "shared-output")]
+                "--shared-output" => config.shared_output = Some(expect_value(&mut args, &arg)?),
+                #[cfg(not(feature = "shared-output"))]
+                "--shared-output" => {
+                    return Err(invalid_arg(format!(
+                        "{} needs a build with the shared-output feature",
+                        arg
+                    )));
+                }
+                "--ipc-port" => {
+                    let port = parse_number(&expect_value(&mut args, &arg)?, &arg)?;
+                    if !(1..=u16::MAX as u32).contains(&port) {
+                        return Err(invalid_arg(format!(
+                            "{} expects a port from 1 to {}, got {}",
+                            arg,
+                            u16::MAX,
+                            port
+                        )));
+                    }
+                    config.ipc_port = Some(port as u16);
+                }
+                flag if flag.starts_with('/') => {
+                    config.screensaver = Some(parse_screensaver(flag, &mut args)?);
+                }
+                _ => return Err(invalid_arg(format!("Unknown argument '{}'", arg))),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Path of the user texture configured for a shader, if any
+    pub fn texture_for(&self, shader_name: &str) -> Option<PathBuf> {
+        self.shader_textures
+            .iter()
+            .rev()
+            .find(|(name, _)| name == shader_name)
+            .map(|(_, path)| path.clone())
+    }
+}
+
+fn expect_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    args.next()
+        .ok_or_else(|| invalid_arg(format!("{} expects a value", flag)))
+}
+
+/// `/s`, `/p <hwnd>` or `/c[:hwnd]`, in either case, with the window handle in decimal
+/// after a space or a colon
+fn parse_screensaver(
+    flag: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<ScreensaverMode> {
+    let (name, inline_hwnd) = match flag.split_once(':') {
+        Some((name, hwnd)) => (name, Some(hwnd.to_string())),
+        None => (flag, None),
+    };
+    let parse_hwnd = |value: String| {
+        value
+            .trim()
+            .parse::<isize>()
+            .map_err(|_| invalid_arg(format!("{} expects a window handle, got '{}'", name, value)))
+    };
+    match name.to_ascii_lowercase().as_str() {
+        "/s" => Ok(ScreensaverMode::Run),
+        "/p" => {
+            let hwnd = match inline_hwnd {
+                Some(hwnd) => hwnd,
+                None => expect_value(args, name)?,
+            };
+            Ok(ScreensaverMode::Preview(parse_hwnd(hwnd)?))
+        }
+        "/c" => Ok(ScreensaverMode::Configure(
+            inline_hwnd.map(parse_hwnd).transpose()?,
+        )),
+        _ => Err(invalid_arg(format!("Unknown argument '{}'", flag))),
+    }
+}
+
+fn parse_number(value: &str, flag: &str) -> Result<u32> {
+    value
+        .parse()
+        .map_err(|_| invalid_arg(format!("{} expects a number, got '{}'", flag, value)))
+}
+
+/// `x,y,w,h` in desktop pixels, the origin may be negative on monitors left of or above
+/// the primary
+fn parse_rect(value: &str, flag: &str) -> Result<[i32; 4]> {
+    let invalid = || {
+        invalid_arg(format!(
+            "{} expects x,y,width,height, got '{}'",
+            flag, value
+        ))
+    };
+    let numbers = value
+        .split(',')
+        .map(|n| n.trim().parse::<i32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>>>()?;
+    match numbers[..] {
+        // The right and bottom edges have to fit too
+        [x, y, width, height]
+            if width > 0
+                && height > 0
+                && x.checked_add(width).is_some()
+                && y.checked_add(height).is_some() =>
+        {
+            Ok([x, y, width, height])
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Comma separated cells and inclusive `first-last` ranges, such as `1-62,64,66-94`
+fn parse_cell_list(value: &str, flag: &str) -> Result<Vec<u32>> {
+    let invalid = || {
+        invalid_arg(format!(
+            "{} expects cells and ranges like 1-62,64, got '{}'",
+            flag, value
+        ))
+    };
+    let mut cells = Vec::new();
+    for part in value.split(',') {
+        let part = part.trim();
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let first: u32 = first.trim().parse().map_err(|_| invalid())?;
+        let last: u32 = last.trim().parse().map_err(|_| invalid())?;
+        if last < first {
+            return Err(invalid());
+        }
+        // Checked before expanding, so a huge range can't run out of memory
+        if last >= MAX_GLYPH_CELLS
+            || cells.len() + (last - first) as usize >= MAX_GLYPH_CELLS as usize
+        {
+            return Err(invalid_arg(format!(
+                "{} can list at most {} cells, numbered below that, got '{}'",
+                flag, MAX_GLYPH_CELLS, value
+            )));
+        }
+        cells.extend(first..=last);
+    }
+    Ok(cells)
+}
+
+fn parse_float(value: &str, flag: &str) -> Result<f32> {
+    value
+        .parse()
+        .map_err(|_| invalid_arg(format!("{} expects a number, got '{}'", flag, value)))
+}
+
+/// A named color from `CLEAR_COLORS` or `#rrggbb[aa]`, as RGBA
+fn parse_color(value: &str, flag: &str) -> Result<[f32; 4]> {
+    if let Some((_, color)) = CLEAR_COLORS.iter().find(|(name, _)| *name == value) {
+        return Ok(*color);
+    }
+
+    let invalid = || {
+        invalid_arg(format!(
+            "{} expects a color name or #rrggbb[aa], got '{}'",
+            flag, value
+        ))
+    };
+    let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut color = [1.0; 4];
+    for (channel, digits) in color.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+        *channel = u8::from_str_radix(digits, 16).map_err(|_| invalid())? as f32 / 255.0;
+    }
+    Ok(color)
+}
+
+fn invalid_arg(message: String) -> Error {
+    Error::new(E_INVALIDARG, message)
+}
@@ -0,0 +1,55 @@
+//! Panic reporting to a file, since the console usually isn't visible when the app dies.
+
+use std::{fs::OpenOptions, io::Write};
+
+const CRASH_LOG: &str = "scrimshady_crash.log";
+
+/// Append every panic's message, location and backtrace to the crash log, then report it
+/// as usual
+pub fn install() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let location = info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()))
+            .unwrap_or_else(|| "unknown location".to_string());
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "non-string panic payload".to_string());
+
+        append(&format!(
+            "{} panic at {}: {}\n{}",
+            timestamp(),
+            location,
+            message,
+            std::backtrace::Backtrace::force_capture()
+        ));
+        default_hook(info);
+    }));
+}
+
+/// Add a line of context to the crash log, such as what the app was doing when it panicked
+pub fn append(text: &str) {
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(CRASH_LOG)
+        .and_then(|mut file| writeln!(file, "{}", text));
+    if let Err(e) = written {
+        println!("Failed to write {}: {}", CRASH_LOG, e);
+    }
+}
+
+fn timestamp() -> String {
+    let t = time::OffsetDateTime::now_utc();
+    let t = match time::UtcOffset::local_offset_at(t) {
+        Ok(offset) => t.to_offset(offset),
+        Err(_) => t,
+    };
+    let format: &[time::format_description::FormatItem<'_>] =
+        time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    t.format(format).unwrap_or_default()
+}
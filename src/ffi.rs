@@ -0,0 +1,272 @@
+//! C ABI for embedding the capture and shading pipeline in another application.
+//!
+//! The host owns the window and its message loop. The renderer captures whatever is behind
+//! the window and presents the shaded result into it. With the `shared-output` feature a
+//! renderer can instead publish to a named shared texture, with no window of the host's.
+//! Every call for a renderer must come from the thread that created it. A panic is stopped at
+//! the boundary and reported as `E_UNEXPECTED`. See `include/scrimshady.h` for the C
+//! declarations.
+
+use windows::{
+    Win32::{
+        Foundation::*,
+        Graphics::Dxgi::DXGI_ERROR_ACCESS_LOST,
+        UI::WindowsAndMessaging::{
+            DestroyWindow, SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE,
+        },
+    },
+    core::*,
+};
+
+use crate::app::{
+    CaptureState, ComInitScope, EmbeddedShaders, capture_and_render_frame, reset_capture,
+    update_occluded, window_changed,
+};
+use crate::config::Config;
+use crate::crash_log;
+#[cfg(feature = "shared-output")]
+use windows::Win32::UI::WindowsAndMessaging::{CreateWindowExW, WINDOW_EX_STYLE, WS_POPUP};
+
+/// Opaque handle handed out to C callers
+pub struct ScrimshadyRenderer {
+    state: CaptureState,
+    // The hidden window a shared-texture renderer draws through, `None` when the host's
+    // window is used. Destroyed after the state's swap chain is released.
+    window: Option<HiddenWindow>,
+    // Declared last so COM outlives every interface in the state
+    _com: ComInitScope,
+}
+
+/// A window the renderer created for itself and never shows
+struct HiddenWindow(HWND);
+
+impl Drop for HiddenWindow {
+    fn drop(&mut self) {
+        _ = unsafe { DestroyWindow(self.0) };
+    }
+}
+
+/// Run the body of the entry point `name`, returning `on_panic` if it panics rather than
+/// unwinding into the caller, which is undefined behavior across the C ABI
+fn guard<T>(name: &str, on_panic: T, body: impl FnOnce() -> T) -> T {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "non-string panic payload".to_string());
+        crash_log::append(&format!("panic in {}: {}", name, message));
+        on_panic
+    })
+}
+
+/// Hand a created renderer to the caller, or report why it couldn't be created
+fn finish_create(
+    created: Result<Box<ScrimshadyRenderer>>,
+    renderer: *mut *mut ScrimshadyRenderer,
+) -> HRESULT {
+    match created {
+        Ok(created) => {
+            unsafe { *renderer = Box::into_raw(created) };
+            S_OK
+        }
+        Err(e) => {
+            println!("Failed to create renderer: {:?}", e);
+            e.code()
+        }
+    }
+}
+
+/// Create a renderer that draws into `hwnd`, excluding the window from its own capture.
+/// On success `*renderer` receives a handle to pass to the other functions.
+///
+/// # Safety
+/// `hwnd` must be a valid window owned by the calling thread and `renderer` a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scrimshady_create(
+    hwnd: HWND,
+    renderer: *mut *mut ScrimshadyRenderer,
+) -> HRESULT {
+    guard("scrimshady_create", E_UNEXPECTED, || {
+        if renderer.is_null() {
+            return E_POINTER;
+        }
+
+        let create = || -> Result<Box<ScrimshadyRenderer>> {
+            let shaders = EmbeddedShaders::spawn(None);
+            let com = ComInitScope::new()?;
+            unsafe { SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE)? };
+
+            let mut state = CaptureState::new(hwnd, &Config::default(), shaders)?;
+            // Size the swap chain and source rect to the window as it is now
+            window_changed(&mut state, hwnd, true);
+            Ok(Box::new(ScrimshadyRenderer {
+                state,
+                window: None,
+                _com: com,
+            }))
+        };
+        finish_create(create(), renderer)
+    })
+}
+
+/// Create a renderer that captures `source`, in physical desktop pixels, and publishes each
+/// shaded frame as the DXGI shared texture `name`, for the host to open with
+/// `ID3D11Device1::OpenSharedResourceByName`. Nothing is shown on screen.
+/// On success `*renderer` receives a handle to pass to the other functions.
+///
+/// # Safety
+/// `name` must be a valid null-terminated string and `renderer` a valid pointer.
+#[cfg(feature = "shared-output")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scrimshady_create_shared(
+    name: PCWSTR,
+    source: RECT,
+    renderer: *mut *mut ScrimshadyRenderer,
+) -> HRESULT {
+    guard("scrimshady_create_shared", E_UNEXPECTED, || {
+        if renderer.is_null() || name.is_null() {
+            return E_POINTER;
+        }
+        let width = source.right - source.left;
+        let height = source.bottom - source.top;
+        if width <= 0 || height <= 0 {
+            return E_INVALIDARG;
+        }
+
+        let create = || -> Result<Box<ScrimshadyRenderer>> {
+            let config = Config {
+                source_rect: Some([source.left, source.top, width, height]),
+                shared_output: Some(unsafe { name.to_string() }?),
+                ..Config::default()
+            };
+            let shaders = EmbeddedShaders::spawn(None);
+            let com = ComInitScope::new()?;
+            // The swap chain needs a window, sized like the output, which is never shown
+            let window = HiddenWindow(unsafe {
+                CreateWindowExW(
+                    WINDOW_EX_STYLE::default(),
+                    w!("STATIC"),
+                    w!("scrimshady"),
+                    WS_POPUP,
+                    0,
+                    0,
+                    width,
+                    height,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            }?);
+
+            let mut state = CaptureState::new(window.0, &config, shaders)?;
+            window_changed(&mut state, window.0, true);
+            Ok(Box::new(ScrimshadyRenderer {
+                state,
+                window: Some(window),
+                _com: com,
+            }))
+        };
+        finish_create(create(), renderer)
+    })
+}
+
+/// Release a renderer. Null is ignored.
+///
+/// # Safety
+/// `renderer` must be null or a handle from `scrimshady_create` or `scrimshady_create_shared` that
+/// hasn't been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scrimshady_destroy(renderer: *mut ScrimshadyRenderer) {
+    guard("scrimshady_destroy", (), || {
+        if !renderer.is_null() {
+            drop(unsafe { Box::from_raw(renderer) });
+        }
+    })
+}
+
+/// Number of shaders available to `scrimshady_set_shader`
+///
+/// # Safety
+/// `renderer` must be a live handle from `scrimshady_create` or `scrimshady_create_shared`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scrimshady_shader_count(renderer: *const ScrimshadyRenderer) -> u32 {
+    guard("scrimshady_shader_count", 0, || {
+        match unsafe { renderer.as_ref() } {
+            Some(renderer) => renderer.state.shader_count() as u32,
+            None => 0,
+        }
+    })
+}
+
+/// Switch to the shader at `index`, in the order of the app's number keys from 0
+///
+/// # Safety
+/// `renderer` must be a live handle from `scrimshady_create` or `scrimshady_create_shared`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scrimshady_set_shader(
+    renderer: *mut ScrimshadyRenderer,
+    index: u32,
+) -> HRESULT {
+    guard("scrimshady_set_shader", E_UNEXPECTED, || {
+        let Some(renderer) = (unsafe { renderer.as_mut() }) else {
+            return E_POINTER;
+        };
+        if renderer.state.select_shader(index as usize) {
+            S_OK
+        } else {
+            E_INVALIDARG
+        }
+    })
+}
+
+/// Tell the renderer its window moved or was resized, from the host's WM_MOVE/WM_SIZE
+///
+/// # Safety
+/// `renderer` must be a live handle from `scrimshady_create` or `scrimshady_create_shared`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scrimshady_window_changed(
+    renderer: *mut ScrimshadyRenderer,
+    resized: bool,
+) -> HRESULT {
+    guard("scrimshady_window_changed", E_UNEXPECTED, || {
+        let Some(renderer) = (unsafe { renderer.as_mut() }) else {
+            return E_POINTER;
+        };
+        let hwnd = renderer.state.hwnd;
+        window_changed(&mut renderer.state, hwnd, resized);
+        S_OK
+    })
+}
+
+/// Capture and present one frame if the desktop has changed. Returns S_OK when there was
+/// nothing new to draw, and DXGI_STATUS_OCCLUDED without rendering while the window is
+/// covered, such as by a fullscreen game.
+///
+/// # Safety
+/// `renderer` must be a live handle from `scrimshady_create` or `scrimshady_create_shared`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn scrimshady_render_frame(renderer: *mut ScrimshadyRenderer) -> HRESULT {
+    guard("scrimshady_render_frame", E_UNEXPECTED, || {
+        let Some(renderer) = (unsafe { renderer.as_mut() }) else {
+            return E_POINTER;
+        };
+        // Covered by a fullscreen app, only a test present until something can see the
+        // window. A hidden window is never visible, and its output goes to the shared texture.
+        if renderer.window.is_none() && update_occluded(&mut renderer.state) {
+            return DXGI_STATUS_OCCLUDED;
+        }
+        let hwnd = renderer.state.hwnd;
+        match capture_and_render_frame(&mut renderer.state, hwnd) {
+            Ok(()) => S_OK,
+            Err(e) => {
+                // Raised on mode changes, recaptured on the next frame like the app does
+                if e.code() == DXGI_ERROR_ACCESS_LOST {
+                    reset_capture(&mut renderer.state);
+                }
+                e.code()
+            }
+        }
+    })
+}
@@ -0,0 +1,206 @@
+//! Local control endpoint for automation, such as driving effect changes from a Stream Deck.
+//!
+//! Clients connect over TCP on localhost and send newline-delimited JSON commands like
+//! `{"token": "...", "command": "set_shader", "name": "dither"}`, each answered with one
+//! line of JSON. The token is made up fresh each run and written to the user's temp directory,
+//! which other users can't read, so they can't drive the window. The
+//! connections are served on background threads; every command is posted to the window as
+//! `WM_IPC_COMMAND` and carried out on the UI thread, which sends the reply back.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex, mpsc},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use windows::Win32::{Foundation::*, System::Com::CoCreateGuid, UI::WindowsAndMessaging::*};
+
+/// Posted to the window with `lparam` owning a `Box<Request>` from `Box::into_raw`
+pub const WM_IPC_COMMAND: u32 = WM_APP + 4;
+
+// How long a client waits for the UI thread before giving up on a command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Holds the session's token while the endpoint is listening, in the temp directory
+const TOKEN_FILE: &str = "scrimshady_ipc_token.txt";
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// The current shader, the available ones and the current shader's parameters
+    Status,
+    SetShader {
+        name: String,
+    },
+    /// Set a parameter of `shader`, or of the current shader when it's left out
+    SetParam {
+        #[serde(default)]
+        shader: Option<String>,
+        name: String,
+        value: f32,
+    },
+    Screenshot,
+    ToggleAlwaysOnTop,
+    Quit,
+}
+
+/// One line from a client: the session's token and the command it authorizes
+#[derive(Deserialize)]
+struct Line {
+    token: String,
+    #[serde(flatten)]
+    command: Command,
+}
+
+/// A command on its way to the UI thread, and where its reply goes
+pub struct Request {
+    pub command: Command,
+    pub reply: mpsc::Sender<Value>,
+}
+
+/// The listening endpoint. Dropping it stops accepting connections and frees any commands
+/// still queued for the window, so drop it before the window is destroyed.
+pub struct Listener {
+    hwnd: HWND,
+    port: u16,
+    token_path: PathBuf,
+    // The window to post commands to, `None` once stopped. Held while posting, so nothing is
+    // posted after the queue has been drained.
+    target: Arc<Mutex<Option<isize>>>,
+}
+
+/// Accept commands on `127.0.0.1:port` for `hwnd` until the returned listener is dropped
+pub fn start(hwnd: HWND, port: u16) -> std::io::Result<Listener> {
+    let token = unsafe { CoCreateGuid() }
+        .map(|guid| format!("{:032x}", guid.to_u128()))
+        .map_err(std::io::Error::other)?;
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let token_path = std::env::temp_dir().join(TOKEN_FILE);
+    std::fs::write(&token_path, &token)?;
+    println!(
+        "Listening for commands on 127.0.0.1:{}, with the token in {}",
+        port,
+        token_path.display()
+    );
+
+    // Window handles aren't Send, but posting to one from any thread is fine
+    let target = Arc::new(Mutex::new(Some(hwnd.0 as isize)));
+    let accepting = target.clone();
+    let token = Arc::new(token);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            if accepting.lock().unwrap().is_none() {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    let target = accepting.clone();
+                    let token = token.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = serve(&target, &token, stream) {
+                            println!("Command connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => println!("Failed to accept a command connection: {}", e),
+            }
+        }
+    });
+    Ok(Listener {
+        hwnd,
+        port,
+        token_path,
+        target,
+    })
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        *self.target.lock().unwrap() = None;
+        // Wake the accept loop so it sees it's stopped and closes the socket
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        let _ = std::fs::remove_file(&self.token_path);
+
+        // Commands posted but not yet handled would be discarded with the window, leaking them
+        let mut message = MSG::default();
+        while unsafe {
+            PeekMessageW(
+                &mut message,
+                Some(self.hwnd),
+                WM_IPC_COMMAND,
+                WM_IPC_COMMAND,
+                PM_REMOVE,
+            )
+        }
+        .as_bool()
+        {
+            let request = unsafe { Box::from_raw(message.lParam.0 as *mut Request) };
+            let _ = request.reply.send(error("window is closing".to_string()));
+        }
+    }
+}
+
+/// Answer each line of `stream` until the client disconnects or sends a line that isn't a
+/// valid command with the session's token
+fn serve(target: &Mutex<Option<isize>>, token: &str, stream: TcpStream) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match serde_json::from_str::<Line>(&line) {
+            Ok(line) if line.token == token => line.command,
+            Ok(_) => {
+                writeln!(writer, "{}", error("wrong token".to_string()))?;
+                return Ok(());
+            }
+            Err(e) => {
+                writeln!(writer, "{}", error(format!("invalid command: {}", e)))?;
+                return Ok(());
+            }
+        };
+        writeln!(writer, "{}", dispatch(target, command))?;
+    }
+    Ok(())
+}
+
+/// Run `command` on the UI thread and wait for its reply
+fn dispatch(target: &Mutex<Option<isize>>, command: Command) -> Value {
+    let (reply, replied) = mpsc::channel();
+    {
+        let target = target.lock().unwrap();
+        let Some(hwnd) = *target else {
+            return error("window is closing".to_string());
+        };
+        let request = Box::into_raw(Box::new(Request { command, reply }));
+        let posted = unsafe {
+            PostMessageW(
+                Some(HWND(hwnd as *mut _)),
+                WM_IPC_COMMAND,
+                WPARAM(0),
+                LPARAM(request as isize),
+            )
+        };
+        if let Err(e) = posted {
+            // Never delivered, so it's still ours to free
+            drop(unsafe { Box::from_raw(request) });
+            return error(format!("window unavailable: {}", e.message()));
+        }
+    }
+    replied
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or_else(|_| error("timed out waiting for the window".to_string()))
+}
+
+pub fn ok() -> Value {
+    json!({ "ok": true })
+}
+
+pub fn error(message: String) -> Value {
+    json!({ "ok": false, "error": message })
+}
@@ -0,0 +1,11 @@
+mod app;
+mod config;
+mod crash_log;
+pub mod ffi;
+mod ipc;
+mod region_select;
+mod saved_state;
+#[cfg(feature = "shared-output")]
+mod shared_output;
+
+pub use app::main as run_app;
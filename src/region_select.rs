@@ -0,0 +1,195 @@
+//! Full-screen overlay for dragging out a fixed capture region on the desktop.
+//!
+//! The overlay dims every monitor and outlines the rectangle being dragged. Releasing the
+//! mouse sends the rectangle, in physical desktop pixels, to the owner window as
+//! `WM_REGION_SELECTED`. Escape or a right click cancels.
+
+use windows::{
+    Win32::{
+        Foundation::*, Graphics::Gdi::*, System::LibraryLoader::*, UI::Input::KeyboardAndMouse::*,
+        UI::WindowsAndMessaging::*,
+    },
+    core::*,
+};
+
+/// Sent to the owner with `lparam` pointing at the selected `RECT`, valid for the call only
+pub const WM_REGION_SELECTED: u32 = WM_APP + 2;
+
+// How opaque the dimmed desktop is, out of 255
+const OVERLAY_ALPHA: u8 = 96;
+const OUTLINE_WIDTH: i32 = 3;
+
+struct Selection {
+    owner: HWND,
+    // Top left of the virtual screen, where the overlay's client area starts
+    origin: POINT,
+    // Where the drag started and where the cursor is now, in desktop coordinates
+    anchor: Option<POINT>,
+    current: POINT,
+}
+
+impl Selection {
+    fn rect(&self) -> Option<RECT> {
+        let anchor = self.anchor?;
+        Some(RECT {
+            left: anchor.x.min(self.current.x),
+            top: anchor.y.min(self.current.y),
+            right: anchor.x.max(self.current.x),
+            bottom: anchor.y.max(self.current.y),
+        })
+    }
+
+    fn point_from_lparam(&self, lparam: LPARAM) -> POINT {
+        POINT {
+            x: self.origin.x + (lparam.0 & 0xFFFF) as i16 as i32,
+            y: self.origin.y + ((lparam.0 >> 16) & 0xFFFF) as i16 as i32,
+        }
+    }
+}
+
+/// Show the selection overlay over every monitor. Returns once it's up; the result arrives
+/// later as `WM_REGION_SELECTED` on `owner`.
+pub fn begin(owner: HWND) -> Result<()> {
+    let window_class = w!("ScrimshadyRegionSelectClass");
+    let hinstance: HINSTANCE = unsafe { GetModuleHandleW(None) }?.into();
+
+    let wc = WNDCLASSEXW {
+        cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+        lpfnWndProc: Some(wndproc),
+        hInstance: hinstance,
+        hCursor: unsafe { LoadCursorW(None, IDC_CROSS)? },
+        lpszClassName: window_class,
+        ..Default::default()
+    };
+
+    unsafe {
+        // Fails harmlessly when the class is still registered from an earlier selection
+        RegisterClassExW(&wc);
+
+        // The process is per-monitor aware, so these are physical pixels like source_rect
+        let origin = POINT {
+            x: GetSystemMetrics(SM_XVIRTUALSCREEN),
+            y: GetSystemMetrics(SM_YVIRTUALSCREEN),
+        };
+        let hwnd = CreateWindowExW(
+            WS_EX_LAYERED | WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
+            window_class,
+            w!("Select capture region"),
+            WS_POPUP,
+            origin.x,
+            origin.y,
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            Some(owner),
+            None,
+            Some(hinstance),
+            None,
+        )?;
+
+        let selection = Selection {
+            owner,
+            origin,
+            anchor: None,
+            current: origin,
+        };
+        SetWindowLongPtrW(
+            hwnd,
+            GWLP_USERDATA,
+            Box::into_raw(Box::new(selection)) as isize,
+        );
+
+        SetLayeredWindowAttributes(hwnd, COLORREF(0), OVERLAY_ALPHA, LWA_ALPHA)?;
+        // Keep the dimming out of the frames being captured underneath
+        let _ = SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE);
+
+        let _ = ShowWindow(hwnd, SW_SHOW);
+        let _ = SetForegroundWindow(hwnd);
+    }
+    println!("Drag to select the capture region, Escape to cancel");
+    Ok(())
+}
+
+extern "system" fn wndproc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        let selection_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Selection;
+        if selection_ptr.is_null() {
+            return DefWindowProcW(hwnd, message, wparam, lparam);
+        }
+        let selection = &mut *selection_ptr;
+
+        match message {
+            WM_LBUTTONDOWN => {
+                let point = selection.point_from_lparam(lparam);
+                selection.anchor = Some(point);
+                selection.current = point;
+                // Keep receiving the drag if the cursor leaves the overlay
+                SetCapture(hwnd);
+                LRESULT(0)
+            }
+            WM_MOUSEMOVE => {
+                if selection.anchor.is_some() {
+                    selection.current = selection.point_from_lparam(lparam);
+                    let _ = InvalidateRect(Some(hwnd), None, false);
+                }
+                LRESULT(0)
+            }
+            WM_LBUTTONUP => {
+                let _ = ReleaseCapture();
+                selection.current = selection.point_from_lparam(lparam);
+                match selection.rect() {
+                    Some(rect) if rect.right > rect.left && rect.bottom > rect.top => {
+                        SendMessageW(
+                            selection.owner,
+                            WM_REGION_SELECTED,
+                            None,
+                            Some(LPARAM(&rect as *const RECT as isize)),
+                        );
+                    }
+                    _ => println!("Region selection cancelled"),
+                }
+                let _ = DestroyWindow(hwnd);
+                LRESULT(0)
+            }
+            WM_RBUTTONUP => {
+                println!("Region selection cancelled");
+                let _ = DestroyWindow(hwnd);
+                LRESULT(0)
+            }
+            WM_KEYDOWN if wparam.0 as u16 == VK_ESCAPE.0 => {
+                println!("Region selection cancelled");
+                let _ = DestroyWindow(hwnd);
+                LRESULT(0)
+            }
+            WM_PAINT => {
+                let mut paint = PAINTSTRUCT::default();
+                let hdc = BeginPaint(hwnd, &mut paint);
+                let mut client_rect = RECT::default();
+                let _ = GetClientRect(hwnd, &mut client_rect);
+                FillRect(hdc, &client_rect, HBRUSH(GetStockObject(BLACK_BRUSH).0));
+
+                if let Some(rect) = selection.rect() {
+                    let mut outline = RECT {
+                        left: rect.left - selection.origin.x,
+                        top: rect.top - selection.origin.y,
+                        right: rect.right - selection.origin.x,
+                        bottom: rect.bottom - selection.origin.y,
+                    };
+                    // Drawn outside the region so the outline itself isn't part of it
+                    let white = HBRUSH(GetStockObject(WHITE_BRUSH).0);
+                    for _ in 0..OUTLINE_WIDTH {
+                        let _ = InflateRect(&mut outline, 1, 1);
+                        FrameRect(hdc, &outline, white);
+                    }
+                }
+                let _ = EndPaint(hwnd, &paint);
+                LRESULT(0)
+            }
+            WM_NCDESTROY => {
+                SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+                drop(Box::from_raw(selection_ptr));
+                DefWindowProcW(hwnd, message, wparam, lparam)
+            }
+            _ => DefWindowProcW(hwnd, message, wparam, lparam),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Shader selection and parameter values saved on exit and restored at startup, so tuning
+//! survives restarts.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+const SAVED_STATE: &str = "scrimshady_state.json";
+
+/// Parameter values by shader name, then parameter name
+pub type SavedParams = BTreeMap<String, BTreeMap<String, f32>>;
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct SavedState {
+    /// Name of the shader showing when the app last exited
+    #[serde(default)]
+    pub shader: Option<String>,
+    #[serde(default)]
+    pub params: SavedParams,
+    /// Whether the help overlay has been shown, so it only appears by itself on the first run
+    #[serde(default)]
+    pub seen_help: bool,
+}
+
+/// The state saved by the last run, empty when there is none or it can't be read
+pub fn load() -> SavedState {
+    let text = match std::fs::read_to_string(SAVED_STATE) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return SavedState::default(),
+        Err(e) => {
+            println!("Failed to read {}: {}", SAVED_STATE, e);
+            return SavedState::default();
+        }
+    };
+    serde_json::from_str(&text).unwrap_or_else(|e| {
+        println!("Ignoring {}, it isn't valid: {}", SAVED_STATE, e);
+        SavedState::default()
+    })
+}
+
+pub fn save(state: &SavedState) {
+    let written = serde_json::to_string_pretty(state)
+        .map_err(std::io::Error::other)
+        .and_then(|text| std::fs::write(SAVED_STATE, text));
+    match written {
+        Ok(()) => println!("Saved shader selection and parameters to {}", SAVED_STATE),
+        Err(e) => println!("Failed to write {}: {}", SAVED_STATE, e),
+    }
+}
@@ -0,0 +1,95 @@
+use windows::{
+    Win32::{
+        Foundation::*,
+        Graphics::{Direct3D11::*, Dxgi::Common::*, Dxgi::*},
+    },
+    core::*,
+};
+
+/// Publishes each rendered frame as a named DXGI shared texture, which other processes
+/// open with `ID3D11Device1::OpenSharedResourceByName`
+pub struct SharedOutput {
+    name: HSTRING,
+    // Recreated, under the same name, whenever the back buffer size or format changes
+    texture: Option<(ID3D11Texture2D, Owned<HANDLE>)>,
+}
+
+impl SharedOutput {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: HSTRING::from(name),
+            texture: None,
+        }
+    }
+
+    /// Copy the back buffer into the shared texture
+    pub fn publish(
+        &mut self,
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        back_buffer: &ID3D11Texture2D,
+    ) -> Result<()> {
+        let mut source_desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { back_buffer.GetDesc(&mut source_desc) };
+
+        if let Some((texture, _)) = &self.texture {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { texture.GetDesc(&mut desc) };
+            if (desc.Width, desc.Height, desc.Format)
+                != (source_desc.Width, source_desc.Height, source_desc.Format)
+            {
+                // Close the old handle first so the name is free to reuse
+                self.texture = None;
+            }
+        }
+
+        if self.texture.is_none() {
+            self.texture = Some(self.create_texture(device, &source_desc)?);
+        }
+        let (texture, _) = self.texture.as_ref().unwrap();
+
+        unsafe { context.CopyResource(texture, back_buffer) };
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        device: &ID3D11Device,
+        source_desc: &D3D11_TEXTURE2D_DESC,
+    ) -> Result<(ID3D11Texture2D, Owned<HANDLE>)> {
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: source_desc.Width,
+            Height: source_desc.Height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: source_desc.Format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: (D3D11_BIND_RENDER_TARGET.0 | D3D11_BIND_SHADER_RESOURCE.0) as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: (D3D11_RESOURCE_MISC_SHARED.0 | D3D11_RESOURCE_MISC_SHARED_NTHANDLE.0)
+                as u32,
+        };
+
+        unsafe {
+            let mut texture_out = None;
+            device.CreateTexture2D(&desc, None, Some(&mut texture_out))?;
+            let texture = texture_out.ok_or(E_POINTER)?;
+
+            let resource: IDXGIResource1 = texture.cast()?;
+            let handle = resource.CreateSharedHandle(
+                None,
+                DXGI_SHARED_RESOURCE_READ.0 | DXGI_SHARED_RESOURCE_WRITE.0,
+                &self.name,
+            )?;
+            println!(
+                "Publishing {}x{} output as shared texture '{}'",
+                desc.Width, desc.Height, self.name
+            );
+            Ok((texture, Owned::new(handle)))
+        }
+    }
+}